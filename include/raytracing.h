@@ -0,0 +1,114 @@
+/*
+ * C interface of the raytracing library, built as a shared library with
+ * `cargo build --release` (target/release/libraytracing.so). `cargo test`
+ * builds tests/c/smoke.c against this header, which calls every function.
+ *
+ * Every function that can fail returns an RtStatus; rt_last_error() gives
+ * the message of the last failure on the calling thread. Scenes and
+ * renderers are opaque handles, made and freed by the library. Vectors are
+ * arrays of three doubles, colors are linear.
+ *
+ *     RtScene *scene;
+ *     RtRenderer *renderer;
+ *     RtMaterial material;
+ *     size_t red;
+ *     double center[3] = {0, 0, 0}, position[3] = {0, 0, 6}, forward[3] = {0, 0, -1};
+ *
+ *     rt_scene_create(&scene);
+ *     rt_material_default(&material);
+ *     material.albedo[1] = material.albedo[2] = 0;
+ *     rt_scene_add_material(scene, &material, &red);
+ *     rt_scene_add_sphere(scene, center, 1.0, red, NULL);
+ *     rt_renderer_create(320, 240, &renderer);
+ *     rt_renderer_set_camera(renderer, position, forward, 45.0);
+ *     if (rt_renderer_render(renderer, scene, 16) != RT_OK)
+ *         fprintf(stderr, "%s\n", rt_last_error());
+ *     rt_renderer_read_pixels(renderer, pixels, 320 * 240 * 4);
+ *     rt_renderer_destroy(renderer);
+ *     rt_scene_destroy(scene);
+ */
+
+#ifndef RAYTRACING_H
+#define RAYTRACING_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef enum RtStatus {
+    RT_OK = 0,
+    RT_NULL_POINTER = 1,
+    RT_INVALID_ARGUMENT = 2,
+    RT_OUT_OF_RANGE = 3,
+    RT_LOAD_FAILED = 4,
+    /* A bug in the library, the handles it was given should be destroyed */
+    RT_PANIC = 5,
+} RtStatus;
+
+/* Linear RGBA colors */
+typedef struct RtMaterial {
+    double albedo[4];
+    double roughness;
+    double metallic;
+    double emission_color[4];
+    double emission_power;
+    double transmission;
+} RtMaterial;
+
+typedef struct RtScene RtScene;
+typedef struct RtRenderer RtRenderer;
+
+/* Empty after a success, valid until the next call on the thread */
+const char *rt_last_error(void);
+
+RtStatus rt_scene_create(RtScene **out);
+/* Reads a scene document, relative paths in it start at its directory */
+RtStatus rt_scene_load(const char *path, RtScene **out);
+void rt_scene_destroy(RtScene *scene);
+
+RtStatus rt_material_default(RtMaterial *out);
+/* `index` may be NULL, for the functions that add to a scene */
+RtStatus rt_scene_add_material(RtScene *scene, const RtMaterial *material, size_t *index);
+/* Keeps the textures of the material at `index` */
+RtStatus rt_scene_set_material(RtScene *scene, size_t index, const RtMaterial *material);
+
+RtStatus rt_scene_add_sphere(RtScene *scene, const double center[3], double radius,
+                             size_t material, size_t *index);
+RtStatus rt_scene_add_plane(RtScene *scene, const double point[3], const double normal[3],
+                            size_t material, size_t *index);
+/* Aligned with the axes */
+RtStatus rt_scene_add_cuboid(RtScene *scene, const double min[3], const double max[3],
+                             size_t material, size_t *index);
+/* `vertex_count` positions and `triangle_count` triangles of three indices */
+RtStatus rt_scene_add_mesh(RtScene *scene, const double *positions, size_t vertex_count,
+                           const uint32_t *triangles, size_t triangle_count, size_t material,
+                           size_t *index);
+RtStatus rt_scene_add_point_light(RtScene *scene, const double position[3],
+                                  const double color[3], double intensity);
+
+/* Path tracer, with the camera at (0, 0, 6) looking down -z */
+RtStatus rt_renderer_create(uint32_t width, uint32_t height, RtRenderer **out);
+void rt_renderer_destroy(RtRenderer *renderer);
+/* Starts the accumulation again, the field of view is vertical, in degrees */
+RtStatus rt_renderer_set_camera(RtRenderer *renderer, const double position[3],
+                                const double forward_direction[3], double vertical_fov);
+/* Named like in the viewer, "path tracing" or "bidirectional" for example.
+ * Starts the accumulation again. */
+RtStatus rt_renderer_set_integrator(RtRenderer *renderer, const char *name);
+/* Starts the accumulation again, needed after the scene changes */
+RtStatus rt_renderer_reset(RtRenderer *renderer);
+/* Adds `samples` samples per pixel to the accumulation */
+RtStatus rt_renderer_render(RtRenderer *renderer, RtScene *scene, uint32_t samples);
+RtStatus rt_renderer_samples(const RtRenderer *renderer, uint32_t *samples);
+/* Average of the samples, width * height * 4 floats of linear RGBA with the
+ * first row at the top; `length` is the size of `pixels` in floats */
+RtStatus rt_renderer_read_pixels(const RtRenderer *renderer, float *pixels, size_t length);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
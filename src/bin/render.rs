@@ -0,0 +1,360 @@
+//! Renders the frames of an animated scene to numbered images, without a
+//! window
+//!
+//! ```text
+//! render scene.yaml [--frames 0..48] [--samples 64] [--size 640x360]
+//!     [--integrator "path tracing"] [--output frames/frame_####.png]
+//! ```
+//!
+//! Frame ranges leave out their end, a single number renders one frame, and
+//! the default is every frame up to the last key. The run of `#` in the
+//! output is replaced by the frame number, padded with zeros.
+//!
+//! ```text
+//! render scene.yaml --turntable 36 [--radius 6] [--elevation 20]
+//!     [--target 0,0,0] [--fps 12] [--output turntable.gif]
+//! ```
+//!
+//! renders an orbit around the target instead, saved as a GIF or, when the
+//! output ends with `.png`, an animated PNG.
+//!
+//! With `--checkpoint FILE`, frames save their accumulation to the file every
+//! `--checkpoint-interval` seconds (60 by default) and when they are done. A
+//! frame matching the checkpoint goes on from it and frames whose image
+//! already exists are skipped, so an interrupted run can be started again
+//! with the same arguments.
+//!
+//! With `--workers N`, frames are rendered by N worker processes, each
+//! getting batches of `--batch` samples, over TCP on the local host.
+//! `render --worker ADDRESS` is how they are started. Such frames have no
+//! checkpoints.
+//!
+//! `render --serve ADDRESS` runs the HTTP render service instead, see
+//! `raytracing::renderer::service`. `--samples`, `--size` and
+//! `--integrator` are then the defaults of submitted jobs.
+
+use std::{
+    error::Error,
+    fs,
+    ops::Range,
+    path::Path,
+    process,
+    time::{Duration, Instant},
+};
+
+use image::ImageFormat;
+use nalgebra::Vector3;
+use raytracing::{
+    camera::Camera,
+    integrator::IntegratorKind,
+    renderer::{
+        checkpoint::Checkpoint,
+        distributed::{self, Coordinator, Job},
+        service::{self, JobSettings},
+        turntable::{Turntable, TurntableJob},
+        Canvas, RaytracingRenderer, RendererSettings,
+    },
+    scene::Scene,
+};
+
+struct Options {
+    scene: String,
+    frames: Option<Range<usize>>,
+    samples: usize,
+    width: u32,
+    height: u32,
+    integrator: IntegratorKind,
+    output: Option<String>,
+    turntable: Option<Turntable>,
+    checkpoint: Option<String>,
+    checkpoint_interval: Duration,
+    workers: usize,
+    batch: Option<usize>,
+    serve: Option<String>,
+}
+
+const USAGE: &str = "usage: render SCENE [--frames START..END] [--samples N] [--size WxH] \
+                     [--integrator NAME] [--output PATTERN] [--turntable FRAMES [--radius R] \
+                     [--elevation DEGREES] [--target X,Y,Z] [--fps FPS]] \
+                     [--checkpoint FILE [--checkpoint-interval SECONDS]] \
+                     [--workers N [--batch SAMPLES]]
+       render --worker ADDRESS
+       render --serve ADDRESS [--samples N] [--size WxH] [--integrator NAME]";
+
+fn parse_options(mut args: impl Iterator<Item = String>) -> Result<Options, Box<dyn Error>> {
+    let mut options = Options {
+        scene: String::new(),
+        frames: None,
+        samples: 64,
+        width: 640,
+        height: 360,
+        integrator: IntegratorKind::Path,
+        output: None,
+        turntable: None,
+        checkpoint: None,
+        checkpoint_interval: Duration::from_secs(60),
+        workers: 0,
+        batch: None,
+        serve: None,
+    };
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or(format!("missing value for {arg}"));
+        match arg.as_str() {
+            "--frames" => options.frames = Some(parse_frames(&value()?)?),
+            "--samples" => options.samples = value()?.parse()?,
+            "--size" => {
+                let size = value()?;
+                let (width, height) = size.split_once('x').ok_or("size should be WxH")?;
+                options.width = width.parse()?;
+                options.height = height.parse()?;
+            }
+            "--integrator" => {
+                let name = value()?;
+                options.integrator = IntegratorKind::ALL
+                    .into_iter()
+                    .find(|kind| kind.name() == name.replace(['-', '_'], " "))
+                    .ok_or(format!("unknown integrator '{name}'"))?;
+            }
+            "--output" => options.output = Some(value()?),
+            "--checkpoint" => options.checkpoint = Some(value()?),
+            "--workers" => options.workers = value()?.parse()?,
+            "--batch" => options.batch = Some(value()?.parse()?),
+            "--serve" => options.serve = Some(value()?),
+            "--checkpoint-interval" => {
+                options.checkpoint_interval = Duration::from_secs_f64(value()?.parse()?)
+            }
+            "--turntable" | "--radius" | "--elevation" | "--target" | "--fps" => {
+                let value = value()?;
+                let turntable = options.turntable.get_or_insert_with(Turntable::default);
+                match arg.as_str() {
+                    "--turntable" => turntable.frames = value.parse()?,
+                    "--radius" => turntable.radius = value.parse()?,
+                    "--elevation" => turntable.elevation = value.parse()?,
+                    "--fps" => turntable.fps = value.parse()?,
+                    _ => {
+                        let target = value
+                            .split(',')
+                            .map(str::parse)
+                            .collect::<Result<Vec<f64>, _>>()?;
+                        if target.len() != 3 {
+                            return Err("target should be X,Y,Z".into());
+                        }
+                        turntable.target = Vector3::from_vec(target);
+                    }
+                }
+            }
+            _ if arg.starts_with("--") => return Err(format!("unknown option {arg}").into()),
+            _ if options.scene.is_empty() => options.scene = arg,
+            _ => return Err(format!("unexpected argument '{arg}'").into()),
+        }
+    }
+    if options.scene.is_empty() && options.serve.is_none() {
+        return Err("missing scene".into());
+    }
+    if options.width == 0 || options.height == 0 || options.samples == 0 {
+        return Err("size and samples should not be zero".into());
+    }
+    if let Some(turntable) = &options.turntable {
+        turntable.check()?;
+    }
+    if options.checkpoint.is_some() && options.workers > 0 {
+        return Err("--checkpoint can't be used with --workers".into());
+    }
+    Ok(options)
+}
+
+fn parse_frames(text: &str) -> Result<Range<usize>, Box<dyn Error>> {
+    Ok(match text.split_once("..") {
+        Some((start, end)) => start.parse()?..end.parse()?,
+        None => {
+            let frame = text.parse()?;
+            frame..frame + 1
+        }
+    })
+}
+
+/// `pattern` with its `#`s replaced by `frame`, or the frame number added
+/// before the extension when there are none
+fn frame_path(pattern: &str, frame: usize) -> String {
+    let Some(start) = pattern.find('#') else {
+        let path = Path::new(pattern);
+        let stem = path.with_extension("");
+        let extension = path.extension().and_then(|e| e.to_str()).unwrap_or("png");
+        return format!("{}_{frame:04}.{extension}", stem.display());
+    };
+    let width = pattern[start..].chars().take_while(|&c| c == '#').count();
+    format!(
+        "{}{frame:0width$}{}",
+        &pattern[..start],
+        &pattern[start + width..]
+    )
+}
+
+/// Writes to a temporary file first, so that an interrupted run doesn't
+/// leave an image that looks finished
+fn save_frame(renderer: &RaytracingRenderer, path: &str) -> Result<(), Box<dyn Error>> {
+    let path = Path::new(path);
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let temporary = path.with_extension("tmp");
+    renderer
+        .canvas
+        .to_image()
+        .save_with_format(&temporary, ImageFormat::from_path(path)?)?;
+    fs::rename(temporary, path)?;
+    Ok(())
+}
+
+/// Goes on from the checkpoint file when it is of this frame
+fn resume(renderer: &mut RaytracingRenderer, path: &str, scene: &Scene, camera: &Camera) {
+    if !Path::new(path).exists() {
+        return;
+    }
+    let resumed =
+        Checkpoint::load(path).and_then(|checkpoint| renderer.resume(checkpoint, scene, camera));
+    match resumed {
+        Ok(()) => eprintln!("resuming from {path} at sample {}", renderer.frame_index()),
+        Err(err) => eprintln!("not resuming from {path}: {err}"),
+    }
+}
+
+fn render_turntable(options: Options, mut turntable: Turntable) -> Result<(), Box<dyn Error>> {
+    let mut scene = Scene::load(&options.scene)?;
+    // animated scenes are shown as they start
+    scene.animate(0.0);
+    turntable.samples = options.samples;
+    turntable.width = options.width;
+    turntable.height = options.height;
+    turntable.path = options.output.unwrap_or(turntable.path);
+    let base = scene.camera.unwrap_or_default();
+    let mut job = TurntableJob::new(turntable, options.integrator, base)?;
+    let start = Instant::now();
+    while !job.is_done() {
+        job.step(&scene);
+    }
+    let path = job.turntable.path.clone();
+    job.finish(&scene)?;
+    eprintln!("turntable in {:.2?}: {path}", start.elapsed());
+    Ok(())
+}
+
+fn run(options: Options) -> Result<(), Box<dyn Error>> {
+    if let Some(address) = &options.serve {
+        let defaults = JobSettings {
+            samples: options.samples,
+            width: options.width,
+            height: options.height,
+            integrator: options.integrator,
+        };
+        eprintln!("serving on http://{address}");
+        return service::serve(address, defaults);
+    }
+    if let Some(turntable) = options.turntable.clone() {
+        return render_turntable(options, turntable);
+    }
+    let output = options.output.as_deref().unwrap_or("frame_####.png");
+    let mut scene = Scene::load(&options.scene)?;
+    let animation = scene.animation.clone().unwrap_or_default();
+    let frames = options.frames.clone().unwrap_or(0..animation.frame_count());
+
+    let mut camera = Camera::new(45.0, 0.1, 100.0);
+    camera.on_resize(options.width, options.height);
+    let settings = RendererSettings {
+        accumulate: true,
+        use_threads: true,
+        slow_random: false,
+    };
+    let mut renderer = RaytracingRenderer::new(Canvas::new(1, 1), settings);
+    renderer.on_resize(options.width, options.height);
+    renderer.set_integrator(options.integrator);
+
+    let scene_path = fs::canonicalize(&options.scene)?;
+    let mut coordinator = match options.workers {
+        0 => None,
+        count => Some(Coordinator::spawn(
+            count,
+            &std::env::current_exe()?,
+            &["--worker"],
+        )?),
+    };
+
+    for frame in frames.clone() {
+        let path = frame_path(output, frame);
+        if options.checkpoint.is_some() && Path::new(&path).exists() {
+            eprintln!("frame {frame} is already rendered: {path}");
+            continue;
+        }
+        scene.animate(animation.frame_time(frame));
+        if let Some(pose) = &scene.camera {
+            camera.set_pose(pose);
+        }
+        if let Some(coordinator) = &mut coordinator {
+            let start = Instant::now();
+            let job = Job {
+                scene: scene_path.clone(),
+                time: animation.frame_time(frame),
+                camera: camera.pose(),
+                width: options.width,
+                height: options.height,
+                integrator: options.integrator,
+            };
+            let batch = options
+                .batch
+                .unwrap_or(options.samples / (4 * coordinator.worker_count().max(1)));
+            coordinator.render(&job, options.samples, batch, &mut renderer, &scene, &camera)?;
+            save_frame(&renderer, &path)?;
+            eprintln!(
+                "frame {frame} on {} workers in {:.2?}: {path}",
+                coordinator.worker_count(),
+                start.elapsed()
+            );
+            continue;
+        }
+
+        renderer.reset_frame_index();
+        if let Some(checkpoint) = &options.checkpoint {
+            resume(&mut renderer, checkpoint, &scene, &camera);
+        }
+        let mut elapsed = Duration::ZERO;
+        let mut last_checkpoint = Instant::now();
+        while renderer.frame_index() <= options.samples {
+            elapsed += renderer.render(&scene, &camera);
+            let Some(checkpoint) = &options.checkpoint else {
+                continue;
+            };
+            let done = renderer.frame_index() > options.samples;
+            if done || last_checkpoint.elapsed() >= options.checkpoint_interval {
+                renderer.checkpoint(&scene, &camera).save(checkpoint)?;
+                last_checkpoint = Instant::now();
+            }
+        }
+
+        save_frame(&renderer, &path)?;
+        eprintln!(
+            "frame {frame} ({} of {}) in {elapsed:.2?}: {path}",
+            frame - frames.start + 1,
+            frames.len()
+        );
+    }
+    Ok(())
+}
+
+fn main() {
+    let mut args = std::env::args().skip(1);
+    if let ("--worker", Some(address)) = (args.next().as_deref().unwrap_or(""), args.next()) {
+        if let Err(err) = distributed::work(&address) {
+            eprintln!("worker failed: {err}");
+            process::exit(1);
+        }
+        return;
+    }
+    let options = parse_options(std::env::args().skip(1)).unwrap_or_else(|err| {
+        eprintln!("{err}\n{USAGE}");
+        process::exit(2);
+    });
+    if let Err(err) = run(options) {
+        eprintln!("render failed: {err}");
+        process::exit(1);
+    }
+}
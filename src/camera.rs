@@ -1,7 +1,10 @@
 extern crate nalgebra_glm as glm;
-use std::fmt::Debug;
+use std::{error::Error, fmt::Debug};
 
-use nalgebra::{Matrix4, Vector2, Vector3};
+use nalgebra::{Matrix4, UnitQuaternion, Vector2, Vector3};
+use serde::{Deserialize, Serialize};
+
+use crate::rt::ray::Ray;
 use winit::event::{
     ElementState::{Pressed, Released},
     KeyboardInput, VirtualKeyCode,
@@ -18,6 +21,77 @@ pub struct CameraState {
     pub is_active: bool,
 }
 
+/// Placement and field of view of a camera, as stored in scene files
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CameraPose {
+    pub position: Vector3<f64>,
+    pub forward_direction: Vector3<f64>,
+    /// In degrees
+    pub vertical_fov: f64,
+    /// Times the shutter opens and closes, every ray gets a time between the
+    /// two. Equal times give no motion blur. Motion goes from time 0 to 1.
+    pub shutter: [f64; 2],
+    /// Where the camera is at time 1 when it moves, going in a straight line
+    /// and turning from this pose at time 0
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub end: Option<EndPose>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct EndPose {
+    pub position: Vector3<f64>,
+    pub forward_direction: Vector3<f64>,
+}
+
+impl Default for CameraPose {
+    fn default() -> Self {
+        CameraPose {
+            position: glm::vec3(0.0, 0.0, 6.0),
+            forward_direction: glm::vec3(0.0, 0.0, -1.0),
+            vertical_fov: 45.0,
+            shutter: [0.0, 0.0],
+            end: None,
+        }
+    }
+}
+
+impl CameraPose {
+    /// Err when the direction is zero or the field of view is out of range,
+    /// poses that would render NaN
+    pub fn check(&self) -> Result<(), Box<dyn Error>> {
+        if !self.forward_direction.norm().is_normal() {
+            return Err("forward_direction should not be zero".into());
+        }
+        if !(self.vertical_fov > 0.0 && self.vertical_fov < 180.0) {
+            return Err("vertical_fov should be between 0 and 180 degrees".into());
+        }
+        Ok(())
+    }
+}
+
+/// Movement of the camera from time 0 to time 1
+#[derive(Clone, Copy, Debug)]
+struct CameraMotion {
+    translation: Vector3<f64>,
+    rotation: UnitQuaternion<f64>,
+}
+
+/// Where points land on the camera's image, without the per pixel ray
+/// directions, for tracing light towards the camera
+#[derive(Clone, Copy, Debug)]
+pub struct CameraProjection {
+    pub position: Vector3<f64>,
+    pub forward_direction: Vector3<f64>,
+    pub width: u32,
+    pub height: u32,
+    view_projection: Matrix4<f64>,
+    projection: Matrix4<f64>,
+    /// Area of the image on a plane one unit in front of the camera
+    pub image_area: f64,
+    motion: Option<CameraMotion>,
+}
+
 pub struct Camera {
     pub position: Vector3<f64>,
     pub forward_direction: Vector3<f64>,
@@ -33,6 +107,8 @@ pub struct Camera {
     viewport_height: u32,
     pub state: CameraState,
     ray_directions: Vec<Vector3<f64>>,
+    pub shutter: [f64; 2],
+    motion: Option<CameraMotion>,
 }
 
 impl Camera {
@@ -63,6 +139,8 @@ impl Camera {
                 backward_speed: 0.0,
                 is_active: false,
             },
+            shutter: [0.0, 0.0],
+            motion: None,
         };
         camera.recalculate_view();
         camera
@@ -122,6 +200,54 @@ impl Camera {
         self.recalculate_ray_directions();
     }
 
+    pub fn pose(&self) -> CameraPose {
+        CameraPose {
+            position: self.position,
+            forward_direction: self.forward_direction,
+            vertical_fov: self.vertical_fov,
+            shutter: self.shutter,
+            end: self.motion.map(|motion| EndPose {
+                position: self.position + motion.translation,
+                forward_direction: motion.rotation * self.forward_direction,
+            }),
+        }
+    }
+
+    pub fn projection(&self) -> CameraProjection {
+        let height = 2.0 * (self.vertical_fov.to_radians() / 2.0).tan();
+        let aspect = self.viewport_width as f64 / self.viewport_height as f64;
+        CameraProjection {
+            position: self.position,
+            forward_direction: self.forward_direction.normalize(),
+            width: self.viewport_width,
+            height: self.viewport_height,
+            view_projection: self.projection * self.view,
+            projection: self.projection,
+            image_area: height * height * aspect,
+            motion: self.motion,
+        }
+    }
+
+    pub fn set_pose(&mut self, pose: &CameraPose) {
+        self.position = pose.position;
+        self.forward_direction = pose.forward_direction.normalize();
+        self.vertical_fov = pose.vertical_fov;
+        self.shutter = pose.shutter;
+        self.motion = pose.end.map(|end| {
+            let up = Vector3::y();
+            let start = UnitQuaternion::face_towards(&self.forward_direction, &up);
+            let end_rotation = UnitQuaternion::face_towards(&end.forward_direction, &up);
+            CameraMotion {
+                translation: end.position - self.position,
+                rotation: end_rotation * start.inverse(),
+            }
+        });
+
+        self.recalculate_view();
+        self.recalculate_projection();
+        self.recalculate_ray_directions();
+    }
+
     pub fn handle_input(&mut self, input: KeyboardInput) {
         let speed = 5.0;
         match input.state {
@@ -151,6 +277,25 @@ impl Camera {
         &self.ray_directions
     }
 
+    /// Time within the shutter interval, `u` going from 0 to 1
+    pub fn shutter_time(&self, u: f64) -> f64 {
+        let [open, close] = self.shutter;
+        open + (close - open) * u
+    }
+
+    /// Ray through a pixel at `time`, from where the camera is then
+    pub fn ray(&self, pixel: usize, time: f64) -> Ray {
+        let direction = self.ray_directions[pixel];
+        match &self.motion {
+            Some(motion) => Ray::with_time(
+                self.position + motion.translation * time,
+                motion.rotation.powf(time) * direction,
+                time,
+            ),
+            None => Ray::with_time(self.position, direction, time),
+        }
+    }
+
     pub fn get_rotation_speed(&self) -> f64 {
         0.3
     }
@@ -204,3 +349,62 @@ impl Camera {
         }
     }
 }
+
+impl CameraProjection {
+    /// Projection from where the camera is at `time`, for cameras that move
+    /// during the shutter interval
+    pub fn at(&self, time: f64) -> CameraProjection {
+        let Some(motion) = self.motion else {
+            return *self;
+        };
+        let position = self.position + motion.translation * time;
+        let forward_direction = motion.rotation.powf(time) * self.forward_direction;
+        let view = glm::look_at(
+            &position,
+            &(position + forward_direction),
+            &glm::vec3(0.0, 1.0, 0.0),
+        );
+        CameraProjection {
+            position,
+            forward_direction,
+            view_projection: self.projection * view,
+            motion: None,
+            ..*self
+        }
+    }
+
+    /// Index of the pixel whose ray goes through `point`, `None` when the
+    /// point is out of view
+    pub fn pixel_of(&self, point: &Vector3<f64>) -> Option<usize> {
+        let clip = self.view_projection * point.insert_row(3, 1.0);
+        if clip.w <= 0.0 {
+            return None;
+        }
+        let x = ((clip.x / clip.w + 1.0) / 2.0 * self.width as f64).floor();
+        let y = ((clip.y / clip.w + 1.0) / 2.0 * self.height as f64).floor();
+        if x < 0.0 || y < 0.0 || x >= self.width as f64 || y >= self.height as f64 {
+            return None;
+        }
+        Some(x as usize + y as usize * self.width as usize)
+    }
+
+    /// Importance emitted towards `direction`, normalized over the whole
+    /// image
+    pub fn importance(&self, direction: &Vector3<f64>) -> f64 {
+        let cos_theta = direction.normalize().dot(&self.forward_direction);
+        if cos_theta <= 0.0 {
+            return 0.0;
+        }
+        1.0 / (self.image_area * cos_theta.powi(4))
+    }
+
+    /// Solid angle density of the camera rays towards `direction`, as if the
+    /// pixel was picked at random
+    pub fn direction_pdf(&self, direction: &Vector3<f64>) -> f64 {
+        let cos_theta = direction.normalize().dot(&self.forward_direction);
+        if cos_theta <= 0.0 {
+            return 0.0;
+        }
+        1.0 / (self.image_area * cos_theta.powi(3))
+    }
+}
@@ -0,0 +1,616 @@
+//! C interface of the library, declared in `include/raytracing.h`
+//!
+//! Functions return an `RtStatus` instead of panicking; the message of the
+//! last error of a thread is given by `rt_last_error`. Scenes and renderers
+//! are opaque handles, created and destroyed by the library. Vectors are
+//! arrays of three `double`s.
+
+use std::{
+    cell::RefCell,
+    ffi::{c_char, CStr, CString},
+    mem,
+    panic::{self, AssertUnwindSafe},
+    slice,
+};
+
+use nalgebra::{Vector3, Vector4};
+
+use crate::{
+    camera::{Camera, CameraPose},
+    integrator::IntegratorKind,
+    renderer::{Canvas, RaytracingRenderer, RendererSettings},
+    scene::{Light, Material, Scene},
+    shapes::{Cuboid, Mesh, Plane, Shape, Sphere},
+};
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RtStatus {
+    Ok = 0,
+    NullPointer = 1,
+    InvalidArgument = 2,
+    OutOfRange = 3,
+    LoadFailed = 4,
+    /// A bug in the library, the handles it was given should be destroyed
+    Panic = 5,
+}
+
+/// Parameters of a material, colors are linear RGBA
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct RtMaterial {
+    pub albedo: [f64; 4],
+    pub roughness: f64,
+    pub metallic: f64,
+    pub emission_color: [f64; 4],
+    pub emission_power: f64,
+    pub transmission: f64,
+}
+
+pub struct RtScene {
+    scene: Scene,
+    /// Whether objects were added since the acceleration was built
+    changed: bool,
+}
+
+pub struct RtRenderer {
+    renderer: RaytracingRenderer,
+    camera: Camera,
+}
+
+struct Failure(RtStatus, String);
+
+type Outcome = Result<(), Failure>;
+
+thread_local! {
+    static LAST_ERROR: RefCell<CString> = RefCell::new(CString::default());
+}
+
+fn set_last_error(message: &str) {
+    let message = CString::new(message.replace('\0', " ")).expect("no nul bytes left");
+    LAST_ERROR.with(|last| *last.borrow_mut() = message);
+}
+
+/// Runs `body`, turning its failures and panics into a status
+fn guard(body: impl FnOnce() -> Outcome) -> RtStatus {
+    let failure = match panic::catch_unwind(AssertUnwindSafe(body)) {
+        Ok(Ok(())) => {
+            set_last_error("");
+            return RtStatus::Ok;
+        }
+        Ok(Err(failure)) => failure,
+        Err(payload) => {
+            let message = payload
+                .downcast_ref::<&str>()
+                .map(|message| message.to_string())
+                .or_else(|| payload.downcast_ref::<String>().cloned())
+                .unwrap_or_else(|| "unknown panic".to_string());
+            Failure(RtStatus::Panic, message)
+        }
+    };
+    set_last_error(&failure.1);
+    failure.0
+}
+
+fn invalid(message: impl Into<String>) -> Failure {
+    Failure(RtStatus::InvalidArgument, message.into())
+}
+
+/// # Safety
+/// `pointer` is null or valid for the lifetime of the result
+unsafe fn reference<'a, T>(pointer: *const T, name: &str) -> Result<&'a T, Failure> {
+    pointer
+        .as_ref()
+        .ok_or_else(|| Failure(RtStatus::NullPointer, format!("{name} is null")))
+}
+
+/// # Safety
+/// `pointer` is null or valid and unaliased for the lifetime of the result
+unsafe fn mutable<'a, T>(pointer: *mut T, name: &str) -> Result<&'a mut T, Failure> {
+    pointer
+        .as_mut()
+        .ok_or_else(|| Failure(RtStatus::NullPointer, format!("{name} is null")))
+}
+
+/// # Safety
+/// `pointer` is null or points to three `double`s
+unsafe fn vector(pointer: *const f64, name: &str) -> Result<Vector3<f64>, Failure> {
+    let components = reference(pointer.cast::<[f64; 3]>(), name)?;
+    Ok(Vector3::from(*components))
+}
+
+/// Length of a slice of `count` groups of three `T`, when it can be one
+fn triples<T>(count: usize, name: &str) -> Result<usize, Failure> {
+    count
+        .checked_mul(3)
+        .filter(|&length| length <= isize::MAX as usize / mem::size_of::<T>())
+        .ok_or_else(|| Failure(RtStatus::OutOfRange, format!("{name} is too large")))
+}
+
+/// # Safety
+/// `out` is null or valid for writes
+unsafe fn write<T>(out: *mut T, value: T) {
+    if let Some(out) = out.as_mut() {
+        *out = value;
+    }
+}
+
+/// Message of the last error on this thread, empty after a success. Valid
+/// until the next call on the thread.
+#[no_mangle]
+pub extern "C" fn rt_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| last.borrow().as_ptr())
+}
+
+/// # Safety
+/// `out` is null or valid for writes
+#[no_mangle]
+pub unsafe extern "C" fn rt_scene_create(out: *mut *mut RtScene) -> RtStatus {
+    guard(|| {
+        let out = mutable(out, "out")?;
+        *out = Box::into_raw(Box::new(RtScene {
+            scene: Scene::default(),
+            changed: false,
+        }));
+        Ok(())
+    })
+}
+
+/// Reads a scene document, relative paths in it start at its directory
+///
+/// # Safety
+/// `path` is null or a nul terminated string, `out` is null or valid for
+/// writes
+#[no_mangle]
+pub unsafe extern "C" fn rt_scene_load(path: *const c_char, out: *mut *mut RtScene) -> RtStatus {
+    guard(|| {
+        let out = mutable(out, "out")?;
+        if path.is_null() {
+            return Err(Failure(RtStatus::NullPointer, "path is null".into()));
+        }
+        let path = CStr::from_ptr(path)
+            .to_str()
+            .map_err(|_| invalid("path is not UTF-8"))?;
+        let scene =
+            Scene::load(path).map_err(|err| Failure(RtStatus::LoadFailed, err.to_string()))?;
+        *out = Box::into_raw(Box::new(RtScene {
+            scene,
+            changed: false,
+        }));
+        Ok(())
+    })
+}
+
+/// # Safety
+/// `scene` is null or was created by the library and not destroyed yet
+#[no_mangle]
+pub unsafe extern "C" fn rt_scene_destroy(scene: *mut RtScene) {
+    if !scene.is_null() {
+        drop(Box::from_raw(scene));
+    }
+}
+
+/// Parameters of the default material
+///
+/// # Safety
+/// `out` is null or valid for writes
+#[no_mangle]
+pub unsafe extern "C" fn rt_material_default(out: *mut RtMaterial) -> RtStatus {
+    guard(|| {
+        let material = Material::default();
+        *mutable(out, "out")? = RtMaterial {
+            albedo: material.albedo.into(),
+            roughness: material.roughness,
+            metallic: material.metallic,
+            emission_color: material.emission_color.into(),
+            emission_power: material.emission_power,
+            transmission: material.transmission,
+        };
+        Ok(())
+    })
+}
+
+/// # Safety
+/// `scene` and `material` are null or valid, `index` is null or valid for
+/// writes
+#[no_mangle]
+pub unsafe extern "C" fn rt_scene_add_material(
+    scene: *mut RtScene,
+    material: *const RtMaterial,
+    index: *mut usize,
+) -> RtStatus {
+    guard(|| {
+        let scene = &mut mutable(scene, "scene")?.scene;
+        let material = reference(material, "material")?;
+        scene.materials.push(Material {
+            albedo: Vector4::from(material.albedo),
+            roughness: material.roughness,
+            metallic: material.metallic,
+            emission_color: Vector4::from(material.emission_color),
+            emission_power: material.emission_power,
+            transmission: material.transmission,
+            ..Default::default()
+        });
+        write(index, scene.materials.len() - 1);
+        Ok(())
+    })
+}
+
+/// Changes the material at `index`, keeping its textures
+///
+/// # Safety
+/// `scene` and `material` are null or valid
+#[no_mangle]
+pub unsafe extern "C" fn rt_scene_set_material(
+    scene: *mut RtScene,
+    index: usize,
+    material: *const RtMaterial,
+) -> RtStatus {
+    guard(|| {
+        let scene = &mut mutable(scene, "scene")?.scene;
+        let material = reference(material, "material")?;
+        let slot = scene
+            .materials
+            .get_mut(index)
+            .ok_or_else(|| Failure(RtStatus::OutOfRange, format!("no material {index}")))?;
+        slot.albedo = Vector4::from(material.albedo);
+        slot.roughness = material.roughness;
+        slot.metallic = material.metallic;
+        slot.emission_color = Vector4::from(material.emission_color);
+        slot.emission_power = material.emission_power;
+        slot.transmission = material.transmission;
+        Ok(())
+    })
+}
+
+fn add_shape(
+    scene: &mut RtScene,
+    shape: impl Shape + 'static,
+    material: usize,
+    index: *mut usize,
+) -> Outcome {
+    if material >= scene.scene.materials.len() {
+        return Err(Failure(
+            RtStatus::OutOfRange,
+            format!("no material {material}"),
+        ));
+    }
+    let added = scene.scene.add(shape, material);
+    scene.changed = true;
+    // SAFETY: the callers' contract
+    unsafe { write(index, added) };
+    Ok(())
+}
+
+/// # Safety
+/// `scene` is null or valid, `center` is null or points to three doubles,
+/// `index` is null or valid for writes
+#[no_mangle]
+pub unsafe extern "C" fn rt_scene_add_sphere(
+    scene: *mut RtScene,
+    center: *const f64,
+    radius: f64,
+    material: usize,
+    index: *mut usize,
+) -> RtStatus {
+    guard(|| {
+        let scene = mutable(scene, "scene")?;
+        let center = vector(center, "center")?;
+        if radius.is_nan() || radius <= 0.0 {
+            return Err(invalid("radius should be positive"));
+        }
+        add_shape(scene, Sphere::new(center, radius), material, index)
+    })
+}
+
+/// # Safety
+/// `scene` is null or valid, `point` and `normal` are null or point to
+/// three doubles, `index` is null or valid for writes
+#[no_mangle]
+pub unsafe extern "C" fn rt_scene_add_plane(
+    scene: *mut RtScene,
+    point: *const f64,
+    normal: *const f64,
+    material: usize,
+    index: *mut usize,
+) -> RtStatus {
+    guard(|| {
+        let scene = mutable(scene, "scene")?;
+        let point = vector(point, "point")?;
+        let normal = vector(normal, "normal")?;
+        if normal.norm() == 0.0 {
+            return Err(invalid("normal should not be zero"));
+        }
+        add_shape(
+            scene,
+            Plane::new(point, normal.normalize()),
+            material,
+            index,
+        )
+    })
+}
+
+/// Box between two corners, aligned with the axes
+///
+/// # Safety
+/// `scene` is null or valid, `min` and `max` are null or point to three
+/// doubles, `index` is null or valid for writes
+#[no_mangle]
+pub unsafe extern "C" fn rt_scene_add_cuboid(
+    scene: *mut RtScene,
+    min: *const f64,
+    max: *const f64,
+    material: usize,
+    index: *mut usize,
+) -> RtStatus {
+    guard(|| {
+        let scene = mutable(scene, "scene")?;
+        let (min, max) = (vector(min, "min")?, vector(max, "max")?);
+        add_shape(
+            scene,
+            Cuboid::new(min.inf(&max), min.sup(&max)),
+            material,
+            index,
+        )
+    })
+}
+
+/// Triangle mesh of `vertex_count` positions, three doubles each, and
+/// `triangle_count` triangles, three vertex indices each
+///
+/// # Safety
+/// `scene` is null or valid, `positions` and `triangles` are null or hold
+/// the given counts, `index` is null or valid for writes
+#[no_mangle]
+pub unsafe extern "C" fn rt_scene_add_mesh(
+    scene: *mut RtScene,
+    positions: *const f64,
+    vertex_count: usize,
+    triangles: *const u32,
+    triangle_count: usize,
+    material: usize,
+    index: *mut usize,
+) -> RtStatus {
+    guard(|| {
+        let scene = mutable(scene, "scene")?;
+        if positions.is_null() || triangles.is_null() {
+            return Err(Failure(RtStatus::NullPointer, "mesh data is null".into()));
+        }
+        let position_length = triples::<f64>(vertex_count, "vertex_count")?;
+        let triangle_length = triples::<u32>(triangle_count, "triangle_count")?;
+        let positions: Vec<Vector3<f64>> = slice::from_raw_parts(positions, position_length)
+            .chunks_exact(3)
+            .map(Vector3::from_column_slice)
+            .collect();
+        let triangles: Vec<[u32; 3]> = slice::from_raw_parts(triangles, triangle_length)
+            .chunks_exact(3)
+            .map(|triangle| [triangle[0], triangle[1], triangle[2]])
+            .collect();
+        if triangles.is_empty() {
+            return Err(invalid("a mesh needs triangles"));
+        }
+        if let Some(vertex) = triangles
+            .iter()
+            .flatten()
+            .find(|&&vertex| vertex as usize >= vertex_count)
+        {
+            return Err(Failure(RtStatus::OutOfRange, format!("no vertex {vertex}")));
+        }
+        add_shape(scene, Mesh::new(positions, triangles), material, index)
+    })
+}
+
+/// Light from a point, `color` is linear RGB
+///
+/// # Safety
+/// `scene` is null or valid, `position` and `color` are null or point to
+/// three doubles
+#[no_mangle]
+pub unsafe extern "C" fn rt_scene_add_point_light(
+    scene: *mut RtScene,
+    position: *const f64,
+    color: *const f64,
+    intensity: f64,
+) -> RtStatus {
+    guard(|| {
+        let scene = &mut mutable(scene, "scene")?.scene;
+        if !(intensity.is_finite() && intensity >= 0.0) {
+            return Err(invalid("intensity should be finite and not negative"));
+        }
+        scene.lights.push(Light::Point {
+            position: vector(position, "position")?,
+            color: vector(color, "color")?,
+            intensity,
+        });
+        Ok(())
+    })
+}
+
+/// Renderer of `width` by `height` pixels, with the path tracer and the
+/// camera the viewer starts with
+///
+/// # Safety
+/// `out` is null or valid for writes
+#[no_mangle]
+pub unsafe extern "C" fn rt_renderer_create(
+    width: u32,
+    height: u32,
+    out: *mut *mut RtRenderer,
+) -> RtStatus {
+    guard(|| {
+        let out = mutable(out, "out")?;
+        if width == 0 || height == 0 {
+            return Err(invalid("width and height should not be zero"));
+        }
+        let settings = RendererSettings {
+            accumulate: true,
+            use_threads: true,
+            slow_random: false,
+        };
+        let mut renderer = RaytracingRenderer::new(Canvas::new(1, 1), settings);
+        renderer.on_resize(width, height);
+        let mut camera = Camera::new(45.0, 0.1, 100.0);
+        camera.on_resize(width, height);
+        *out = Box::into_raw(Box::new(RtRenderer { renderer, camera }));
+        Ok(())
+    })
+}
+
+/// # Safety
+/// `renderer` is null or was created by the library and not destroyed yet
+#[no_mangle]
+pub unsafe extern "C" fn rt_renderer_destroy(renderer: *mut RtRenderer) {
+    if !renderer.is_null() {
+        drop(Box::from_raw(renderer));
+    }
+}
+
+/// Places the camera, starting the accumulation again
+///
+/// # Safety
+/// `renderer` is null or valid, `position` and `forward_direction` are null
+/// or point to three doubles
+#[no_mangle]
+pub unsafe extern "C" fn rt_renderer_set_camera(
+    renderer: *mut RtRenderer,
+    position: *const f64,
+    forward_direction: *const f64,
+    vertical_fov: f64,
+) -> RtStatus {
+    guard(|| {
+        let renderer = mutable(renderer, "renderer")?;
+        let position = vector(position, "position")?;
+        let forward_direction = vector(forward_direction, "forward_direction")?;
+        if forward_direction.norm() == 0.0 {
+            return Err(invalid("forward_direction should not be zero"));
+        }
+        if !(vertical_fov > 0.0 && vertical_fov < 180.0) {
+            return Err(invalid("vertical_fov should be between 0 and 180 degrees"));
+        }
+        renderer.camera.set_pose(&CameraPose {
+            position,
+            forward_direction,
+            vertical_fov,
+            ..Default::default()
+        });
+        renderer.renderer.reset_frame_index();
+        Ok(())
+    })
+}
+
+/// Switches to the integrator named like in the viewer, starting the
+/// accumulation again
+///
+/// # Safety
+/// `renderer` is null or valid, `name` is null or a nul terminated string
+#[no_mangle]
+pub unsafe extern "C" fn rt_renderer_set_integrator(
+    renderer: *mut RtRenderer,
+    name: *const c_char,
+) -> RtStatus {
+    guard(|| {
+        let renderer = mutable(renderer, "renderer")?;
+        if name.is_null() {
+            return Err(Failure(RtStatus::NullPointer, "name is null".into()));
+        }
+        let name = CStr::from_ptr(name).to_string_lossy();
+        let kind = IntegratorKind::ALL
+            .into_iter()
+            .find(|kind| kind.name() == name.replace(['-', '_'], " "))
+            .ok_or_else(|| invalid(format!("unknown integrator '{name}'")))?;
+        renderer.renderer.set_integrator(kind);
+        Ok(())
+    })
+}
+
+/// Starts the accumulation again, needed after the scene changes
+///
+/// # Safety
+/// `renderer` is null or valid
+#[no_mangle]
+pub unsafe extern "C" fn rt_renderer_reset(renderer: *mut RtRenderer) -> RtStatus {
+    guard(|| {
+        mutable(renderer, "renderer")?.renderer.reset_frame_index();
+        Ok(())
+    })
+}
+
+/// Adds `samples` samples per pixel to the accumulation
+///
+/// # Safety
+/// `renderer` and `scene` are null or valid
+#[no_mangle]
+pub unsafe extern "C" fn rt_renderer_render(
+    renderer: *mut RtRenderer,
+    scene: *mut RtScene,
+    samples: u32,
+) -> RtStatus {
+    guard(|| {
+        let renderer = mutable(renderer, "renderer")?;
+        let scene = mutable(scene, "scene")?;
+        if scene.changed {
+            scene.scene.update();
+            scene.changed = false;
+        }
+        for _ in 0..samples {
+            renderer.renderer.render(&scene.scene, &renderer.camera);
+        }
+        Ok(())
+    })
+}
+
+/// Samples per pixel accumulated so far
+///
+/// # Safety
+/// `renderer` is null or valid, `samples` is null or valid for writes
+#[no_mangle]
+pub unsafe extern "C" fn rt_renderer_samples(
+    renderer: *const RtRenderer,
+    samples: *mut u32,
+) -> RtStatus {
+    guard(|| {
+        let renderer = reference(renderer, "renderer")?;
+        let done = renderer.renderer.frame_index().saturating_sub(1);
+        *mutable(samples, "samples")? = done as u32;
+        Ok(())
+    })
+}
+
+/// Copies the average of the accumulated samples into `pixels`, which holds
+/// `length` floats and needs `width * height * 4` of them: linear RGBA, the
+/// first row at the top
+///
+/// # Safety
+/// `renderer` is null or valid, `pixels` is null or valid for `length`
+/// writes
+#[no_mangle]
+pub unsafe extern "C" fn rt_renderer_read_pixels(
+    renderer: *const RtRenderer,
+    pixels: *mut f32,
+    length: usize,
+) -> RtStatus {
+    guard(|| {
+        let renderer = &reference(renderer, "renderer")?.renderer;
+        let width = renderer.canvas.width as usize;
+        let needed = width * renderer.canvas.height as usize * 4;
+        if pixels.is_null() {
+            return Err(Failure(RtStatus::NullPointer, "pixels is null".into()));
+        }
+        if length < needed {
+            return Err(Failure(
+                RtStatus::OutOfRange,
+                format!("{needed} floats are needed, not {length}"),
+            ));
+        }
+        let pixels = slice::from_raw_parts_mut(pixels, needed);
+        // the canvas' first row is at the bottom
+        let colors = renderer.linear_colors();
+        let rows = colors.chunks(width).rev().flatten();
+        for (pixel, color) in pixels.chunks_exact_mut(4).zip(rows) {
+            for (out, component) in pixel.iter_mut().zip(color.iter()) {
+                *out = *component as f32;
+            }
+        }
+        Ok(())
+    })
+}
@@ -0,0 +1,458 @@
+//! Integrators turn camera rays into colors. The renderer takes care of the
+//! pixels and the accumulation, and hands each camera ray to the integrator
+//! picked in its settings.
+
+use std::f64::consts::TAU;
+
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use nalgebra::{Vector2, Vector3, Vector4};
+
+use crate::{
+    camera::Camera,
+    random::random_f64,
+    rt::{onb::Onb, ray::Ray},
+    scene::{medium, Medium, Scene},
+    shapes::{Hit, Param},
+};
+
+pub mod ao;
+pub mod bdpt;
+pub mod debug;
+pub mod direct;
+pub mod path;
+pub mod photon;
+pub mod whitted;
+
+pub use ao::AmbientOcclusion;
+pub use bdpt::Bidirectional;
+pub use debug::DebugView;
+pub use direct::DirectLighting;
+pub use path::PathTracer;
+pub use photon::PhotonMapper;
+pub use whitted::Whitted;
+
+/// Color of the sky, seen by the rays that miss everything
+pub const SKY_COLOR: Vector4<f64> = Vector4::new(0.6, 0.7, 0.9, 1.0);
+
+pub trait Integrator: Send + Sync {
+    /// Short name, used in the user interface
+    fn name(&self) -> &'static str;
+
+    /// Called before every frame, for what only changes from frame to frame.
+    /// `frame_index` starts at 1 and goes back to it when the accumulation
+    /// is reset.
+    fn prepare(&mut self, _scene: &Scene, _camera: &Camera, _frame_index: usize) {}
+
+    /// Color seen along a camera ray. Integrators tracing paths from the
+    /// lights add the light they bring to other pixels to `splats`.
+    fn radiance(
+        &self,
+        ray: Ray,
+        scene: &Scene,
+        sampler: &mut Sampler,
+        splats: &mut Vec<Splat>,
+    ) -> Vector4<f64>;
+
+    /// Whether `radiance` returns CIE XYZ instead of RGB. The renderer
+    /// accumulates it as it is and turns it into RGB when showing it.
+    fn spectral(&self) -> bool {
+        false
+    }
+
+    /// Current values of the editable settings
+    fn params(&self) -> Vec<(&'static str, Param)> {
+        Vec::new()
+    }
+
+    /// Updates a setting returned by `params`, false if there is no setting
+    /// with that name and type
+    fn set_param(&mut self, _name: &str, _value: Param) -> bool {
+        false
+    }
+}
+
+/// Light reaching the camera through a pixel other than the one being
+/// rendered, added to the image after the frame
+pub struct Splat {
+    pub pixel: usize,
+    pub color: Vector4<f64>,
+}
+
+/// Random numbers for one camera sample
+pub struct Sampler {
+    pub seed: u32,
+    /// Use the thread's random generator instead of the seed hash
+    pub slow_random: bool,
+}
+
+/// Every integrator the renderer can be switched to
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IntegratorKind {
+    #[default]
+    Path,
+    SpectralPath,
+    Bidirectional,
+    PhotonMapping,
+    AmbientOcclusion,
+    DirectLighting,
+    Whitted,
+    Normals,
+    Depth,
+    Uv,
+    BounceCount,
+    BvhCost,
+}
+
+#[derive(Default)]
+pub struct HitPayload {
+    pub hit_distance: f64,
+    pub world_position: Vector3<f64>,
+    /// Faces the ray, on whichever side it hit
+    pub world_normal: Vector3<f64>,
+    /// Whether the ray hit the outside of the surface
+    pub front_face: bool,
+    pub uv: Vector2<f64>,
+    /// Per vertex color of meshes, multiplies the material albedo
+    pub vertex_color: Option<Vector4<f64>>,
+    pub object_index: usize,
+    pub material_index: usize,
+    /// Time of the ray that made the hit
+    pub time: f64,
+}
+
+impl Sampler {
+    pub fn new(seed: u32, slow_random: bool) -> Sampler {
+        Sampler { seed, slow_random }
+    }
+
+    /// Uniform in [0, 1)
+    pub fn next_f64(&mut self) -> f64 {
+        if self.slow_random {
+            rand::thread_rng().gen()
+        } else {
+            random_f64(&mut self.seed)
+        }
+    }
+
+    /// Point in the cube from -1 to 1
+    pub fn in_unit_cube(&mut self) -> Vector3<f64> {
+        if self.slow_random {
+            let mut rng = rand::thread_rng();
+            Vector3::new(
+                rng.gen_range(-1.0..1.0),
+                rng.gen_range(-1.0..1.0),
+                rng.gen_range(-1.0..1.0),
+            )
+        } else {
+            Vector3::new(
+                random_f64(&mut self.seed) * 2.0 - 1.0,
+                random_f64(&mut self.seed) * 2.0 - 1.0,
+                random_f64(&mut self.seed) * 2.0 - 1.0,
+            )
+        }
+    }
+
+    /// Cosine weighted direction around `normal`
+    pub fn cosine_direction(&mut self, normal: &Vector3<f64>) -> Vector3<f64> {
+        let (u1, u2) = (self.next_f64(), self.next_f64());
+        let radius = u1.sqrt();
+        let angle = TAU * u2;
+        let local = Vector3::new(
+            radius * angle.cos(),
+            radius * angle.sin(),
+            (1.0 - u1).sqrt(),
+        );
+        Onb::from_w(normal).to_world(&local)
+    }
+
+    /// Uniformly distributed unit vector
+    pub fn unit_direction(&mut self) -> Vector3<f64> {
+        let z = 1.0 - 2.0 * self.next_f64();
+        let radius = (1.0 - z * z).max(0.0).sqrt();
+        let angle = TAU * self.next_f64();
+        Vector3::new(radius * angle.cos(), radius * angle.sin(), z)
+    }
+}
+
+impl IntegratorKind {
+    pub const ALL: [IntegratorKind; 12] = [
+        IntegratorKind::Path,
+        IntegratorKind::SpectralPath,
+        IntegratorKind::Bidirectional,
+        IntegratorKind::PhotonMapping,
+        IntegratorKind::AmbientOcclusion,
+        IntegratorKind::DirectLighting,
+        IntegratorKind::Whitted,
+        IntegratorKind::Normals,
+        IntegratorKind::Depth,
+        IntegratorKind::Uv,
+        IntegratorKind::BounceCount,
+        IntegratorKind::BvhCost,
+    ];
+
+    pub fn build(self) -> Box<dyn Integrator> {
+        match self {
+            IntegratorKind::Path => Box::<PathTracer>::default(),
+            IntegratorKind::SpectralPath => Box::new(PathTracer {
+                spectral: true,
+                ..Default::default()
+            }),
+            IntegratorKind::Bidirectional => Box::<Bidirectional>::default(),
+            IntegratorKind::PhotonMapping => Box::<PhotonMapper>::default(),
+            IntegratorKind::AmbientOcclusion => Box::<AmbientOcclusion>::default(),
+            IntegratorKind::DirectLighting => Box::<DirectLighting>::default(),
+            IntegratorKind::Whitted => Box::<Whitted>::default(),
+            IntegratorKind::Normals => Box::new(DebugView::Normals),
+            IntegratorKind::Depth => Box::new(DebugView::Depth),
+            IntegratorKind::Uv => Box::new(DebugView::Uv),
+            IntegratorKind::BounceCount => Box::new(DebugView::BounceCount),
+            IntegratorKind::BvhCost => Box::new(DebugView::BvhCost),
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            IntegratorKind::Path => "path tracing",
+            IntegratorKind::SpectralPath => "spectral path tracing",
+            IntegratorKind::Bidirectional => "bidirectional",
+            IntegratorKind::PhotonMapping => "photon mapping",
+            IntegratorKind::AmbientOcclusion => "ambient occlusion",
+            IntegratorKind::DirectLighting => "direct lighting",
+            IntegratorKind::Whitted => "whitted",
+            IntegratorKind::Normals => "normals",
+            IntegratorKind::Depth => "depth",
+            IntegratorKind::Uv => "uv",
+            IntegratorKind::BounceCount => "bounce count",
+            IntegratorKind::BvhCost => "bvh cost",
+        }
+    }
+}
+
+pub fn trace_ray(ray: &Ray, scene: &Scene) -> HitPayload {
+    match scene.intersect(ray, 0.0, f64::MAX) {
+        Some((object_index, hit)) => closest_hit(ray, scene, object_index, &hit),
+        None => miss(ray),
+    }
+}
+
+fn closest_hit(ray: &Ray, scene: &Scene, object_index: usize, hit: &Hit) -> HitPayload {
+    let object = &scene.objects[object_index];
+    let surface = object.shape.surface(ray, hit);
+
+    // flat surfaces can be hit from both sides, so make the normal face the ray
+    let front_face = surface.normal.dot(&ray.direction) <= 0.0;
+    let mut world_normal = if !front_face {
+        -surface.normal
+    } else {
+        surface.normal
+    };
+
+    if let Some(material) = scene.materials.get(object.material_index) {
+        if material.normal_map.is_some() || material.bump_map.is_some() {
+            let derivatives = object.shape.uv_derivatives(ray, hit);
+            world_normal = material.shading_normal(&world_normal, &surface.uv, derivatives);
+        }
+    }
+
+    HitPayload {
+        hit_distance: hit.distance,
+        world_position: ray.at(hit.distance),
+        world_normal,
+        front_face,
+        uv: surface.uv,
+        vertex_color: object.shape.color(ray, hit),
+        object_index,
+        material_index: object.material_index,
+        time: ray.time,
+    }
+}
+
+fn miss(ray: &Ray) -> HitPayload {
+    HitPayload {
+        hit_distance: f64::MAX,
+        time: ray.time,
+        ..Default::default()
+    }
+}
+
+/// Material albedo at the hit, with the vertex color applied
+pub fn albedo(scene: &Scene, payload: &HitPayload) -> Vector4<f64> {
+    let mut albedo = scene.materials[payload.material_index].albedo_at(&payload.uv);
+    if let Some(color) = payload.vertex_color {
+        albedo.component_mul_assign(&color);
+    }
+    albedo
+}
+
+/// Ray reflected or refracted by a smooth dielectric surface, picked with
+/// the Fresnel reflectance
+pub fn dielectric(ray: &Ray, payload: &HitPayload, ior: f64, sampler: &mut Sampler) -> Ray {
+    let normal = payload.world_normal;
+    let eta = if payload.front_face { 1.0 / ior } else { ior };
+    let direction = dielectric_direction(&ray.direction.normalize(), &normal, eta, sampler);
+    let side = normal.dot(&direction).signum();
+    Ray::with_time(
+        payload.world_position + normal * (0.0001 * side),
+        direction,
+        ray.time,
+    )
+}
+
+/// Direction leaving a smooth dielectric surface, `normal` facing the
+/// incoming unit `direction` and `eta` being the ratio of the indices of
+/// refraction on its side and on the other
+pub fn dielectric_direction(
+    direction: &Vector3<f64>,
+    normal: &Vector3<f64>,
+    eta: f64,
+    sampler: &mut Sampler,
+) -> Vector3<f64> {
+    let cos_i = (-direction.dot(normal)).clamp(0.0, 1.0);
+    let sin_t_squared = eta * eta * (1.0 - cos_i * cos_i);
+
+    if sin_t_squared < 1.0 {
+        let cos_t = (1.0 - sin_t_squared).sqrt();
+        let r_s = (eta * cos_i - cos_t) / (eta * cos_i + cos_t);
+        let r_p = (cos_i - eta * cos_t) / (cos_i + eta * cos_t);
+        let reflectance = (r_s * r_s + r_p * r_p) / 2.0;
+        if sampler.next_f64() >= reflectance {
+            return eta * direction + (eta * cos_i - cos_t) * normal;
+        }
+    }
+    direction - 2.0 * direction.dot(normal) * normal
+}
+
+/// Light reaching the hit point straight from the scene's lights
+pub fn direct_light(scene: &Scene, payload: &HitPayload, seed: &mut u32) -> Vector4<f64> {
+    let origin = payload.world_position + payload.world_normal * 0.0001;
+    scene
+        .lights
+        .iter()
+        .filter_map(|light| light.illuminate(&origin))
+        .filter_map(|sample| {
+            let cos_theta = sample.direction.dot(&payload.world_normal);
+            if cos_theta <= 0.0 {
+                return None;
+            }
+            let transmittance = shadow(
+                scene,
+                &origin,
+                &sample.direction,
+                sample.distance,
+                payload.time,
+                seed,
+            )?;
+            Some(sample.radiance.component_mul(&transmittance) * cos_theta)
+        })
+        .sum()
+}
+
+/// Emissive objects whose shape can be sampled, for the integrators that
+/// sample points on them
+pub fn area_lights(scene: &Scene) -> Vec<usize> {
+    scene
+        .objects
+        .iter()
+        .enumerate()
+        .filter(|(_, object)| {
+            scene
+                .materials
+                .get(object.material_index)
+                .is_some_and(|material| material.get_emission().xyz().max() > 0.0)
+                && object.shape.sample(0.0, &mut 0).is_some()
+        })
+        .map(|(i, _)| i)
+        .collect()
+}
+
+/// Irradiance at the hit point from the objects of `area_lights`, with one
+/// point sampled on every one of them. `direct_light` leaves these out, as
+/// paths find them by themselves.
+pub fn area_light_irradiance(
+    scene: &Scene,
+    area_lights: &[usize],
+    payload: &HitPayload,
+    seed: &mut u32,
+) -> Vector3<f64> {
+    let origin = payload.world_position + payload.world_normal * 0.0001;
+    let mut irradiance = Vector3::zeros();
+    for &object in area_lights {
+        let object = &scene.objects[object];
+        let Some(sample) = object.shape.sample(payload.time, seed) else {
+            continue;
+        };
+        let to_light = sample.position - origin;
+        let distance = to_light.norm();
+        let direction = to_light / distance;
+        let cos_theta = payload.world_normal.dot(&direction);
+        let cos_light = sample.normal.dot(&direction).abs();
+        if cos_theta <= 0.0 || cos_light <= 0.0 {
+            continue;
+        }
+        let Some(transmittance) = shadow(
+            scene,
+            &origin,
+            &direction,
+            distance - 0.001,
+            payload.time,
+            seed,
+        ) else {
+            continue;
+        };
+        let emission = scene.materials[object.material_index].get_emission().xyz();
+        // the area density of the point, as a solid angle one
+        irradiance += emission.component_mul(&transmittance.xyz()) * cos_theta * cos_light
+            / (distance * distance * sample.pdf);
+    }
+    irradiance
+}
+
+/// Light reaching a point inside a medium from the scene's lights and
+/// scattered along `direction`
+pub fn medium_direct_light(
+    scene: &Scene,
+    medium: &Medium,
+    position: &Vector3<f64>,
+    direction: &Vector3<f64>,
+    time: f64,
+    seed: &mut u32,
+) -> Vector4<f64> {
+    scene
+        .lights
+        .iter()
+        .filter_map(|light| light.illuminate(position))
+        .filter_map(|sample| {
+            let transmittance = shadow(
+                scene,
+                position,
+                &sample.direction,
+                sample.distance,
+                time,
+                seed,
+            )?;
+            let phase = medium.phase(direction, &sample.direction);
+            Some(sample.radiance.component_mul(&transmittance) * phase)
+        })
+        .sum()
+}
+
+/// Fraction of light getting from `origin` to a light `distance` away at
+/// `time`, `None` when a surface is in the way
+pub fn shadow(
+    scene: &Scene,
+    origin: &Vector3<f64>,
+    direction: &Vector3<f64>,
+    distance: f64,
+    time: f64,
+    seed: &mut u32,
+) -> Option<Vector4<f64>> {
+    let shadow_ray = Ray::with_time(*origin, *direction, time);
+    if scene.intersect(&shadow_ray, 0.0, distance).is_some() {
+        return None;
+    }
+    let media = scene.media(&shadow_ray, distance);
+    Some(medium::transmittance(&media, &shadow_ray, distance, seed).insert_row(3, 1.0))
+}
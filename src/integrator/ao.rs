@@ -0,0 +1,46 @@
+use nalgebra::Vector4;
+
+use super::{trace_ray, Integrator, Sampler, Splat};
+use crate::{rt::ray::Ray, scene::Scene};
+
+/// How open the surface seen by the camera is: white where nothing is
+/// within `distance` of it, darker in creases and corners
+pub struct AmbientOcclusion {
+    pub distance: f64,
+}
+
+impl Default for AmbientOcclusion {
+    fn default() -> Self {
+        AmbientOcclusion { distance: 1.0 }
+    }
+}
+
+impl Integrator for AmbientOcclusion {
+    fn name(&self) -> &'static str {
+        "ambient occlusion"
+    }
+
+    fn radiance(
+        &self,
+        ray: Ray,
+        scene: &Scene,
+        sampler: &mut Sampler,
+        _splats: &mut Vec<Splat>,
+    ) -> Vector4<f64> {
+        let payload = trace_ray(&ray, scene);
+        if payload.hit_distance == f64::MAX {
+            return Vector4::new(1.0, 1.0, 1.0, 1.0);
+        }
+
+        let occlusion_ray = Ray::with_time(
+            payload.world_position + payload.world_normal * 0.0001,
+            sampler.cosine_direction(&payload.world_normal),
+            ray.time,
+        );
+        let visibility = match scene.intersect(&occlusion_ray, 0.0, self.distance) {
+            Some(_) => 0.0,
+            None => 1.0,
+        };
+        Vector4::new(visibility, visibility, visibility, 1.0)
+    }
+}
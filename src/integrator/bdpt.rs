@@ -0,0 +1,874 @@
+//! Bidirectional path tracing: a path is traced from the camera and another
+//! one from a light, and every prefix of one is connected to every prefix of
+//! the other. All the ways of building the same path are weighted with
+//! multiple importance sampling (balance heuristic), and connections straight
+//! to the camera are splatted on the pixel they land on.
+//!
+//! Materials are read as smooth glass for their transmission, and a
+//! Lambertian lobe weighted by the roughness plus a perfect mirror for the
+//! rest. Emissive surfaces emit on both sides.
+//! Directional lights and the sky can't start light paths, so they are only
+//! found from the camera side. Media are ignored.
+
+use std::f64::consts::{PI, TAU};
+
+use nalgebra::{Vector3, Vector4};
+
+use super::{dielectric_direction, shadow, trace_ray, Integrator, Sampler, Splat, SKY_COLOR};
+use crate::{
+    camera::{Camera, CameraProjection},
+    rt::{onb::Onb, ray::Ray, spectrum::LAMBDA_RGB},
+    scene::{Light, Scene},
+};
+
+pub struct Bidirectional {
+    /// Most bounces a path can have, counting neither end
+    pub max_depth: usize,
+    camera: Option<CameraProjection>,
+    lights: Vec<PathLight>,
+    /// Index in `lights` of every object that light paths can start from
+    object_lights: Vec<Option<usize>>,
+}
+
+/// Light that light paths can start from
+enum PathLight {
+    /// Emissive object whose shape can be sampled
+    Area {
+        object: usize,
+        emission: Vector3<f64>,
+        /// Density of the points picked on it, with respect to area
+        pdf_position: f64,
+    },
+    /// Point or spot light of the scene
+    Delta { light: usize },
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum VertexKind {
+    Camera,
+    Light,
+    Surface,
+}
+
+#[derive(Clone, Copy)]
+struct Vertex {
+    kind: VertexKind,
+    position: Vector3<f64>,
+    /// Zero for the camera and point lights, which aren't on a surface
+    normal: Vector3<f64>,
+    /// Throughput from the start of the path up to this vertex
+    beta: Vector3<f64>,
+    /// Whether the path bounced off a mirror or went through glass here
+    delta: bool,
+    /// Area densities of reaching this vertex from the previous one, and
+    /// from the next one going the other way
+    pdf_forward: f64,
+    pdf_reverse: f64,
+    albedo: Vector3<f64>,
+    roughness: f64,
+    transmission: f64,
+    /// Ratio of the indices of refraction on the side of `normal` and on
+    /// the other
+    eta: f64,
+    emission: Vector3<f64>,
+    /// Index in `Bidirectional::lights` of the light at this vertex
+    light: Option<usize>,
+}
+
+/// Direction picked by a bounce off a surface
+struct Bounce {
+    direction: Vector3<f64>,
+    /// BSDF times cosine over the density
+    weight: Vector3<f64>,
+    /// Solid angle densities of the direction and of going back
+    pdf: f64,
+    pdf_reverse: f64,
+    delta: bool,
+}
+
+impl Default for Bidirectional {
+    fn default() -> Self {
+        Bidirectional {
+            max_depth: 5,
+            camera: None,
+            lights: Vec::new(),
+            object_lights: Vec::new(),
+        }
+    }
+}
+
+impl Integrator for Bidirectional {
+    fn name(&self) -> &'static str {
+        "bidirectional"
+    }
+
+    fn prepare(&mut self, scene: &Scene, camera: &Camera, _frame_index: usize) {
+        self.camera = Some(camera.projection());
+        self.lights.clear();
+        self.object_lights = vec![None; scene.objects.len()];
+
+        let mut seed = 0;
+        for (i, object) in scene.objects.iter().enumerate() {
+            let Some(material) = scene.materials.get(object.material_index) else {
+                continue;
+            };
+            let emission = material.get_emission().xyz();
+            if emission.max() <= 0.0 {
+                continue;
+            }
+            let Some(sample) = object.shape.sample(0.0, &mut seed) else {
+                continue;
+            };
+            self.object_lights[i] = Some(self.lights.len());
+            self.lights.push(PathLight::Area {
+                object: i,
+                emission,
+                pdf_position: sample.pdf,
+            });
+        }
+        for (i, light) in scene.lights.iter().enumerate() {
+            if matches!(light, Light::Point { .. } | Light::Spot { .. }) {
+                self.lights.push(PathLight::Delta { light: i });
+            }
+        }
+    }
+
+    fn radiance(
+        &self,
+        ray: Ray,
+        scene: &Scene,
+        sampler: &mut Sampler,
+        splats: &mut Vec<Splat>,
+    ) -> Vector4<f64> {
+        let Some(camera) = &self.camera else {
+            return Vector4::new(0.0, 0.0, 0.0, 1.0);
+        };
+
+        let mut radiance = Vector3::zeros();
+        let time = ray.time;
+        let camera = &camera.at(time);
+        let camera_path = self.camera_path(scene, camera, ray, sampler, &mut radiance);
+        let light_path = self.light_path(scene, time, sampler);
+
+        for t in 1..=camera_path.len() {
+            for s in 0..=light_path.len() {
+                let depth = s as isize + t as isize - 2;
+                if (s == 1 && t == 1) || depth < 0 || depth > self.max_depth as isize {
+                    continue;
+                }
+                let Some((color, pixel)) = self.connect(
+                    scene,
+                    camera,
+                    &light_path,
+                    &camera_path,
+                    s,
+                    t,
+                    time,
+                    sampler,
+                ) else {
+                    continue;
+                };
+                match pixel {
+                    Some(pixel) => splats.push(Splat {
+                        pixel,
+                        color: color.insert_row(3, 0.0),
+                    }),
+                    None => radiance += color,
+                }
+            }
+        }
+
+        radiance.insert_row(3, 1.0)
+    }
+}
+
+impl Bidirectional {
+    /// Path from the camera. Light from the sky and from directional lights,
+    /// which only this side can find, goes straight into `radiance`.
+    fn camera_path(
+        &self,
+        scene: &Scene,
+        camera: &CameraProjection,
+        ray: Ray,
+        sampler: &mut Sampler,
+        radiance: &mut Vector3<f64>,
+    ) -> Vec<Vertex> {
+        let start = Vertex {
+            kind: VertexKind::Camera,
+            position: ray.origin,
+            normal: Vector3::zeros(),
+            beta: Vector3::repeat(1.0),
+            ..Vertex::default()
+        };
+        let pdf = camera.direction_pdf(&ray.direction);
+        let mut path = vec![start];
+        self.random_walk(
+            scene,
+            ray,
+            Vector3::repeat(1.0),
+            pdf,
+            self.max_depth + 2,
+            &mut path,
+            sampler,
+            Some(radiance),
+        );
+        path
+    }
+
+    fn light_path(&self, scene: &Scene, time: f64, sampler: &mut Sampler) -> Vec<Vertex> {
+        if self.lights.is_empty() {
+            return Vec::new();
+        }
+        let index =
+            ((sampler.next_f64() * self.lights.len() as f64) as usize).min(self.lights.len() - 1);
+        let light_pdf = 1.0 / self.lights.len() as f64;
+
+        let (start, ray, beta, pdf) = match &self.lights[index] {
+            PathLight::Area {
+                object, emission, ..
+            } => {
+                let Some(sample) = scene.objects[*object].shape.sample(time, &mut sampler.seed)
+                else {
+                    return Vec::new();
+                };
+                // either side, then around the normal
+                let side = if sampler.next_f64() < 0.5 { 1.0 } else { -1.0 };
+                let normal = sample.normal * side;
+                let direction = sampler.cosine_direction(&normal);
+                let cos_theta = normal.dot(&direction);
+                let pdf_direction = cos_theta / TAU;
+                let start = Vertex {
+                    kind: VertexKind::Light,
+                    position: sample.position,
+                    normal: sample.normal,
+                    beta: *emission,
+                    pdf_forward: sample.pdf * light_pdf,
+                    emission: *emission,
+                    light: Some(index),
+                    ..Vertex::default()
+                };
+                let ray = Ray::with_time(sample.position + normal * 1e-4, direction, time);
+                let beta = emission * cos_theta / (light_pdf * sample.pdf * pdf_direction);
+                (start, ray, beta, pdf_direction)
+            }
+            PathLight::Delta { light } => {
+                let light = &scene.lights[*light];
+                let (position, direction, pdf_direction) = match light {
+                    Light::Point { position, .. } => {
+                        (*position, sampler.unit_direction(), 1.0 / (4.0 * PI))
+                    }
+                    Light::Spot {
+                        position,
+                        direction,
+                        outer_cone_angle,
+                        ..
+                    } => {
+                        let cos_outer = outer_cone_angle.cos();
+                        let cos_theta = 1.0 - sampler.next_f64() * (1.0 - cos_outer);
+                        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+                        let phi = TAU * sampler.next_f64();
+                        let local =
+                            Vector3::new(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta);
+                        let sampled = Onb::from_w(direction).to_world(&local);
+                        (*position, sampled, 1.0 / (TAU * (1.0 - cos_outer)))
+                    }
+                    Light::Directional { .. } => return Vec::new(),
+                };
+                let Some(emitted) = light.illuminate(&(position + direction)) else {
+                    return Vec::new();
+                };
+                let intensity = emitted.radiance.xyz();
+                let start = Vertex {
+                    kind: VertexKind::Light,
+                    position,
+                    normal: Vector3::zeros(),
+                    beta: intensity,
+                    pdf_forward: light_pdf,
+                    light: Some(index),
+                    ..Vertex::default()
+                };
+                let ray = Ray::with_time(position, direction, time);
+                let beta = intensity / (light_pdf * pdf_direction);
+                (start, ray, beta, pdf_direction)
+            }
+        };
+
+        let mut path = vec![start];
+        self.random_walk(
+            scene,
+            ray,
+            beta,
+            pdf,
+            self.max_depth + 1,
+            &mut path,
+            sampler,
+            None,
+        );
+        path
+    }
+
+    /// Extends `path` until it has `max_vertices` vertices or leaves the
+    /// scene, `pdf` being the solid angle density of the ray's direction
+    #[allow(clippy::too_many_arguments)]
+    fn random_walk(
+        &self,
+        scene: &Scene,
+        mut ray: Ray,
+        mut beta: Vector3<f64>,
+        mut pdf: f64,
+        max_vertices: usize,
+        path: &mut Vec<Vertex>,
+        sampler: &mut Sampler,
+        mut radiance: Option<&mut Vector3<f64>>,
+    ) {
+        while path.len() < max_vertices {
+            let payload = trace_ray(&ray, scene);
+            if payload.hit_distance == f64::MAX {
+                if let Some(radiance) = radiance.as_deref_mut() {
+                    *radiance += beta.component_mul(&SKY_COLOR.xyz());
+                }
+                break;
+            }
+
+            let material = &scene.materials[payload.material_index];
+            let mut albedo = material.albedo_at(&payload.uv);
+            if let Some(color) = payload.vertex_color {
+                albedo.component_mul_assign(&color);
+            }
+            let previous = path.len() - 1;
+            let mut vertex = Vertex {
+                kind: VertexKind::Surface,
+                position: payload.world_position,
+                normal: payload.world_normal,
+                beta,
+                albedo: albedo.xyz(),
+                roughness: material.roughness_at(&payload.uv).clamp(0.0, 1.0),
+                transmission: material.transmission.clamp(0.0, 1.0),
+                eta: if payload.front_face {
+                    1.0 / material.ior.at(LAMBDA_RGB)
+                } else {
+                    material.ior.at(LAMBDA_RGB)
+                },
+                emission: material.emission_at(&payload.uv).xyz(),
+                light: self
+                    .object_lights
+                    .get(payload.object_index)
+                    .copied()
+                    .flatten(),
+                ..Vertex::default()
+            };
+            vertex.pdf_forward = convert_density(&path[previous], pdf, &vertex);
+            path.push(vertex);
+
+            let wo = -ray.direction.normalize();
+            if let Some(radiance) = radiance.as_deref_mut() {
+                *radiance += directional_light(scene, &vertex, &wo, ray.time, &mut sampler.seed);
+            }
+            if path.len() >= max_vertices {
+                break;
+            }
+
+            let Some(bounce) = vertex.sample(&wo, sampler) else {
+                break;
+            };
+            beta.component_mul_assign(&bounce.weight);
+            pdf = bounce.pdf;
+            let current = path.len() - 1;
+            path[current].delta = bounce.delta;
+            path[previous].pdf_reverse =
+                convert_density(&path[current], bounce.pdf_reverse, &path[previous]);
+
+            let side = vertex.normal.dot(&bounce.direction).signum();
+            ray = Ray::with_time(
+                vertex.position + vertex.normal * (1e-4 * side),
+                bounce.direction,
+                ray.time,
+            );
+        }
+    }
+
+    /// Light carried by the path made of the first `s` vertices of the light
+    /// path and the first `t` of the camera path, already weighted. The pixel
+    /// is set for connections to the camera, that land on another pixel.
+    #[allow(clippy::too_many_arguments)]
+    fn connect(
+        &self,
+        scene: &Scene,
+        camera: &CameraProjection,
+        light_path: &[Vertex],
+        camera_path: &[Vertex],
+        s: usize,
+        t: usize,
+        time: f64,
+        sampler: &mut Sampler,
+    ) -> Option<(Vector3<f64>, Option<usize>)> {
+        let mut sampled = None;
+        let mut pixel = None;
+        let color = if s == 0 {
+            // the camera path found an emitter by itself
+            let end = &camera_path[t - 1];
+            if end.emission.max() <= 0.0 {
+                return None;
+            }
+            let color = end.emission.component_mul(&end.beta);
+            if end.light.is_none() {
+                // no light path can reach this emitter
+                return Some((color, None));
+            }
+            color
+        } else if t == 1 {
+            // straight from a light path vertex to the camera
+            let end = &light_path[s - 1];
+            if end.delta || end.kind != VertexKind::Surface {
+                return None;
+            }
+            pixel = Some(camera.pixel_of(&end.position)?);
+            let to_camera = camera.position - end.position;
+            let distance_squared = to_camera.norm_squared();
+            let to_camera = to_camera / distance_squared.sqrt();
+            let cos_camera = (-to_camera).dot(&camera.forward_direction);
+            let importance = camera.importance(&-to_camera) * cos_camera / distance_squared;
+            let vertex = Vertex {
+                kind: VertexKind::Camera,
+                position: camera.position,
+                normal: Vector3::zeros(),
+                beta: Vector3::repeat(importance),
+                ..Vertex::default()
+            };
+            sampled = Some(vertex);
+            let f = end.f(&light_path[s - 2], &vertex.position);
+            let color = end.beta.component_mul(&f) * importance * end.normal.dot(&to_camera).abs();
+            if color.max() <= 0.0 || !visible(scene, end, &vertex, time) {
+                return None;
+            }
+            color
+        } else if s == 1 {
+            // next event estimation, a point picked on a light
+            let end = &camera_path[t - 1];
+            if end.delta {
+                return None;
+            }
+            let vertex = self.sample_light(scene, end, time, sampler)?;
+            sampled = Some(vertex);
+            let direction = (vertex.position - end.position).normalize();
+            let f = end.f(&camera_path[t - 2], &vertex.position);
+            let color = end.beta.component_mul(&f).component_mul(&vertex.beta)
+                * end.normal.dot(&direction).abs();
+            if color.max() <= 0.0 || !visible(scene, end, &vertex, time) {
+                return None;
+            }
+            color
+        } else {
+            let light_end = &light_path[s - 1];
+            let camera_end = &camera_path[t - 1];
+            if light_end.delta || camera_end.delta {
+                return None;
+            }
+            let f_light = light_end.f(&light_path[s - 2], &camera_end.position);
+            let f_camera = camera_end.f(&camera_path[t - 2], &light_end.position);
+            let color = light_end
+                .beta
+                .component_mul(&f_light)
+                .component_mul(&f_camera)
+                .component_mul(&camera_end.beta)
+                * geometry(light_end, camera_end);
+            if color.max() <= 0.0 || !visible(scene, light_end, camera_end, time) {
+                return None;
+            }
+            color
+        };
+
+        let weight = self.mis_weight(scene, camera, light_path, camera_path, sampled, s, t);
+        Some((color * weight, pixel))
+    }
+
+    /// Picks a point on one of the lights at `time` as seen from `from`, as
+    /// the light end of a path
+    fn sample_light(
+        &self,
+        scene: &Scene,
+        from: &Vertex,
+        time: f64,
+        sampler: &mut Sampler,
+    ) -> Option<Vertex> {
+        if self.lights.is_empty() {
+            return None;
+        }
+        let index =
+            ((sampler.next_f64() * self.lights.len() as f64) as usize).min(self.lights.len() - 1);
+        let light_pdf = 1.0 / self.lights.len() as f64;
+
+        match &self.lights[index] {
+            PathLight::Area {
+                object, emission, ..
+            } => {
+                let sample = scene.objects[*object]
+                    .shape
+                    .sample(time, &mut sampler.seed)?;
+                let to_light = sample.position - from.position;
+                let distance_squared = to_light.norm_squared();
+                let cos_light = sample.normal.dot(&to_light).abs() / distance_squared.sqrt();
+                if cos_light <= 0.0 {
+                    return None;
+                }
+                // solid angle density of the point as seen from `from`
+                let pdf = sample.pdf * distance_squared / cos_light;
+                Some(Vertex {
+                    kind: VertexKind::Light,
+                    position: sample.position,
+                    normal: sample.normal,
+                    beta: emission / (pdf * light_pdf),
+                    pdf_forward: sample.pdf * light_pdf,
+                    emission: *emission,
+                    light: Some(index),
+                    ..Vertex::default()
+                })
+            }
+            PathLight::Delta { light } => {
+                let light = &scene.lights[*light];
+                let sample = light.illuminate(&from.position)?;
+                Some(Vertex {
+                    kind: VertexKind::Light,
+                    position: from.position + sample.direction * sample.distance,
+                    normal: Vector3::zeros(),
+                    beta: sample.radiance.xyz() / light_pdf,
+                    pdf_forward: light_pdf,
+                    light: Some(index),
+                    ..Vertex::default()
+                })
+            }
+        }
+    }
+
+    /// Balance heuristic weight of the strategy that made the path, found by
+    /// walking its vertices and comparing the densities every other strategy
+    /// would have had
+    #[allow(clippy::too_many_arguments)]
+    fn mis_weight(
+        &self,
+        scene: &Scene,
+        camera: &CameraProjection,
+        light_path: &[Vertex],
+        camera_path: &[Vertex],
+        sampled: Option<Vertex>,
+        s: usize,
+        t: usize,
+    ) -> f64 {
+        if s + t == 2 {
+            return 1.0;
+        }
+
+        let mut light = light_path[..s].to_vec();
+        let mut camera_vertices = camera_path[..t].to_vec();
+        if let Some(vertex) = sampled {
+            if s == 1 {
+                light[0] = vertex;
+            } else if t == 1 {
+                camera_vertices[0] = vertex;
+            }
+        }
+        let cam = &mut camera_vertices;
+
+        // the connected ends were not sampled, so they are never mirrors
+        cam[t - 1].delta = false;
+        if s > 0 {
+            light[s - 1].delta = false;
+        }
+
+        cam[t - 1].pdf_reverse = if s > 0 {
+            let previous = (s > 1).then(|| light[s - 2]);
+            self.pdf(scene, camera, &light[s - 1], previous.as_ref(), &cam[t - 1])
+        } else {
+            self.pdf_light_origin(&cam[t - 1])
+        };
+        if t > 1 {
+            cam[t - 2].pdf_reverse = if s > 0 {
+                self.pdf(scene, camera, &cam[t - 1], Some(&light[s - 1]), &cam[t - 2])
+            } else {
+                self.pdf_light(scene, &cam[t - 1], &cam[t - 2])
+            };
+        }
+        if s > 0 {
+            let previous = (t > 1).then(|| cam[t - 2]);
+            light[s - 1].pdf_reverse =
+                self.pdf(scene, camera, &cam[t - 1], previous.as_ref(), &light[s - 1]);
+        }
+        if s > 1 {
+            light[s - 2].pdf_reverse = self.pdf(
+                scene,
+                camera,
+                &light[s - 1],
+                Some(&cam[t - 1]),
+                &light[s - 2],
+            );
+        }
+
+        let remap = |pdf: f64| if pdf != 0.0 { pdf } else { 1.0 };
+        let mut sum = 0.0;
+        let mut ratio = 1.0;
+        for i in (1..t).rev() {
+            ratio *= remap(cam[i].pdf_reverse) / remap(cam[i].pdf_forward);
+            if !cam[i].delta && !cam[i - 1].delta {
+                sum += ratio;
+            }
+        }
+        ratio = 1.0;
+        for i in (0..s).rev() {
+            ratio *= remap(light[i].pdf_reverse) / remap(light[i].pdf_forward);
+            let delta_before = if i > 0 {
+                light[i - 1].delta
+            } else {
+                self.is_delta_light(&light[0])
+            };
+            if !light[i].delta && !delta_before {
+                sum += ratio;
+            }
+        }
+        1.0 / (1.0 + sum)
+    }
+
+    /// Area density at `next` of a path going through `vertex` from
+    /// `previous`
+    fn pdf(
+        &self,
+        scene: &Scene,
+        camera: &CameraProjection,
+        vertex: &Vertex,
+        previous: Option<&Vertex>,
+        next: &Vertex,
+    ) -> f64 {
+        let pdf = match vertex.kind {
+            VertexKind::Light => return self.pdf_light(scene, vertex, next),
+            VertexKind::Camera => camera.direction_pdf(&(next.position - vertex.position)),
+            VertexKind::Surface => {
+                let Some(previous) = previous else {
+                    return 0.0;
+                };
+                let wo = (previous.position - vertex.position).normalize();
+                let wi = (next.position - vertex.position).normalize();
+                vertex.pdf_direction(&wo, &wi)
+            }
+        };
+        convert_density(vertex, pdf, next)
+    }
+
+    /// Area density at `next` of light leaving the light at `vertex`
+    fn pdf_light(&self, scene: &Scene, vertex: &Vertex, next: &Vertex) -> f64 {
+        let Some(index) = vertex.light else {
+            return 0.0;
+        };
+        let to_next = next.position - vertex.position;
+        let distance_squared = to_next.norm_squared();
+        let direction = to_next / distance_squared.sqrt();
+        let pdf_direction = match &self.lights[index] {
+            PathLight::Area { .. } => vertex.normal.dot(&direction).abs() / TAU,
+            PathLight::Delta { light } => match &scene.lights[*light] {
+                Light::Spot {
+                    direction: axis,
+                    outer_cone_angle,
+                    ..
+                } => {
+                    let cos_outer = outer_cone_angle.cos();
+                    if direction.dot(&axis.normalize()) < cos_outer {
+                        0.0
+                    } else {
+                        1.0 / (TAU * (1.0 - cos_outer))
+                    }
+                }
+                _ => 1.0 / (4.0 * PI),
+            },
+        };
+        let mut pdf = pdf_direction / distance_squared;
+        if next.is_on_surface() {
+            pdf *= next.normal.dot(&direction).abs();
+        }
+        pdf
+    }
+
+    /// Area density of a light path starting at `vertex`
+    fn pdf_light_origin(&self, vertex: &Vertex) -> f64 {
+        let Some(index) = vertex.light else {
+            return 0.0;
+        };
+        let light_pdf = 1.0 / self.lights.len() as f64;
+        match &self.lights[index] {
+            PathLight::Area { pdf_position, .. } => pdf_position * light_pdf,
+            PathLight::Delta { .. } => light_pdf,
+        }
+    }
+
+    fn is_delta_light(&self, vertex: &Vertex) -> bool {
+        vertex
+            .light
+            .is_some_and(|index| matches!(self.lights[index], PathLight::Delta { .. }))
+    }
+}
+
+impl Vertex {
+    fn is_on_surface(&self) -> bool {
+        self.normal != Vector3::zeros()
+    }
+
+    /// Weight of the Lambertian lobe
+    fn diffuse(&self) -> f64 {
+        (1.0 - self.transmission) * self.roughness
+    }
+
+    /// BSDF for light going from `previous` towards `next`, mirrors and
+    /// glass excluded
+    fn f(&self, previous: &Vertex, next: &Vector3<f64>) -> Vector3<f64> {
+        let wo = previous.position - self.position;
+        let wi = next - self.position;
+        if self.normal.dot(&wo) * self.normal.dot(&wi) <= 0.0 {
+            return Vector3::zeros();
+        }
+        self.albedo * (self.diffuse() / PI)
+    }
+
+    /// Solid angle density of bouncing from `wo` towards `wi`
+    fn pdf_direction(&self, wo: &Vector3<f64>, wi: &Vector3<f64>) -> f64 {
+        let cos_theta = self.normal.dot(wi);
+        if self.normal.dot(wo) * cos_theta <= 0.0 {
+            return 0.0;
+        }
+        self.diffuse() * cos_theta.abs() / PI
+    }
+
+    fn sample(&self, wo: &Vector3<f64>, sampler: &mut Sampler) -> Option<Bounce> {
+        let (normal, eta) = if self.normal.dot(wo) < 0.0 {
+            (-self.normal, 1.0 / self.eta)
+        } else {
+            (self.normal, self.eta)
+        };
+        if sampler.next_f64() < self.transmission {
+            let direction = dielectric_direction(&-wo, &normal, eta, sampler);
+            return Some(Bounce {
+                direction,
+                weight: self.albedo,
+                pdf: 0.0,
+                pdf_reverse: 0.0,
+                delta: true,
+            });
+        }
+        if sampler.next_f64() < self.roughness {
+            let direction = sampler.cosine_direction(&normal);
+            Some(Bounce {
+                direction,
+                weight: self.albedo,
+                pdf: self.pdf_direction(wo, &direction),
+                pdf_reverse: self.pdf_direction(&direction, wo),
+                delta: false,
+            })
+        } else {
+            let direction = 2.0 * normal.dot(wo) * normal - wo;
+            Some(Bounce {
+                direction,
+                weight: self.albedo,
+                pdf: 0.0,
+                pdf_reverse: 0.0,
+                delta: true,
+            })
+        }
+    }
+}
+
+impl Default for Vertex {
+    fn default() -> Self {
+        Vertex {
+            kind: VertexKind::Surface,
+            position: Vector3::zeros(),
+            normal: Vector3::zeros(),
+            beta: Vector3::zeros(),
+            delta: false,
+            pdf_forward: 0.0,
+            pdf_reverse: 0.0,
+            albedo: Vector3::zeros(),
+            roughness: 1.0,
+            transmission: 0.0,
+            eta: 1.0,
+            emission: Vector3::zeros(),
+            light: None,
+        }
+    }
+}
+
+/// Turns a solid angle density at `from` into an area density at `to`
+fn convert_density(from: &Vertex, pdf: f64, to: &Vertex) -> f64 {
+    let w = to.position - from.position;
+    let distance_squared = w.norm_squared();
+    let mut pdf = pdf / distance_squared;
+    if to.is_on_surface() {
+        pdf *= to.normal.dot(&(w / distance_squared.sqrt())).abs();
+    }
+    pdf
+}
+
+/// Cosines over squared distance between two vertices
+fn geometry(a: &Vertex, b: &Vertex) -> f64 {
+    let w = b.position - a.position;
+    let distance_squared = w.norm_squared();
+    let w = w / distance_squared.sqrt();
+    let mut g = 1.0 / distance_squared;
+    if a.is_on_surface() {
+        g *= a.normal.dot(&w).abs();
+    }
+    if b.is_on_surface() {
+        g *= b.normal.dot(&w).abs();
+    }
+    g
+}
+
+fn visible(scene: &Scene, a: &Vertex, b: &Vertex, time: f64) -> bool {
+    let offset = |vertex: &Vertex, towards: &Vector3<f64>| {
+        let side = vertex.normal.dot(&(towards - vertex.position)).signum();
+        vertex.position + vertex.normal * (1e-4 * side)
+    };
+    let origin = offset(a, &b.position);
+    let target = offset(b, &a.position);
+    let ray = Ray::with_time(origin, target - origin, time);
+    scene.intersect(&ray, 0.0, 1.0 - 1e-6).is_none()
+}
+
+/// Light from the directional lights reflected at a camera path vertex
+fn directional_light(
+    scene: &Scene,
+    vertex: &Vertex,
+    wo: &Vector3<f64>,
+    time: f64,
+    seed: &mut u32,
+) -> Vector3<f64> {
+    let mut light = Vector3::zeros();
+    if vertex.diffuse() <= 0.0 {
+        return light;
+    }
+    for directional in &scene.lights {
+        if !matches!(directional, Light::Directional { .. }) {
+            continue;
+        }
+        let Some(sample) = directional.illuminate(&vertex.position) else {
+            continue;
+        };
+        let cos_theta = vertex.normal.dot(&sample.direction);
+        if vertex.normal.dot(wo) * cos_theta <= 0.0 {
+            continue;
+        }
+        let origin = vertex.position + vertex.normal * (1e-4 * cos_theta.signum());
+        let Some(transmittance) = shadow(
+            scene,
+            &origin,
+            &sample.direction,
+            sample.distance,
+            time,
+            seed,
+        ) else {
+            continue;
+        };
+        let f = vertex.albedo * (vertex.diffuse() / PI);
+        light += vertex
+            .beta
+            .component_mul(&f)
+            .component_mul(&sample.radiance.component_mul(&transmittance).xyz())
+            * cos_theta.abs();
+    }
+    light
+}
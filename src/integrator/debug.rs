@@ -0,0 +1,91 @@
+use nalgebra::{Vector3, Vector4};
+
+use super::{path::PathTracer, trace_ray, Integrator, Sampler, Splat};
+use crate::{
+    rt::{bvh::take_node_visits, ray::Ray},
+    scene::Scene,
+};
+
+/// Views of what the renderer sees rather than of the light, to track down
+/// problems with the geometry or with performance
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DebugView {
+    /// Shading normals, with each component mapped from [-1, 1] to [0, 1]
+    Normals,
+    /// Distance to the camera, bright up close
+    Depth,
+    /// Texture coordinates in red and green
+    Uv,
+    /// How many times the path tracer's paths bounce, from blue to red
+    BounceCount,
+    /// BVH nodes visited by the camera rays, from blue to red
+    BvhCost,
+}
+
+/// Distance at which the depth view fades to about a third of its brightness
+const DEPTH_SCALE: f64 = 10.0;
+/// Node visits shown in red by the cost view
+const MAX_NODE_VISITS: f64 = 200.0;
+
+impl Integrator for DebugView {
+    fn name(&self) -> &'static str {
+        match self {
+            DebugView::Normals => "normals",
+            DebugView::Depth => "depth",
+            DebugView::Uv => "uv",
+            DebugView::BounceCount => "bounce count",
+            DebugView::BvhCost => "bvh cost",
+        }
+    }
+
+    fn radiance(
+        &self,
+        ray: Ray,
+        scene: &Scene,
+        sampler: &mut Sampler,
+        _splats: &mut Vec<Splat>,
+    ) -> Vector4<f64> {
+        let color = match self {
+            DebugView::BounceCount => {
+                let path_tracer = PathTracer::default();
+                let (_, bounces) = path_tracer.trace(ray, scene, sampler, None);
+                heat(bounces as f64 / path_tracer.bounces as f64)
+            }
+            DebugView::BvhCost => {
+                take_node_visits();
+                trace_ray(&ray, scene);
+                heat(take_node_visits() as f64 / MAX_NODE_VISITS)
+            }
+            _ => {
+                let payload = trace_ray(&ray, scene);
+                if payload.hit_distance == f64::MAX {
+                    Vector3::zeros()
+                } else {
+                    match self {
+                        DebugView::Normals => payload.world_normal.add_scalar(1.0) * 0.5,
+                        DebugView::Depth => {
+                            let depth = payload.hit_distance * ray.direction.norm();
+                            Vector3::repeat((-depth / DEPTH_SCALE).exp())
+                        }
+                        _ => Vector3::new(
+                            payload.uv.x.rem_euclid(1.0),
+                            payload.uv.y.rem_euclid(1.0),
+                            0.0,
+                        ),
+                    }
+                }
+            }
+        };
+        color.insert_row(3, 1.0)
+    }
+}
+
+/// Blue through green to red as `t` goes from 0 to 1
+fn heat(t: f64) -> Vector3<f64> {
+    let t = t.clamp(0.0, 1.0);
+    Vector3::new(
+        (2.0 * t - 1.0).clamp(0.0, 1.0),
+        1.0 - (2.0 * t - 1.0).abs(),
+        (1.0 - 2.0 * t).clamp(0.0, 1.0),
+    )
+}
@@ -0,0 +1,49 @@
+use nalgebra::Vector4;
+
+use super::{
+    albedo, area_light_irradiance, area_lights, direct_light, trace_ray, Integrator, Sampler,
+    Splat, SKY_COLOR,
+};
+use crate::{camera::Camera, rt::ray::Ray, scene::Scene};
+
+/// Emission and the light arriving straight from the scene's lights and
+/// emissive objects at the first surface hit, without any bounce
+#[derive(Default)]
+pub struct DirectLighting {
+    /// Emissive objects whose shape can be sampled
+    area_lights: Vec<usize>,
+}
+
+impl Integrator for DirectLighting {
+    fn name(&self) -> &'static str {
+        "direct lighting"
+    }
+
+    fn prepare(&mut self, scene: &Scene, _camera: &Camera, _frame_index: usize) {
+        self.area_lights = area_lights(scene);
+    }
+
+    fn radiance(
+        &self,
+        ray: Ray,
+        scene: &Scene,
+        sampler: &mut Sampler,
+        _splats: &mut Vec<Splat>,
+    ) -> Vector4<f64> {
+        let payload = trace_ray(&ray, scene);
+        if payload.hit_distance == f64::MAX {
+            return SKY_COLOR;
+        }
+
+        let material = &scene.materials[payload.material_index];
+        // reflected like the light of the scene's lights
+        let area_light =
+            area_light_irradiance(scene, &self.area_lights, &payload, &mut sampler.seed);
+        let light =
+            direct_light(scene, &payload, &mut sampler.seed) + area_light.insert_row(3, 0.0);
+        let mut color =
+            material.emission_at(&payload.uv) + albedo(scene, &payload).component_mul(&light);
+        color.w = 1.0;
+        color
+    }
+}
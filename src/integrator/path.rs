@@ -0,0 +1,168 @@
+use nalgebra::Vector4;
+
+extern crate nalgebra_glm as glm;
+
+use super::{
+    albedo, dielectric, direct_light, medium_direct_light, trace_ray, Integrator, Sampler, Splat,
+    SKY_COLOR,
+};
+use crate::{
+    rt::{
+        ray::Ray,
+        spectrum::{self, Wavelengths, LAMBDA_RGB},
+    },
+    scene::{
+        medium::{self, Collision},
+        Scene,
+    },
+};
+
+/// Follows a single path from the camera, bouncing off surfaces and
+/// scattering in media, adding up what lights it finds along the way
+pub struct PathTracer {
+    pub bounces: u32,
+    /// Trace hero wavelengths instead of RGB, colors being upsampled to
+    /// spectra as the path meets them
+    pub spectral: bool,
+}
+
+impl PathTracer {
+    /// Color along the ray and the number of bounces the path made. With
+    /// `wavelengths` it is the spectrum at them instead, and the secondary
+    /// wavelengths are terminated when the path goes through a dispersive
+    /// surface.
+    pub fn trace(
+        &self,
+        mut ray: Ray,
+        scene: &Scene,
+        sampler: &mut Sampler,
+        mut wavelengths: Option<&mut Wavelengths>,
+    ) -> (Vector4<f64>, u32) {
+        // every term is weighted by the path's contribution as it is added
+        let mut light = Vector4::zeros();
+        let mut contribution = Vector4::new(1.0, 1.0, 1.0, 1.0);
+        let mut bounces = 0;
+
+        for i in 0..self.bounces {
+            let payload = trace_ray(&ray, scene);
+            sampler.seed = sampler.seed.wrapping_add(i);
+
+            let media = scene.media(&ray, payload.hit_distance);
+            let seed = &mut sampler.seed;
+            match medium::sample_collision(&media, &ray, payload.hit_distance, seed) {
+                Collision::Absorbed => break,
+                Collision::Scattered {
+                    distance,
+                    medium,
+                    weight,
+                } => {
+                    bounces += 1;
+                    contribution
+                        .component_mul_assign(&carried(weight.insert_row(3, 1.0), &wavelengths));
+                    let position = ray.at(distance);
+                    let direction = ray.direction.normalize();
+                    let direct =
+                        medium_direct_light(scene, medium, &position, &direction, ray.time, seed);
+                    light += carried(direct, &wavelengths).component_mul(&contribution);
+                    ray.origin = position;
+                    ray.direction = medium.sample_phase(&direction, seed);
+                    continue;
+                }
+                Collision::Passed { weight } => {
+                    contribution
+                        .component_mul_assign(&carried(weight.insert_row(3, 1.0), &wavelengths));
+                }
+            }
+
+            if payload.hit_distance == f64::MAX {
+                light += carried(SKY_COLOR, &wavelengths).component_mul(&contribution);
+                break;
+            }
+            bounces += 1;
+
+            let material = &scene.materials[payload.material_index];
+            light += carried(material.emission_at(&payload.uv), &wavelengths)
+                .component_mul(&contribution);
+            contribution.component_mul_assign(&carried(albedo(scene, &payload), &wavelengths));
+
+            if material.transmission > 0.0 && sampler.next_f64() < material.transmission {
+                let lambda = match wavelengths.as_deref_mut() {
+                    Some(wavelengths) => {
+                        if material.ior.is_dispersive() {
+                            wavelengths.terminate_secondary();
+                        }
+                        wavelengths.hero()
+                    }
+                    None => LAMBDA_RGB,
+                };
+                ray = dielectric(&ray, &payload, material.ior.at(lambda), sampler);
+                continue;
+            }
+
+            let direct = direct_light(scene, &payload, &mut sampler.seed);
+            light += carried(direct, &wavelengths).component_mul(&contribution);
+
+            if i < (self.bounces - 1) {
+                ray.origin = payload.world_position + payload.world_normal * 0.0001;
+
+                ray.direction = glm::reflect_vec(&ray.direction, &(payload.world_normal));
+                let roughness = material.roughness_at(&payload.uv);
+                if roughness > 0.0 {
+                    ray.direction += roughness * sampler.in_unit_cube();
+                }
+            }
+        }
+
+        // RGB paths carry a 1 in the alpha channel, spectra have no room for it
+        if wavelengths.is_none() {
+            light.w = 1.0;
+        }
+        (light, bounces)
+    }
+}
+
+/// An RGB color as the path carries it, as a spectrum with `wavelengths`
+fn carried(rgb: Vector4<f64>, wavelengths: &Option<&mut Wavelengths>) -> Vector4<f64> {
+    match wavelengths {
+        Some(wavelengths) => spectrum::upsample(&rgb, wavelengths),
+        None => rgb,
+    }
+}
+
+impl Default for PathTracer {
+    fn default() -> Self {
+        PathTracer {
+            bounces: 5,
+            spectral: false,
+        }
+    }
+}
+
+impl Integrator for PathTracer {
+    fn name(&self) -> &'static str {
+        if self.spectral {
+            "spectral path tracing"
+        } else {
+            "path tracing"
+        }
+    }
+
+    fn radiance(
+        &self,
+        ray: Ray,
+        scene: &Scene,
+        sampler: &mut Sampler,
+        _splats: &mut Vec<Splat>,
+    ) -> Vector4<f64> {
+        if !self.spectral {
+            return self.trace(ray, scene, sampler, None).0;
+        }
+        let mut wavelengths = Wavelengths::sample(sampler.next_f64());
+        let (spectrum, _) = self.trace(ray, scene, sampler, Some(&mut wavelengths));
+        wavelengths.to_xyz(&spectrum).insert_row(3, 1.0)
+    }
+
+    fn spectral(&self) -> bool {
+        self.spectral
+    }
+}
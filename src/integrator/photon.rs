@@ -0,0 +1,375 @@
+//! Progressive photon mapping. Before every frame, photons are shot from the
+//! lights and kept where they land on rough surfaces. Camera rays follow
+//! mirrors and glass until they reach a rough surface, where the photons
+//! around are gathered. The gather radius shrinks a little every frame
+//! (Knaus and Zwicker's probabilistic formulation), so the average of the
+//! frames converges, caustics included.
+//!
+//! Materials are read as in the bidirectional integrator, so photons are
+//! refracted by glass too. Light coming straight from the lights is sampled
+//! instead of gathered. Directional lights and the sky don't shoot photons,
+//! so they only light surfaces directly.
+
+use std::f64::consts::{PI, TAU};
+
+use nalgebra::{Vector3, Vector4};
+use rayon::prelude::*;
+
+use super::{
+    albedo, area_light_irradiance, area_lights, dielectric, direct_light, trace_ray, Integrator,
+    Sampler, Splat, SKY_COLOR,
+};
+use crate::{
+    camera::Camera,
+    rt::{onb::Onb, ray::Ray, spectrum::LAMBDA_RGB},
+    scene::{Light, Scene},
+    shapes::Param,
+};
+
+/// Photons traced by the same sampler
+const PHOTONS_PER_TASK: usize = 4096;
+
+pub struct PhotonMapper {
+    /// Photons shot every frame
+    pub photons: usize,
+    /// Gather radius of the first frame
+    pub radius: f64,
+    /// Fraction of the photons a frame keeps from the previous one's gather
+    /// area, lower values shrink the radius faster
+    pub alpha: f64,
+    /// Most bounces of photons and of camera paths
+    pub max_depth: usize,
+    frame_radius: f64,
+    /// Emissive objects whose shape can be sampled
+    area_lights: Vec<usize>,
+    map: PhotonMap,
+}
+
+#[derive(Clone, Copy)]
+enum Emitter {
+    Object(usize),
+    Light(usize),
+}
+
+struct Photon {
+    position: Vector3<f64>,
+    /// Normal of the surface, on the side the photon came from
+    normal: Vector3<f64>,
+    power: Vector3<f64>,
+}
+
+/// Hash grid of photons, with cells as large as the gather radius
+#[derive(Default)]
+struct PhotonMap {
+    /// Sorted by bucket
+    photons: Vec<Photon>,
+    cell_size: f64,
+    /// Index of the first photon of every bucket, plus the end
+    buckets: Vec<usize>,
+}
+
+impl Default for PhotonMapper {
+    fn default() -> Self {
+        PhotonMapper {
+            photons: 100_000,
+            radius: 0.1,
+            alpha: 0.7,
+            max_depth: 5,
+            frame_radius: 0.1,
+            area_lights: Vec::new(),
+            map: PhotonMap::default(),
+        }
+    }
+}
+
+impl Integrator for PhotonMapper {
+    fn name(&self) -> &'static str {
+        "photon mapping"
+    }
+
+    fn prepare(&mut self, scene: &Scene, camera: &Camera, frame_index: usize) {
+        // from the first frame rather than the last one, so that renders
+        // resumed from a checkpoint shrink it the same way
+        self.frame_radius = (1..frame_index.max(1)).fold(self.radius, |radius, i| {
+            let i = i as f64;
+            radius * ((i + self.alpha) / (i + 1.0)).sqrt()
+        });
+
+        self.area_lights = area_lights(scene);
+
+        let emitters: Vec<Emitter> = self
+            .area_lights
+            .iter()
+            .map(|&object| Emitter::Object(object))
+            .chain(
+                scene
+                    .lights
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, light)| matches!(light, Light::Point { .. } | Light::Spot { .. }))
+                    .map(|(i, _)| Emitter::Light(i)),
+            )
+            .collect();
+        if emitters.is_empty() {
+            self.map = PhotonMap::default();
+            return;
+        }
+
+        let tasks = self.photons.div_ceil(PHOTONS_PER_TASK);
+        let photons = (0..tasks)
+            .into_par_iter()
+            .flat_map_iter(|task| {
+                let seed = (task as u32 + 1)
+                    .wrapping_mul(0x9e37_79b9)
+                    .wrapping_add(frame_index as u32);
+                let mut sampler = Sampler::new(seed, false);
+                let count = PHOTONS_PER_TASK.min(self.photons - task * PHOTONS_PER_TASK);
+                let mut photons = Vec::new();
+                for _ in 0..count {
+                    self.trace_photon(scene, camera, &emitters, &mut sampler, &mut photons);
+                }
+                photons
+            })
+            .collect();
+        self.map = PhotonMap::new(photons, self.frame_radius);
+    }
+
+    fn radiance(
+        &self,
+        mut ray: Ray,
+        scene: &Scene,
+        sampler: &mut Sampler,
+        _splats: &mut Vec<Splat>,
+    ) -> Vector4<f64> {
+        let mut radiance = Vector3::zeros();
+        let mut beta = Vector3::repeat(1.0);
+
+        for _ in 0..=self.max_depth {
+            let payload = trace_ray(&ray, scene);
+            if payload.hit_distance == f64::MAX {
+                radiance += beta.component_mul(&SKY_COLOR.xyz());
+                break;
+            }
+
+            let material = &scene.materials[payload.material_index];
+            radiance += beta.component_mul(&material.emission_at(&payload.uv).xyz());
+            beta.component_mul_assign(&albedo(scene, &payload).xyz());
+
+            if material.transmission > 0.0 && sampler.next_f64() < material.transmission {
+                ray = dielectric(&ray, &payload, material.ior.at(LAMBDA_RGB), sampler);
+                continue;
+            }
+            if sampler.next_f64() < material.roughness_at(&payload.uv) {
+                let irradiance = self.direct_irradiance(scene, &payload, sampler)
+                    + self.map.irradiance(
+                        &payload.world_position,
+                        &payload.world_normal,
+                        self.frame_radius,
+                    );
+                radiance += beta.component_mul(&irradiance) / PI;
+                break;
+            }
+
+            let normal = payload.world_normal;
+            ray = Ray::with_time(
+                payload.world_position + normal * 0.0001,
+                ray.direction - 2.0 * ray.direction.dot(&normal) * normal,
+                ray.time,
+            );
+        }
+
+        radiance.insert_row(3, 1.0)
+    }
+
+    fn params(&self) -> Vec<(&'static str, Param)> {
+        vec![
+            ("photons", Param::Count(self.photons)),
+            ("radius", Param::Length(self.radius)),
+            ("alpha", Param::Fraction(self.alpha)),
+        ]
+    }
+
+    fn set_param(&mut self, name: &str, value: Param) -> bool {
+        match (name, value) {
+            ("photons", Param::Count(photons)) => self.photons = photons,
+            ("radius", Param::Length(radius)) => self.radius = radius,
+            ("alpha", Param::Fraction(alpha)) => self.alpha = alpha,
+            _ => return false,
+        }
+        true
+    }
+}
+
+impl PhotonMapper {
+    /// Shoots a photon from one of the emitters and keeps it everywhere it
+    /// bounces off a rough surface, but where it lands first
+    fn trace_photon(
+        &self,
+        scene: &Scene,
+        camera: &Camera,
+        emitters: &[Emitter],
+        sampler: &mut Sampler,
+        photons: &mut Vec<Photon>,
+    ) -> Option<()> {
+        let index = ((sampler.next_f64() * emitters.len() as f64) as usize).min(emitters.len() - 1);
+        // flux carried by every photon shot from the emitter
+        let scale = emitters.len() as f64 / self.photons as f64;
+        let time = camera.shutter_time(sampler.next_f64());
+
+        let (origin, direction, mut power) = match emitters[index] {
+            Emitter::Object(object) => {
+                let object = &scene.objects[object];
+                let emission = scene.materials[object.material_index].get_emission().xyz();
+                let sample = object.shape.sample(time, &mut sampler.seed)?;
+                // both sides emit, so half of the directions go each way
+                let side = if sampler.next_f64() < 0.5 { 1.0 } else { -1.0 };
+                let normal = sample.normal * side;
+                let direction = sampler.cosine_direction(&normal);
+                let power = emission * (TAU / sample.pdf);
+                (sample.position + normal * 0.0001, direction, power)
+            }
+            Emitter::Light(light) => {
+                let light = &scene.lights[light];
+                let (position, direction, pdf) = match light {
+                    Light::Point { position, .. } => {
+                        (*position, sampler.unit_direction(), 1.0 / (4.0 * PI))
+                    }
+                    Light::Spot {
+                        position,
+                        direction,
+                        outer_cone_angle,
+                        ..
+                    } => {
+                        let cos_outer = outer_cone_angle.cos();
+                        let cos_theta = 1.0 - sampler.next_f64() * (1.0 - cos_outer);
+                        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+                        let phi = TAU * sampler.next_f64();
+                        let local =
+                            Vector3::new(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta);
+                        let sampled = Onb::from_w(direction).to_world(&local);
+                        (*position, sampled, 1.0 / (TAU * (1.0 - cos_outer)))
+                    }
+                    Light::Directional { .. } => return None,
+                };
+                let intensity = light.illuminate(&(position + direction))?.radiance.xyz();
+                (position, direction, intensity / pdf)
+            }
+        };
+        power *= scale;
+
+        let mut ray = Ray::with_time(origin, direction, time);
+        for depth in 0..self.max_depth {
+            let payload = trace_ray(&ray, scene);
+            if payload.hit_distance == f64::MAX {
+                break;
+            }
+
+            let material = &scene.materials[payload.material_index];
+            let roughness = material.roughness_at(&payload.uv);
+            let normal = payload.world_normal;
+            // only surfaces that aren't fully transmissive gather photons
+            if depth > 0 && roughness > 0.0 && material.transmission < 1.0 {
+                photons.push(Photon {
+                    position: payload.world_position,
+                    normal,
+                    power,
+                });
+            }
+
+            power.component_mul_assign(&albedo(scene, &payload).xyz());
+            if material.transmission > 0.0 && sampler.next_f64() < material.transmission {
+                ray = dielectric(&ray, &payload, material.ior.at(LAMBDA_RGB), sampler);
+                continue;
+            }
+            let direction = if sampler.next_f64() < roughness {
+                sampler.cosine_direction(&normal)
+            } else {
+                ray.direction - 2.0 * ray.direction.dot(&normal) * normal
+            };
+            ray = Ray::with_time(payload.world_position + normal * 0.0001, direction, time);
+        }
+        Some(())
+    }
+
+    /// Irradiance straight from the lights, with one point sampled on every
+    /// emissive object and one direction towards the sky
+    fn direct_irradiance(
+        &self,
+        scene: &Scene,
+        payload: &super::HitPayload,
+        sampler: &mut Sampler,
+    ) -> Vector3<f64> {
+        let mut irradiance = direct_light(scene, payload, &mut sampler.seed).xyz();
+        let origin = payload.world_position + payload.world_normal * 0.0001;
+
+        let direction = sampler.cosine_direction(&payload.world_normal);
+        let sky_ray = Ray::with_time(origin, direction, payload.time);
+        if scene.intersect(&sky_ray, 0.0, f64::MAX).is_none() {
+            irradiance += SKY_COLOR.xyz() * PI;
+        }
+        irradiance + area_light_irradiance(scene, &self.area_lights, payload, &mut sampler.seed)
+    }
+}
+
+impl PhotonMap {
+    fn new(mut photons: Vec<Photon>, radius: f64) -> PhotonMap {
+        let mut map = PhotonMap {
+            photons: Vec::new(),
+            cell_size: radius,
+            buckets: vec![0; photons.len().next_power_of_two() + 1],
+        };
+        photons.sort_unstable_by_key(|photon| map.bucket(map.cell(&photon.position)));
+        for photon in &photons {
+            let bucket = map.bucket(map.cell(&photon.position));
+            map.buckets[bucket + 1] += 1;
+        }
+        for i in 1..map.buckets.len() {
+            map.buckets[i] += map.buckets[i - 1];
+        }
+        map.photons = photons;
+        map
+    }
+
+    fn cell(&self, point: &Vector3<f64>) -> [i64; 3] {
+        [point.x, point.y, point.z].map(|x| (x / self.cell_size).floor() as i64)
+    }
+
+    fn bucket(&self, [x, y, z]: [i64; 3]) -> usize {
+        let hash =
+            x.wrapping_mul(73_856_093) ^ y.wrapping_mul(19_349_663) ^ z.wrapping_mul(83_492_791);
+        hash as usize & (self.buckets.len() - 2)
+    }
+
+    /// Density estimate of the irradiance from the photons within `radius`
+    /// that came from the side `normal` faces
+    fn irradiance(&self, point: &Vector3<f64>, normal: &Vector3<f64>, radius: f64) -> Vector3<f64> {
+        if self.photons.is_empty() {
+            return Vector3::zeros();
+        }
+
+        // neighbouring cells can share a bucket, which must only be read once
+        let [x, y, z] = self.cell(point);
+        let mut buckets = Vec::with_capacity(27);
+        for dz in -1..=1 {
+            for dy in -1..=1 {
+                for dx in -1..=1 {
+                    buckets.push(self.bucket([x + dx, y + dy, z + dz]));
+                }
+            }
+        }
+        buckets.sort_unstable();
+        buckets.dedup();
+
+        let mut power = Vector3::zeros();
+        for bucket in buckets {
+            for photon in &self.photons[self.buckets[bucket]..self.buckets[bucket + 1]] {
+                if (photon.position - point).norm_squared() < radius * radius
+                    && photon.normal.dot(normal) > 0.0
+                {
+                    power += photon.power;
+                }
+            }
+        }
+        power / (PI * radius * radius)
+    }
+}
@@ -0,0 +1,71 @@
+use nalgebra::Vector4;
+
+extern crate nalgebra_glm as glm;
+
+use super::{albedo, dielectric, direct_light, trace_ray, Integrator, Sampler, Splat, SKY_COLOR};
+use crate::{
+    rt::{ray::Ray, spectrum::LAMBDA_RGB},
+    scene::Scene,
+};
+
+/// Classic recursive ray tracing: direct light from the scene's lights and
+/// perfect mirror reflections, mixed by the roughness of the surface, and
+/// refractions through transmissive surfaces
+pub struct Whitted {
+    pub depth: u32,
+}
+
+impl Default for Whitted {
+    fn default() -> Self {
+        Whitted { depth: 5 }
+    }
+}
+
+impl Integrator for Whitted {
+    fn name(&self) -> &'static str {
+        "whitted"
+    }
+
+    fn radiance(
+        &self,
+        mut ray: Ray,
+        scene: &Scene,
+        sampler: &mut Sampler,
+        _splats: &mut Vec<Splat>,
+    ) -> Vector4<f64> {
+        let mut color = Vector4::zeros();
+        let mut weight = Vector4::new(1.0, 1.0, 1.0, 1.0);
+
+        for _ in 0..self.depth {
+            let payload = trace_ray(&ray, scene);
+            if payload.hit_distance == f64::MAX {
+                color += SKY_COLOR.component_mul(&weight);
+                break;
+            }
+
+            let material = &scene.materials[payload.material_index];
+            let albedo = albedo(scene, &payload);
+            if material.transmission > 0.0 && sampler.next_f64() < material.transmission {
+                color += material.emission_at(&payload.uv).component_mul(&weight);
+                weight.component_mul_assign(&albedo);
+                ray = dielectric(&ray, &payload, material.ior.at(LAMBDA_RGB), sampler);
+                continue;
+            }
+
+            let roughness = material.roughness_at(&payload.uv).clamp(0.0, 1.0);
+            let diffuse = direct_light(scene, &payload, &mut sampler.seed).component_mul(&albedo);
+            color +=
+                (material.emission_at(&payload.uv) + diffuse * roughness).component_mul(&weight);
+
+            weight = weight.component_mul(&albedo) * (1.0 - roughness);
+            if weight.xyz().max() < 1e-3 {
+                break;
+            }
+            ray.origin = payload.world_position + payload.world_normal * 0.0001;
+            ray.direction = glm::reflect_vec(&ray.direction, &payload.world_normal);
+        }
+
+        color.w = 1.0;
+        color
+    }
+}
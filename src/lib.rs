@@ -1,5 +1,11 @@
 pub mod camera;
+pub mod ffi;
+pub mod integrator;
+#[cfg(feature = "python")]
+mod python;
 pub mod random;
+pub mod remote;
 pub mod renderer;
 pub mod rt;
 pub mod scene;
+pub mod shapes;
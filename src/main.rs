@@ -1,10 +1,18 @@
 extern crate nalgebra_glm as glm;
 use nalgebra::{Vector2, Vector4};
 use raytracing::camera::Camera;
+use raytracing::integrator::IntegratorKind;
+use raytracing::remote;
+use raytracing::renderer::checkpoint::Checkpoint;
+use raytracing::renderer::turntable::TurntableJob;
 use raytracing::renderer::{Canvas, RaytracingRenderer, RendererSettings, State};
-use raytracing::scene::{self, Material, Scene, Sphere};
+use raytracing::scene::{
+    Capsule, Cone, Cuboid, Cylinder, Disk, Ior, Material, Object, Plane, Rect, Scene, Sphere, Torus,
+};
+use raytracing::shapes::{Param, Shape};
 use std::error::Error;
 use std::io::Read;
+use std::path::Path;
 use std::{fs::File, io::Write, time::Instant};
 use winit::dpi::PhysicalPosition;
 use winit::event::ElementState::{Pressed, Released};
@@ -22,6 +30,11 @@ use imgui::*;
 
 static DEFAULT_WIDTH: u32 = 400;
 static DEFAULT_HEIGHT: u32 = 400;
+static CHECKPOINT_PATH: &str = "checkpoint.bin";
+/// Address to listen for remote control commands on, see `remote`
+static REMOTE_VARIABLE: &str = "RAYTRACING_REMOTE";
+/// Seconds between checkpoints of the accumulation
+static CHECKPOINT_INTERVAL: u64 = 60;
 
 use raytracing::rt::color::write_color;
 
@@ -50,20 +63,7 @@ fn load_state() -> Result<State, Box<dyn Error>> {
     Ok(state)
 }
 
-fn main() {
-    let mut state = match load_state() {
-        Ok(content) => content,
-        Err(err) => {
-            eprintln!("Failed to read state file: {}", err);
-            State {
-                error_msg: format!("Failed to read state file: {}\nusing default settings", err),
-                ..State::default()
-            }
-        }
-    };
-
-    let mut camera = Camera::new(45.0, 0.1, 100.0);
-
+fn demo_scene() -> Scene {
     let pink_sphere = Material {
         albedo: Vector4::new(1.0, 0.0, 1.0, 1.0),
         roughness: 0.0,
@@ -82,27 +82,43 @@ fn main() {
         ..Default::default()
     };
 
-    let mut scene = scene::Scene {
-        spheres: vec![
-            Sphere {
-                position: glm::vec3(-2.0, 0.0, 0.0),
-                radius: 1.0,
-                material_index: 0,
-            },
-            Sphere {
-                position: glm::vec3(0.0, -101.0, 0.0),
-                radius: 100.0,
-                material_index: 1,
-            },
-            Sphere {
-                position: glm::vec3(2.0, 0.0, 0.0),
-                radius: 1.0,
-                material_index: 2,
-            },
-        ],
-        materials: vec![pink_sphere, blue_sphere, orange_sphere],
+    let mut scene = Scene::new(vec![pink_sphere, blue_sphere, orange_sphere]);
+    scene.add(Sphere::new(glm::vec3(-2.0, 0.0, 0.0), 1.0), 0);
+    scene.add(
+        Plane::new(glm::vec3(0.0, -1.0, 0.0), glm::vec3(0.0, 1.0, 0.0)),
+        1,
+    );
+    scene.add(Sphere::new(glm::vec3(2.0, 0.0, 0.0), 1.0), 2);
+    scene.update();
+    scene
+}
+
+fn main() {
+    let mut state = match load_state() {
+        Ok(content) => content,
+        Err(err) => {
+            eprintln!("Failed to read state file: {}", err);
+            State {
+                error_msg: format!("Failed to read state file: {}\nusing default settings", err),
+                ..State::default()
+            }
+        }
     };
 
+    let mut camera = Camera::new(45.0, 0.1, 100.0);
+
+    let mut scene = match std::env::args().nth(1) {
+        Some(path) => Scene::load(&path).unwrap_or_else(|err| {
+            eprintln!("Failed to load scene '{}': {}", path, err);
+            state.error_msg = format!("Failed to load scene '{}': {}", path, err);
+            demo_scene()
+        }),
+        None => demo_scene(),
+    };
+    if let Some(pose) = &scene.camera {
+        camera.set_pose(pose);
+    }
+
     let (event_loop, window) = utils::create_window("Custom textures", glutin::GlRequest::Latest);
     let (mut winit_platform, mut imgui_context) = utils::imgui_init(&window);
     let gl = utils::glow_context(&window);
@@ -117,8 +133,23 @@ fn main() {
     let mut ig_renderer = Renderer::initialize(&gl, &mut imgui_context, &mut textures, false)
         .expect("failed to create renderer");
     let mut textures_ui = Program::new();
+    textures_ui.renderer.set_integrator(state.integrator);
+    if Path::new(CHECKPOINT_PATH).exists() {
+        if let Err(err) = textures_ui.resume_checkpoint(&mut state, &scene, &mut camera) {
+            state.error_msg = format!("Not resuming '{}': {}", CHECKPOINT_PATH, err);
+        }
+    }
+
+    let remote = std::env::var(REMOTE_VARIABLE).ok().and_then(|address| {
+        remote::listen(&address)
+            .map_err(|err| {
+                state.error_msg = format!("Failed to listen on '{}': {}", address, err);
+            })
+            .ok()
+    });
 
     let mut last_frame = Instant::now();
+    let mut last_checkpoint = Instant::now();
     let mut where_mouse_clicked = PhysicalPosition::new(200, 200);
     event_loop.run(move |event, _, control_flow| {
         // Note we can potentially make the loop more efficient by
@@ -162,7 +193,14 @@ fn main() {
                         .expect("Failed to set cursor position");
                 }
 
-                textures_ui.show(ui, &mut state, &mut scene);
+                textures_ui.show(ui, &mut state, &mut scene, &mut camera);
+
+                for message in remote.iter().flat_map(|remote| remote.try_iter()) {
+                    let renderer = &mut textures_ui.renderer;
+                    let answer = remote::apply(message.command, &mut scene, &mut camera, renderer);
+                    let _ = message.reply.send(answer);
+                    state.integrator = renderer.integrator_kind();
+                }
 
                 textures_ui
                     .renderer
@@ -170,6 +208,12 @@ fn main() {
                 camera.on_resize(state.canvas_width, state.canvas_height);
                 textures_ui.renderer.settings.use_threads = state.use_threads;
                 state.last_render_time = textures_ui.renderer.render(&scene, &camera);
+                if last_checkpoint.elapsed().as_secs() >= CHECKPOINT_INTERVAL {
+                    last_checkpoint = Instant::now();
+                    if let Err(err) = textures_ui.save_checkpoint(&scene, &camera) {
+                        state.error_msg = format!("Failed saving the checkpoint: {}", err);
+                    }
+                }
 
                 let texture = Program::new_texture(&textures_ui.renderer.canvas, &state, &gl);
                 textures_ui.prepare_texture(texture, &mut textures, &gl);
@@ -186,6 +230,9 @@ fn main() {
                 event: glutin::event::WindowEvent::CloseRequested,
                 ..
             } => {
+                if let Err(err) = textures_ui.save_checkpoint(&scene, &camera) {
+                    eprintln!("Failed saving the checkpoint: {}", err);
+                }
                 *control_flow = glutin::event_loop::ControlFlow::Exit;
             }
             glutin::event::Event::WindowEvent {
@@ -235,11 +282,103 @@ fn main() {
     });
 }
 
+const SHAPE_NAMES: [&str; 9] = [
+    "sphere",
+    "plane",
+    "disk",
+    "rectangle",
+    "box",
+    "cylinder",
+    "cone",
+    "capsule",
+    "torus",
+];
+
+fn new_shape(name: &str) -> Box<dyn Shape> {
+    match name {
+        "plane" => Box::<Plane>::default(),
+        "disk" => Box::<Disk>::default(),
+        "rectangle" => Box::<Rect>::default(),
+        "box" => Box::<Cuboid>::default(),
+        "cylinder" => Box::<Cylinder>::default(),
+        "cone" => Box::<Cone>::default(),
+        "capsule" => Box::<Capsule>::default(),
+        "torus" => Box::<Torus>::default(),
+        _ => Box::<Sphere>::default(),
+    }
+}
+
+/// Draws a widget for every parameter of the shape, true if any was changed
+fn edit_shape(ui: &imgui::Ui, shape: &mut dyn Shape) -> bool {
+    edit_params(ui, shape.params(), |name, value| {
+        shape.set_param(name, value)
+    })
+}
+
+/// Draws a widget for every parameter, handing the edited ones to `set`.
+/// True if any was changed.
+fn edit_params(
+    ui: &imgui::Ui,
+    params: Vec<(&'static str, Param)>,
+    mut set: impl FnMut(&str, Param) -> bool,
+) -> bool {
+    let mut edited = false;
+    for (name, param) in params {
+        let changed = match param {
+            Param::Point(mut point) => Drag::new(name)
+                .range(-100.0, 100.0)
+                .speed(0.1)
+                .build_array(ui, point.as_mut_slice())
+                .then_some(Param::Point(point)),
+            Param::Direction(mut direction) => Drag::new(name)
+                .range(-1.0, 1.0)
+                .speed(0.01)
+                .build_array(ui, direction.as_mut_slice())
+                .then_some(Param::Direction(direction)),
+            Param::Length(mut length) => Drag::new(name)
+                .range(0.01, 100.0)
+                .speed(0.05)
+                .build(ui, &mut length)
+                .then_some(Param::Length(length)),
+            Param::Flag(mut flag) => ui.checkbox(name, &mut flag).then_some(Param::Flag(flag)),
+            Param::Rotation(mut angles) => Drag::new(name)
+                .range(-180.0, 180.0)
+                .speed(0.5)
+                .build_array(ui, angles.as_mut_slice())
+                .then_some(Param::Rotation(angles)),
+            Param::Scale(mut scale) => Drag::new(name)
+                .range(0.01, 100.0)
+                .speed(0.01)
+                .build_array(ui, scale.as_mut_slice())
+                .then_some(Param::Scale(scale)),
+            Param::Count(count) => {
+                let mut count = count as u32;
+                Drag::new(name)
+                    .range(1, 10_000_000)
+                    .speed(1000.0)
+                    .build(ui, &mut count)
+                    .then_some(Param::Count(count as usize))
+            }
+            Param::Fraction(mut fraction) => ui
+                .slider(name, 0.0, 1.0, &mut fraction)
+                .then_some(Param::Fraction(fraction)),
+        };
+        if let Some(value) = changed {
+            edited |= set(name, value);
+        }
+    }
+    edited
+}
+
 struct Program {
     generated_texture: Option<imgui::TextureId>,
     renderer: RaytracingRenderer,
     viewport_width: u32,
     viewport_height: u32,
+    new_shape: usize,
+    /// Shown time of the scene's animation
+    animation_time: f64,
+    turntable_job: Option<TurntableJob>,
 }
 
 impl Program {
@@ -256,6 +395,9 @@ impl Program {
             generated_texture: None,
             viewport_width: 100,
             viewport_height: 100,
+            new_shape: 0,
+            animation_time: 0.0,
+            turntable_job: None,
             renderer,
         }
     }
@@ -304,8 +446,93 @@ impl Program {
         gl_texture
     }
 
-    fn show(&mut self, ui: &imgui::Ui, state: &mut State, scene: &mut Scene) {
+    /// Saves the accumulation, unless there is nothing accumulated yet
+    fn save_checkpoint(&self, scene: &Scene, camera: &Camera) -> Result<(), Box<dyn Error>> {
+        if self.renderer.frame_index() <= 1 || !self.renderer.settings.accumulate {
+            return Ok(());
+        }
+        self.renderer
+            .checkpoint(scene, camera)
+            .save(CHECKPOINT_PATH)
+    }
+
+    /// Goes on with the accumulation of the checkpoint file, at its size
+    fn resume_checkpoint(
+        &mut self,
+        state: &mut State,
+        scene: &Scene,
+        camera: &mut Camera,
+    ) -> Result<(), Box<dyn Error>> {
+        let checkpoint = Checkpoint::load(CHECKPOINT_PATH)?;
+        state.canvas_width = checkpoint.width;
+        state.canvas_height = checkpoint.height;
+        self.renderer.on_resize(checkpoint.width, checkpoint.height);
+        camera.on_resize(checkpoint.width, checkpoint.height);
+        self.renderer.resume(checkpoint, scene, camera)
+    }
+
+    /// Renders a bit more of the turntable, every frame whatever the user
+    /// interface shows, and saves it once done
+    fn step_turntable(&mut self, state: &mut State, scene: &Scene) {
+        let Some(job) = &mut self.turntable_job else {
+            return;
+        };
+        job.step(scene);
+        if job.is_done() {
+            let job = self.turntable_job.take().expect("job is running");
+            if let Err(err) = job.finish(scene) {
+                state.error_msg = format!("Failed saving the turntable: {}", err);
+            }
+        }
+    }
+
+    /// Turntable settings, and the progress of the one being rendered
+    fn edit_turntable(&mut self, ui: &imgui::Ui, state: &mut State, camera: &Camera) {
+        let _turntable = ui.push_id("turntable");
+        if let Some(job) = &self.turntable_job {
+            ProgressBar::new(job.progress() as f32).build(ui);
+            if ui.button("Cancel") {
+                self.turntable_job = None;
+            }
+            return;
+        }
+
+        let turntable = &mut state.turntable;
+        Drag::new("target")
+            .speed(0.1)
+            .build_array(ui, turntable.target.as_mut_slice());
+        Drag::new("radius")
+            .range(0.01, 1000.0)
+            .speed(0.1)
+            .build(ui, &mut turntable.radius);
+        Drag::new("elevation")
+            .range(-89.0, 89.0)
+            .speed(1.0)
+            .build(ui, &mut turntable.elevation);
+        Drag::new("frames")
+            .range(1, 1000)
+            .build(ui, &mut turntable.frames);
+        Drag::new("samples")
+            .range(1, 10000)
+            .build(ui, &mut turntable.samples);
+        Drag::new("fps")
+            .range(1.0, 60.0)
+            .speed(0.1)
+            .build(ui, &mut turntable.fps);
+        turntable.width = state.canvas_width;
+        turntable.height = state.canvas_height;
+        ui.input_text("file", &mut turntable.path).build();
+        if ui.button("Render turntable") {
+            match TurntableJob::new(turntable.clone(), state.integrator, camera.pose()) {
+                Ok(job) => self.turntable_job = Some(job),
+                Err(err) => state.error_msg = format!("Can't render the turntable: {}", err),
+            }
+        }
+    }
+
+    fn show(&mut self, ui: &imgui::Ui, state: &mut State, scene: &mut Scene, camera: &mut Camera) {
         ui.dockspace_over_main_viewport();
+        self.step_turntable(state, scene);
 
         ui.window("Settings")
             .size([400.0, 400.0], Condition::FirstUseEver)
@@ -322,10 +549,41 @@ impl Program {
                 ui.checkbox("Accummulate", &mut self.renderer.settings.accumulate);
                 ui.checkbox("Slow random", &mut self.renderer.settings.slow_random);
 
+                let mut integrator = IntegratorKind::ALL
+                    .iter()
+                    .position(|&kind| kind == state.integrator)
+                    .unwrap_or(0);
+                if ui.combo(
+                    "Integrator",
+                    &mut integrator,
+                    &IntegratorKind::ALL,
+                    |kind| kind.name().into(),
+                ) {
+                    state.integrator = IntegratorKind::ALL[integrator];
+                    self.renderer.set_integrator(state.integrator);
+                }
+                let integrator = &mut self.renderer.integrator;
+                if edit_params(ui, integrator.params(), |name, value| {
+                    integrator.set_param(name, value)
+                }) {
+                    self.renderer.reset_frame_index();
+                }
+
                 if ui.button("Reset") {
                     self.renderer.reset_frame_index();
                 }
 
+                if let Some(animation) = &scene.animation {
+                    let moves_camera = animation.moves_camera();
+                    if ui.slider("Time", 0.0, animation.duration(), &mut self.animation_time) {
+                        scene.animate(self.animation_time);
+                        if let (true, Some(pose)) = (moves_camera, &scene.camera) {
+                            camera.set_pose(pose);
+                        }
+                        self.renderer.reset_frame_index();
+                    }
+                }
+
                 Drag::new("Canvas width")
                     .range(20, self.viewport_width)
                     .speed(1.0)
@@ -340,6 +598,22 @@ impl Program {
                     save_ppm(&mut file, &self.renderer.canvas);
                 }
 
+                if ui.button("Save checkpoint") {
+                    if let Err(err) = self.save_checkpoint(scene, camera) {
+                        state.error_msg = format!("Failed saving the checkpoint: {}", err);
+                    }
+                }
+                ui.same_line();
+                if ui.button("Resume checkpoint") {
+                    if let Err(err) = self.resume_checkpoint(state, scene, camera) {
+                        state.error_msg = format!("Not resuming '{}': {}", CHECKPOINT_PATH, err);
+                    }
+                }
+
+                if ui.collapsing_header("Turntable", TreeNodeFlags::empty()) {
+                    self.edit_turntable(ui, state, camera);
+                }
+
                 if ui.button("Save Settings") {
                     if let Err(err) = save_state(state) {
                         state.error_msg = format!("Failed saving state: {:?}", err);
@@ -351,29 +625,41 @@ impl Program {
                 }
             });
 
+        let mut geometry_changed = false;
         ui.window("Scene").build(|| {
-            scene
-                .spheres
-                .iter_mut()
-                .enumerate()
-                .for_each(|(i, sphere)| {
-                    let token = ui.push_id(i.to_string());
-                    Drag::new("position")
-                        .range(-100.0, 100.0)
-                        .speed(0.1)
-                        .build_array(ui, sphere.position.as_mut_slice());
-                    Drag::new("radius")
-                        .range(0.1, 100.0)
-                        .speed(0.1)
-                        .build(ui, &mut sphere.radius);
-                    Drag::new("material")
-                        .range(0, scene.materials.len() - 1)
-                        .speed(1.0)
-                        .build(ui, &mut sphere.material_index);
-                    ui.separator();
-                    token.pop();
-                });
+            if ui.collapsing_header("Objects", TreeNodeFlags::DEFAULT_OPEN) {
+                let _objects = ui.push_id("objects");
+                let material_count = scene.materials.len();
+                scene
+                    .objects
+                    .iter_mut()
+                    .enumerate()
+                    .for_each(|(i, object)| {
+                        let token = ui.push_id(i.to_string());
+                        ui.text(format!("{} #{}", object.shape.name(), i));
+                        geometry_changed |= edit_shape(ui, object.shape.as_mut());
+                        Drag::new("material")
+                            .range(0, material_count - 1)
+                            .speed(1.0)
+                            .build(ui, &mut object.material_index);
+                        ui.separator();
+                        token.pop();
+                    });
+
+                ui.combo_simple_string("##new shape", &mut self.new_shape, &SHAPE_NAMES);
+                ui.same_line();
+                if ui.button("Add") {
+                    scene.objects.push(Object {
+                        shape: new_shape(SHAPE_NAMES[self.new_shape]),
+                        material_index: 0,
+                    });
+                }
+            }
 
+            if !ui.collapsing_header("Materials", TreeNodeFlags::DEFAULT_OPEN) {
+                return;
+            }
+            let _materials = ui.push_id("materials");
             scene
                 .materials
                 .iter_mut()
@@ -404,10 +690,23 @@ impl Program {
                         .speed(0.05)
                         .range(0.0, 1.0)
                         .build(ui, &mut material.metallic);
+                    Drag::new("transmission")
+                        .speed(0.05)
+                        .range(0.0, 1.0)
+                        .build(ui, &mut material.transmission);
+                    if let Ior::Constant { value } = &mut material.ior {
+                        Drag::new("ior")
+                            .speed(0.01)
+                            .range(1.0, 3.0)
+                            .build(ui, value);
+                    }
                     ui.separator();
                     token.pop();
                 });
         });
+        if geometry_changed {
+            scene.update();
+        }
 
         let token = ui.push_style_var(StyleVar::WindowPadding([0.0, 0.0]));
         ui.window("Viewport")
@@ -0,0 +1,462 @@
+//! Python extension module, built with `maturin build --features python`
+//!
+//! ```python
+//! import raytracing as rt
+//!
+//! scene = rt.Scene()
+//! red = scene.add_material(rt.Material(albedo=(1, 0, 0), roughness=0.4))
+//! scene.add_sphere(rt.Sphere((0, 0, 0), 1.0), red)
+//! camera = rt.Camera(position=(0, 0, 6), forward_direction=(0, 0, -1))
+//! linear = rt.render(scene, camera, 320, 240, samples=16)  # float32, (240, 320, 4)
+//! shown = rt.render(scene, camera, 320, 240, eight_bit=True)  # uint8
+//! ```
+//!
+//! Images come with their first row at the top. Float images are the linear
+//! RGB average of the samples, eight bit ones are what the viewer shows.
+
+use nalgebra::{Vector3, Vector4};
+use numpy::{ndarray::Array3, PyArray3};
+use pyo3::{
+    exceptions::{PyIndexError, PyRuntimeError, PyValueError},
+    prelude::*,
+};
+
+use crate::{
+    camera::{self, CameraPose},
+    integrator::IntegratorKind,
+    renderer::{Canvas, RaytracingRenderer, RendererSettings},
+    scene, shapes,
+};
+
+fn vector3(v: (f64, f64, f64)) -> Vector3<f64> {
+    Vector3::new(v.0, v.1, v.2)
+}
+
+fn tuple3(v: Vector3<f64>) -> (f64, f64, f64) {
+    (v.x, v.y, v.z)
+}
+
+fn runtime_error(err: impl ToString) -> PyErr {
+    PyRuntimeError::new_err(err.to_string())
+}
+
+/// Same checks as the C interface
+fn check_radius(radius: f64) -> PyResult<()> {
+    if radius.is_nan() || radius <= 0.0 {
+        return Err(PyValueError::new_err("radius should be positive"));
+    }
+    Ok(())
+}
+
+fn check_pose(pose: &CameraPose) -> PyResult<()> {
+    pose.check()
+        .map_err(|err| PyValueError::new_err(err.to_string()))
+}
+
+#[pyclass(module = "raytracing")]
+#[derive(Clone)]
+struct Sphere(shapes::Sphere);
+
+#[pymethods]
+impl Sphere {
+    #[new]
+    #[pyo3(signature = (position = (0.0, 0.0, 0.0), radius = 0.5))]
+    fn new(position: (f64, f64, f64), radius: f64) -> PyResult<Sphere> {
+        check_radius(radius)?;
+        Ok(Sphere(shapes::Sphere::new(vector3(position), radius)))
+    }
+
+    #[getter]
+    fn position(&self) -> (f64, f64, f64) {
+        tuple3(self.0.position)
+    }
+
+    #[setter]
+    fn set_position(&mut self, position: (f64, f64, f64)) {
+        self.0.position = vector3(position);
+    }
+
+    #[getter]
+    fn radius(&self) -> f64 {
+        self.0.radius
+    }
+
+    #[setter]
+    fn set_radius(&mut self, radius: f64) -> PyResult<()> {
+        check_radius(radius)?;
+        self.0.radius = radius;
+        Ok(())
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "Sphere(position={:?}, radius={})",
+            self.position(),
+            self.0.radius
+        )
+    }
+}
+
+/// Colors are RGB, or RGBA
+#[pyclass(module = "raytracing")]
+#[derive(Clone)]
+struct Material(scene::Material);
+
+fn color(components: Vec<f64>) -> PyResult<Vector4<f64>> {
+    match components[..] {
+        [r, g, b] => Ok(Vector4::new(r, g, b, 1.0)),
+        [r, g, b, a] => Ok(Vector4::new(r, g, b, a)),
+        _ => Err(PyValueError::new_err("colors have 3 or 4 components")),
+    }
+}
+
+#[pymethods]
+impl Material {
+    #[new]
+    #[pyo3(signature = (
+        albedo = None,
+        roughness = None,
+        metallic = None,
+        emission_color = None,
+        emission_power = None,
+        transmission = None,
+    ))]
+    fn new(
+        albedo: Option<Vec<f64>>,
+        roughness: Option<f64>,
+        metallic: Option<f64>,
+        emission_color: Option<Vec<f64>>,
+        emission_power: Option<f64>,
+        transmission: Option<f64>,
+    ) -> PyResult<Material> {
+        let mut material = scene::Material::default();
+        if let Some(albedo) = albedo {
+            material.albedo = color(albedo)?;
+        }
+        if let Some(emission_color) = emission_color {
+            material.emission_color = color(emission_color)?;
+        }
+        material.roughness = roughness.unwrap_or(material.roughness);
+        material.metallic = metallic.unwrap_or(material.metallic);
+        material.emission_power = emission_power.unwrap_or(material.emission_power);
+        material.transmission = transmission.unwrap_or(material.transmission);
+        Ok(Material(material))
+    }
+
+    #[getter]
+    fn albedo(&self) -> Vec<f64> {
+        self.0.albedo.iter().copied().collect()
+    }
+
+    #[setter]
+    fn set_albedo(&mut self, albedo: Vec<f64>) -> PyResult<()> {
+        self.0.albedo = color(albedo)?;
+        Ok(())
+    }
+
+    #[getter]
+    fn roughness(&self) -> f64 {
+        self.0.roughness
+    }
+
+    #[setter]
+    fn set_roughness(&mut self, roughness: f64) {
+        self.0.roughness = roughness;
+    }
+
+    #[getter]
+    fn metallic(&self) -> f64 {
+        self.0.metallic
+    }
+
+    #[setter]
+    fn set_metallic(&mut self, metallic: f64) {
+        self.0.metallic = metallic;
+    }
+
+    #[getter]
+    fn emission_color(&self) -> Vec<f64> {
+        self.0.emission_color.iter().copied().collect()
+    }
+
+    #[setter]
+    fn set_emission_color(&mut self, emission_color: Vec<f64>) -> PyResult<()> {
+        self.0.emission_color = color(emission_color)?;
+        Ok(())
+    }
+
+    #[getter]
+    fn emission_power(&self) -> f64 {
+        self.0.emission_power
+    }
+
+    #[setter]
+    fn set_emission_power(&mut self, emission_power: f64) {
+        self.0.emission_power = emission_power;
+    }
+
+    #[getter]
+    fn transmission(&self) -> f64 {
+        self.0.transmission
+    }
+
+    #[setter]
+    fn set_transmission(&mut self, transmission: f64) {
+        self.0.transmission = transmission;
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "Material(albedo={:?}, roughness={}, metallic={}, emission_color={:?}, \
+             emission_power={}, transmission={})",
+            self.albedo(),
+            self.0.roughness,
+            self.0.metallic,
+            self.emission_color(),
+            self.0.emission_power,
+            self.0.transmission
+        )
+    }
+}
+
+#[pyclass(module = "raytracing")]
+#[derive(Clone)]
+struct Camera(CameraPose);
+
+#[pymethods]
+impl Camera {
+    #[new]
+    #[pyo3(signature = (
+        position = (0.0, 0.0, 6.0),
+        forward_direction = (0.0, 0.0, -1.0),
+        vertical_fov = 45.0,
+    ))]
+    fn new(
+        position: (f64, f64, f64),
+        forward_direction: (f64, f64, f64),
+        vertical_fov: f64,
+    ) -> PyResult<Camera> {
+        let pose = CameraPose {
+            position: vector3(position),
+            forward_direction: vector3(forward_direction),
+            vertical_fov,
+            ..Default::default()
+        };
+        check_pose(&pose)?;
+        Ok(Camera(pose))
+    }
+
+    #[getter]
+    fn position(&self) -> (f64, f64, f64) {
+        tuple3(self.0.position)
+    }
+
+    #[setter]
+    fn set_position(&mut self, position: (f64, f64, f64)) {
+        self.0.position = vector3(position);
+    }
+
+    #[getter]
+    fn forward_direction(&self) -> (f64, f64, f64) {
+        tuple3(self.0.forward_direction)
+    }
+
+    #[setter]
+    fn set_forward_direction(&mut self, forward_direction: (f64, f64, f64)) -> PyResult<()> {
+        let pose = CameraPose {
+            forward_direction: vector3(forward_direction),
+            ..self.0
+        };
+        check_pose(&pose)?;
+        self.0 = pose;
+        Ok(())
+    }
+
+    #[getter]
+    fn vertical_fov(&self) -> f64 {
+        self.0.vertical_fov
+    }
+
+    #[setter]
+    fn set_vertical_fov(&mut self, vertical_fov: f64) -> PyResult<()> {
+        let pose = CameraPose {
+            vertical_fov,
+            ..self.0
+        };
+        check_pose(&pose)?;
+        self.0 = pose;
+        Ok(())
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "Camera(position={:?}, forward_direction={:?}, vertical_fov={})",
+            self.position(),
+            self.forward_direction(),
+            self.0.vertical_fov
+        )
+    }
+}
+
+#[pyclass(module = "raytracing")]
+struct Scene(scene::Scene);
+
+#[pymethods]
+impl Scene {
+    #[new]
+    fn new() -> Scene {
+        Scene(scene::Scene::default())
+    }
+
+    /// Reads a scene document, like the viewer does
+    #[staticmethod]
+    fn load(path: &str) -> PyResult<Scene> {
+        scene::Scene::load(path).map(Scene).map_err(runtime_error)
+    }
+
+    /// Index of the new material
+    fn add_material(&mut self, material: &Material) -> usize {
+        self.0.materials.push(material.0.clone());
+        self.0.materials.len() - 1
+    }
+
+    /// Index of the new object
+    fn add_sphere(&mut self, sphere: &Sphere, material: usize) -> PyResult<usize> {
+        if material >= self.0.materials.len() {
+            return Err(PyIndexError::new_err(format!("no material {material}")));
+        }
+        Ok(self.0.add(sphere.0.clone(), material))
+    }
+
+    /// A copy, changing it does not change the scene
+    fn material(&self, index: usize) -> PyResult<Material> {
+        self.0
+            .materials
+            .get(index)
+            .map(|material| Material(material.clone()))
+            .ok_or_else(|| PyIndexError::new_err(format!("no material {index}")))
+    }
+
+    fn set_material(&mut self, index: usize, material: &Material) -> PyResult<()> {
+        let slot = self
+            .0
+            .materials
+            .get_mut(index)
+            .ok_or_else(|| PyIndexError::new_err(format!("no material {index}")))?;
+        *slot = material.0.clone();
+        Ok(())
+    }
+
+    #[getter]
+    fn material_count(&self) -> usize {
+        self.0.materials.len()
+    }
+
+    #[getter]
+    fn object_count(&self) -> usize {
+        self.0.objects.len()
+    }
+
+    /// The one the scene document sets, if any
+    #[getter]
+    fn camera(&self) -> Option<Camera> {
+        self.0.camera.map(Camera)
+    }
+
+    /// Moves the scene's animation to `time`, in seconds
+    fn animate(&mut self, time: f64) {
+        self.0.animate(time);
+    }
+}
+
+/// Renders `samples` samples per pixel, with the integrator named like in
+/// the viewer. The GIL is released while rendering.
+#[pyfunction]
+#[pyo3(signature = (
+    scene,
+    camera,
+    width = 320,
+    height = 240,
+    samples = 16,
+    integrator = "path tracing",
+    eight_bit = false,
+))]
+#[allow(clippy::too_many_arguments)]
+fn render<'py>(
+    py: Python<'py>,
+    mut scene: PyRefMut<'py, Scene>,
+    camera: &Camera,
+    width: u32,
+    height: u32,
+    samples: usize,
+    integrator: &str,
+    eight_bit: bool,
+) -> PyResult<Bound<'py, PyAny>> {
+    if width == 0 || height == 0 {
+        return Err(PyValueError::new_err("width and height should not be zero"));
+    }
+    if samples == 0 {
+        return Err(PyValueError::new_err("samples should not be zero"));
+    }
+    let kind = IntegratorKind::ALL
+        .into_iter()
+        .find(|kind| kind.name() == integrator.replace(['-', '_'], " "))
+        .ok_or_else(|| PyValueError::new_err(format!("unknown integrator '{integrator}'")))?;
+
+    // an ImportError rather than a panic of the array API without NumPy
+    py.import("numpy")?;
+
+    let scene = &mut scene.0;
+    scene.update();
+    let pose = camera.0;
+    let renderer = py.detach(|| {
+        let mut camera = camera::Camera::new(pose.vertical_fov, 0.1, 100.0);
+        camera.on_resize(width, height);
+        camera.set_pose(&pose);
+        let settings = RendererSettings {
+            accumulate: true,
+            use_threads: true,
+            slow_random: false,
+        };
+        let mut renderer = RaytracingRenderer::new(Canvas::new(1, 1), settings);
+        renderer.on_resize(width, height);
+        renderer.set_integrator(kind);
+        for _ in 0..samples {
+            renderer.render(scene, &camera);
+        }
+        renderer
+    });
+
+    let shape = (height as usize, width as usize, 4);
+    if eight_bit {
+        let image = renderer.canvas.to_image().into_raw();
+        let array = Array3::from_shape_vec(shape, image).map_err(runtime_error)?;
+        return Ok(PyArray3::from_owned_array(py, array).into_any());
+    }
+    // the canvas' first row is at the bottom
+    let colors = renderer.linear_colors();
+    let rows = colors.chunks(width as usize).rev();
+    let components = rows
+        .flatten()
+        .flat_map(|color| color.iter().map(|&component| component as f32))
+        .collect();
+    let array = Array3::from_shape_vec(shape, components).map_err(runtime_error)?;
+    Ok(PyArray3::from_owned_array(py, array).into_any())
+}
+
+#[pymodule]
+fn raytracing(module: &Bound<'_, PyModule>) -> PyResult<()> {
+    module.add_class::<Scene>()?;
+    module.add_class::<Sphere>()?;
+    module.add_class::<Material>()?;
+    module.add_class::<Camera>()?;
+    module.add_function(wrap_pyfunction!(render, module)?)?;
+    module.add(
+        "INTEGRATORS",
+        IntegratorKind::ALL
+            .iter()
+            .map(|kind| kind.name())
+            .collect::<Vec<_>>(),
+    )?;
+    Ok(())
+}
@@ -3,9 +3,9 @@ use nalgebra::{Vector3, Vector4};
 /// Wang hash
 pub fn random_u32(seed: &mut u32) -> u32 {
     *seed = (*seed ^ 61) ^ (*seed >> 16);
-    *seed *= 9;
+    *seed = seed.wrapping_mul(9);
     *seed = *seed ^ (*seed >> 4);
-    *seed *= 0x27d4eb2d;
+    *seed = seed.wrapping_mul(0x27d4eb2d);
     *seed = *seed ^ (*seed >> 15);
     *seed
 }
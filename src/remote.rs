@@ -0,0 +1,257 @@
+//! Remote control of a running viewer: JSON commands, one per line, on a
+//! TCP socket of the local host. Every command gets a line back, with `ok`
+//! and what the command returns, or `error`.
+//!
+//! ```json
+//! {"command": "status"}
+//! {"command": "get_camera"}
+//! {"command": "set_camera", "position": [0, 1, 6], "forward_direction": [0, 0, -1]}
+//! {"command": "move_camera", "offset": [0.5, 0, 0]}
+//! {"command": "list_objects"}
+//! {"command": "set", "target": {"type": "object", "object": 0, "param": "radius"}, "value": 0.7}
+//! {"command": "set", "target": {"type": "material", "material": 1, "param": "albedo"}, "value": [1, 0, 0]}
+//! {"command": "set_object_material", "object": 0, "material": 2}
+//! {"command": "set_integrator", "name": "bidirectional"}
+//! {"command": "reset"}
+//! {"command": "screenshot", "path": "shot.png"}
+//! ```
+//!
+//! `set` takes the targets of animation tracks, see `scene::animation`.
+//! Commands are applied by the viewer between two frames, with `apply`.
+
+use std::{
+    error::Error,
+    io::{BufRead, BufReader, Write},
+    net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs},
+    sync::mpsc::{self, Receiver, Sender},
+    thread,
+};
+
+use nalgebra::Vector3;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::{
+    camera::Camera,
+    integrator::IntegratorKind,
+    renderer::RaytracingRenderer,
+    scene::{
+        animation::{self, Target},
+        Scene,
+    },
+    shapes::Param,
+};
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(tag = "command", rename_all = "snake_case")]
+pub enum Command {
+    Status,
+    GetCamera,
+    /// Parameters left out keep their value
+    SetCamera {
+        position: Option<Vector3<f64>>,
+        forward_direction: Option<Vector3<f64>>,
+        vertical_fov: Option<f64>,
+    },
+    MoveCamera {
+        offset: Vector3<f64>,
+    },
+    ListObjects,
+    Set {
+        target: Target,
+        value: animation::Value,
+    },
+    SetObjectMaterial {
+        object: usize,
+        material: usize,
+    },
+    SetIntegrator {
+        name: String,
+    },
+    /// Starts accumulating again
+    Reset,
+    /// Saves the canvas as it is
+    Screenshot {
+        path: String,
+    },
+}
+
+/// A command waiting for the viewer, and where its answer goes
+pub struct Message {
+    pub command: Command,
+    pub reply: Sender<Value>,
+}
+
+/// Accepts connections on `address` in the background, their commands
+/// come out of the receiver. Anyone who can connect controls the viewer, so
+/// the address must be a loopback one.
+pub fn listen(address: &str) -> Result<Receiver<Message>, Box<dyn Error>> {
+    let addresses: Vec<SocketAddr> = address.to_socket_addrs()?.collect();
+    if addresses.is_empty() || !addresses.iter().all(|address| address.ip().is_loopback()) {
+        return Err(format!("'{address}' is not an address of the local host").into());
+    }
+    let listener = TcpListener::bind(addresses.as_slice())?;
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let sender = sender.clone();
+            thread::spawn(move || serve(stream, &sender));
+        }
+    });
+    Ok(receiver)
+}
+
+fn serve(stream: TcpStream, messages: &Sender<Message>) {
+    let Ok(input) = stream.try_clone() else {
+        return;
+    };
+    let mut out = stream;
+    for line in BufReader::new(input).lines() {
+        let Ok(line) = line else {
+            return;
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+        let answer = match serde_json::from_str(&line) {
+            Ok(command) => {
+                let (reply, answer) = mpsc::channel();
+                if messages.send(Message { command, reply }).is_err() {
+                    return;
+                }
+                match answer.recv() {
+                    Ok(answer) => answer,
+                    Err(_) => return,
+                }
+            }
+            Err(err) => json!({ "ok": false, "error": err.to_string() }),
+        };
+        if writeln!(out, "{answer}").is_err() {
+            return;
+        }
+    }
+}
+
+fn param_value(param: Param) -> Value {
+    match param {
+        Param::Point(vector)
+        | Param::Direction(vector)
+        | Param::Rotation(vector)
+        | Param::Scale(vector) => json!([vector.x, vector.y, vector.z]),
+        Param::Length(value) | Param::Fraction(value) => json!(value),
+        Param::Count(count) => json!(count),
+        Param::Flag(flag) => json!(flag),
+    }
+}
+
+/// Runs `command` on what the viewer shows, like its panels would
+pub fn apply(
+    command: Command,
+    scene: &mut Scene,
+    camera: &mut Camera,
+    renderer: &mut RaytracingRenderer,
+) -> Value {
+    match run(command, scene, camera, renderer) {
+        Ok(Value::Null) => json!({ "ok": true }),
+        Ok(answer) => json!({ "ok": true, "result": answer }),
+        Err(err) => json!({ "ok": false, "error": err.to_string() }),
+    }
+}
+
+fn run(
+    command: Command,
+    scene: &mut Scene,
+    camera: &mut Camera,
+    renderer: &mut RaytracingRenderer,
+) -> Result<Value, Box<dyn Error>> {
+    match command {
+        Command::Status => {
+            return Ok(json!({
+                "width": renderer.canvas.width,
+                "height": renderer.canvas.height,
+                "frame_index": renderer.frame_index(),
+                "integrator": renderer.integrator.name(),
+                "objects": scene.objects.len(),
+                "materials": scene.materials.len(),
+            }))
+        }
+        Command::GetCamera => return Ok(serde_json::to_value(camera.pose())?),
+        Command::SetCamera {
+            position,
+            forward_direction,
+            vertical_fov,
+        } => {
+            let mut pose = camera.pose();
+            pose.position = position.unwrap_or(pose.position);
+            pose.forward_direction = forward_direction.unwrap_or(pose.forward_direction);
+            pose.vertical_fov = vertical_fov.unwrap_or(pose.vertical_fov);
+            pose.check()?;
+            camera.set_pose(&pose);
+        }
+        Command::MoveCamera { offset } => {
+            let mut pose = camera.pose();
+            pose.position += offset;
+            camera.set_pose(&pose);
+        }
+        Command::ListObjects => {
+            let objects = scene
+                .objects
+                .iter()
+                .map(|object| {
+                    let params: serde_json::Map<String, Value> = object
+                        .shape
+                        .params()
+                        .into_iter()
+                        .map(|(name, param)| (name.to_string(), param_value(param)))
+                        .collect();
+                    json!({
+                        "shape": object.shape.name(),
+                        "material": object.material_index,
+                        "params": params,
+                    })
+                })
+                .collect();
+            return Ok(Value::Array(objects));
+        }
+        Command::Set { target, value } => {
+            let applied = match &target {
+                Target::Camera { param } => {
+                    // degenerate poses aren't applied
+                    let mut pose = camera.pose();
+                    let applied = animation::set_camera_param(&mut pose, param, value.components());
+                    camera.set_pose(&pose);
+                    applied
+                }
+                _ => target.apply(scene, value.components()),
+            };
+            if !applied {
+                return Err(format!("can't set {target:?} to {:?}", value.components()).into());
+            }
+            if let Target::Object { .. } = target {
+                scene.update();
+            }
+        }
+        Command::SetObjectMaterial { object, material } => {
+            if material >= scene.materials.len() {
+                return Err(format!("no material {material}").into());
+            }
+            let object = scene.objects.get_mut(object).ok_or("no such object")?;
+            object.material_index = material;
+        }
+        Command::SetIntegrator { name } => {
+            let kind = IntegratorKind::ALL
+                .into_iter()
+                .find(|kind| kind.name() == name)
+                .ok_or(format!("unknown integrator '{name}'"))?;
+            renderer.set_integrator(kind);
+        }
+        Command::Reset => {}
+        Command::Screenshot { path } => {
+            renderer.canvas.to_image().save(&path)?;
+            return Ok(json!({ "path": path }));
+        }
+    }
+    // everything else changes what is rendered
+    renderer.reset_frame_index();
+    Ok(Value::Null)
+}
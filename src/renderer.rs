@@ -1,19 +1,28 @@
-use rand::Rng;
 use rayon::prelude::*;
 extern crate nalgebra_glm as glm;
 use core::time;
 use std::time::Instant;
 
-use nalgebra::{ArrayStorage, Const, Matrix, Vector3, Vector4};
+use nalgebra::{ArrayStorage, Const, Matrix, Vector4};
 use serde::{Deserialize, Serialize};
 
 use crate::{
     camera::Camera,
-    random::random_f64,
-    rt::{color::color_to_u32, ray::Ray},
+    integrator::{Integrator, IntegratorKind, PathTracer, Sampler, Splat},
+    rt::{color::color_to_u32, spectrum},
     scene::Scene,
 };
 
+pub mod checkpoint;
+pub mod distributed;
+pub mod service;
+pub mod turntable;
+
+use turntable::Turntable;
+
+/// Accumulated color of a pixel before its first frame
+pub const ACCUMULATION_START: Vector4<f64> = Vector4::new(0.0, 0.0, 0.0, 1.0);
+
 pub struct RendererSettings {
     pub accumulate: bool,
     pub use_threads: bool,
@@ -25,6 +34,8 @@ pub struct RaytracingRenderer {
     pub accumulation_data: Vec<Vector4<f64>>,
     frame_index: usize,
     pub settings: RendererSettings,
+    pub integrator: Box<dyn Integrator>,
+    integrator_kind: IntegratorKind,
 }
 
 pub struct Canvas {
@@ -33,14 +44,6 @@ pub struct Canvas {
     pub height: u32,
 }
 
-#[derive(Default)]
-struct HitPayload {
-    hit_distance: f64,
-    world_position: Vector3<f64>,
-    world_normal: Vector3<f64>,
-    object_index: usize,
-}
-
 #[derive(Serialize, Deserialize)]
 pub struct State {
     pub use_linear_filter: bool,
@@ -48,6 +51,10 @@ pub struct State {
     pub canvas_width: u32,
     pub canvas_height: u32,
     pub sphere_color: [f32; 4],
+    #[serde(default)]
+    pub integrator: IntegratorKind,
+    #[serde(default)]
+    pub turntable: Turntable,
     #[serde(skip_serializing, skip_deserializing)]
     pub last_render_time: time::Duration,
     #[serde(skip_serializing, skip_deserializing)]
@@ -64,41 +71,103 @@ impl RaytracingRenderer {
             accumulation_data: Default::default(),
             frame_index: 1,
             settings,
+            integrator: Box::<PathTracer>::default(),
+            integrator_kind: IntegratorKind::Path,
         }
     }
 
+    /// Switches to another integrator and starts accumulating again
+    pub fn set_integrator(&mut self, kind: IntegratorKind) {
+        self.integrator = kind.build();
+        self.integrator_kind = kind;
+        self.reset_frame_index();
+    }
+
+    /// Kind of the integrator last set, path tracing at first
+    pub fn integrator_kind(&self) -> IntegratorKind {
+        self.integrator_kind
+    }
+
     pub fn reset_frame_index(&mut self) {
         self.frame_index = 1;
     }
 
+    /// Frame rendered next, one more than the frames accumulated
+    pub fn frame_index(&self) -> usize {
+        self.frame_index
+    }
+
+    /// Starts accumulating again from `frame_index` rather than 1, to render
+    /// a batch of the frames of a render split between processes
+    pub fn start_at(&mut self, frame_index: usize) {
+        self.accumulation_data.fill(ACCUMULATION_START);
+        self.frame_index = frame_index.max(1);
+    }
+
+    /// Average of the accumulated frames in linear RGB, with the alpha
+    /// clamped to 1, first row at the bottom like the canvas
+    pub fn linear_colors(&self) -> Vec<Vector4<f64>> {
+        let frames = self.frame_index.saturating_sub(1).max(1) as f64;
+        let spectral = self.integrator.spectral();
+        self.accumulation_data
+            .iter()
+            .map(|accumulated| {
+                let color = accumulated / frames;
+                let rgb = match spectral {
+                    true => spectrum::xyz_to_rgb(&color.xyz()),
+                    false => color.xyz(),
+                };
+                rgb.insert_row(3, color.w.min(1.0))
+            })
+            .collect()
+    }
+
+    /// Replaces the accumulation with one of `frames` frames, and shows it
+    pub fn set_accumulation(&mut self, accumulation: Vec<Vector4<f64>>, frames: usize) {
+        self.accumulation_data = accumulation;
+        self.frame_index = frames + 1;
+        if frames == 0 {
+            return;
+        }
+        let spectral = self.integrator.spectral();
+        for (pixel, accumulated) in self.canvas.data.iter_mut().zip(&self.accumulation_data) {
+            *pixel = display_color(&(accumulated / frames as f64), spectral);
+        }
+    }
+
     pub fn on_resize(&mut self, viewport_width: u32, viewport_height: u32) {
         if self.canvas.width == viewport_width && self.canvas.height == viewport_height {
             return;
         }
         self.canvas.resize(viewport_width, viewport_height);
         self.accumulation_data =
-            vec![Vector4::new(0.0, 0.0, 0.0, 1.0); (viewport_width * viewport_height) as usize];
+            vec![ACCUMULATION_START; (viewport_width * viewport_height) as usize];
     }
 
     pub fn render(&mut self, scene: &Scene, camera: &Camera) -> time::Duration {
         let start = Instant::now();
 
         if self.frame_index == 1 {
-            self.accumulation_data
-                .fill(Vector4::new(0.0, 0.0, 0.0, 1.0));
+            self.accumulation_data.fill(ACCUMULATION_START);
         }
 
+        self.integrator.prepare(scene, camera, self.frame_index);
+        let integrator = &*self.integrator;
+        let spectral = integrator.spectral();
         let render_row =
             |(y, (current_row, cumulated_row)): (usize, (CurrentData, AccumulationData))| {
+                let mut splats = Vec::new();
                 for x in 0..self.canvas.width {
+                    let pixel = (x + y as u32 * self.canvas.width) as usize;
+                    let seed = (pixel as u32).wrapping_mul(self.frame_index as u32);
+                    let mut sampler = Sampler::new(seed, self.settings.slow_random);
                     let color = Self::per_pixel(
-                        x,
-                        y as u32,
-                        self.canvas.width,
+                        integrator,
+                        pixel,
                         camera,
                         scene,
-                        self.settings.slow_random,
-                        self.frame_index as u32,
+                        &mut sampler,
+                        &mut splats,
                     );
                     let x = x as usize;
 
@@ -106,33 +175,43 @@ impl RaytracingRenderer {
 
                     let accumulated_color = cumulated_row[x] / self.frame_index as f64;
 
-                    let color = glm::clamp(&accumulated_color, 0.0, 1.0);
-
-                    current_row[x] = color_to_u32(&color);
+                    current_row[x] = display_color(&accumulated_color, spectral);
                 }
+                splats
             };
         //
 
         // let data = &mut self.canvas.data;
         let width = self.canvas.width as usize;
 
-        match self.settings.use_threads {
+        let splats: Vec<Splat> = match self.settings.use_threads {
             true => self
                 .canvas
                 .data
                 .par_chunks_mut(width)
                 .zip_eq(self.accumulation_data.par_chunks_mut(width))
                 .enumerate()
-                .for_each(render_row),
+                .flat_map_iter(render_row)
+                .collect(),
             false => self
                 .canvas
                 .data
                 .chunks_mut(width)
                 .zip(self.accumulation_data.chunks_mut(width))
                 .enumerate()
-                .for_each(render_row),
+                .flat_map(render_row)
+                .collect(),
         };
 
+        for splat in splats {
+            let Some(accumulated) = self.accumulation_data.get_mut(splat.pixel) else {
+                continue;
+            };
+            *accumulated += splat.color;
+            let color = *accumulated / self.frame_index as f64;
+            self.canvas.data[splat.pixel] = display_color(&color, spectral);
+        }
+
         if self.settings.accumulate {
             self.frame_index += 1;
         } else {
@@ -144,111 +223,32 @@ impl RaytracingRenderer {
 
     // RayGen
     pub fn per_pixel(
-        x: u32,
-        y: u32,
-        width: u32,
+        integrator: &dyn Integrator,
+        pixel: usize,
         camera: &Camera,
         scene: &Scene,
-        slow_random: bool,
-        frame_index: u32,
+        sampler: &mut Sampler,
+        splats: &mut Vec<Splat>,
     ) -> Vector4<f64> {
-        let mut ray = Ray {
-            origin: camera.position,
-            direction: camera.get_ray_directions()[(x + y * width) as usize],
+        // only draw a time when the shutter is open, so that still cameras
+        // keep their random sequences
+        let time = match camera.shutter[0] < camera.shutter[1] {
+            true => camera.shutter_time(sampler.next_f64()),
+            false => camera.shutter[0],
         };
-
-        let mut light = Vector4::new(0.0, 0.0, 0.0, 1.0);
-        let mut contribution = Vector4::new(1.0, 1.0, 1.0, 1.0);
-        let sky_color = Vector4::new(0.6, 0.7, 0.9, 1.0);
-        let bounces = 5;
-        let mut seed = (x + y * width) * frame_index;
-
-        for i in 0..bounces {
-            let payload = Self::trace_ray(&ray, scene);
-            seed += i;
-
-            if payload.hit_distance == f64::MAX {
-                light += sky_color;
-                break;
-            }
-
-            let closest_sphere = &scene.spheres[payload.object_index];
-            let sphere_material = &scene.materials[closest_sphere.material_index];
-
-            // light += glm::vec3_to_vec4(sphere_color).component_mul(&contribution);
-            contribution.component_mul_assign(&sphere_material.albedo);
-            // light.component_mul_assign(&contribution);
-            // contribution.component_mul_assign(&light);
-            light += sphere_material.get_emission().component_mul(&contribution);
-
-            if i < (bounces - 1) {
-                ray.origin = payload.world_position + payload.world_normal * 0.0001;
-
-                ray.direction = glm::reflect_vec(&ray.direction, &(payload.world_normal));
-                if sphere_material.roughness > 0.0 {
-                    let unit_sphere = random_unit_vec3f64(&mut seed, slow_random);
-                    ray.direction += sphere_material.roughness * unit_sphere;
-                }
-            }
-            // ray.direction = glm::normalize(&(payload.world_normal + unit_sphere));
-        }
-
-        light.component_mul(&contribution)
-    }
-
-    fn trace_ray(ray: &Ray, scene: &Scene) -> HitPayload {
-        let mut closest_sphere_index = usize::MAX;
-        let mut hit_distance = f64::MAX;
-        for (i, sphere) in scene.spheres.iter().enumerate() {
-            let sphere_origin = sphere.position;
-            let radius = sphere.radius;
-
-            let oc = ray.origin - sphere_origin;
-
-            let a = ray.direction.dot(&ray.direction);
-            let b = 2.0 * oc.dot(&ray.direction);
-            let c = oc.dot(&oc) - radius * radius;
-
-            let discriminant = b * b - 4.0 * a * c;
-            if discriminant < 0.0 {
-                continue;
-            }
-
-            // (-b +- sqrt(discriminant)) / 2a
-            let closest_t = (-b - discriminant.sqrt()) / 2.0 * a;
-            if closest_t > 0.0 && closest_t < hit_distance {
-                hit_distance = closest_t;
-                closest_sphere_index = i;
-            }
-        }
-
-        if closest_sphere_index == usize::MAX {
-            return Self::miss(ray);
-        }
-
-        Self::closest_hit(ray, scene, closest_sphere_index, hit_distance)
-    }
-
-    fn closest_hit(ray: &Ray, scene: &Scene, object_index: usize, hit_distance: f64) -> HitPayload {
-        let closest_sphere = &scene.spheres[object_index];
-        let origin = ray.origin - closest_sphere.position;
-        let hit_point = origin + ray.direction * hit_distance;
-        let normal = hit_point.normalize();
-
-        HitPayload {
-            hit_distance,
-            world_position: hit_point + closest_sphere.position,
-            world_normal: normal,
-            object_index,
-        }
+        let ray = camera.ray(pixel, time);
+        integrator.radiance(ray, scene, sampler, splats)
     }
+}
 
-    fn miss(_ray: &Ray) -> HitPayload {
-        HitPayload {
-            hit_distance: f64::MAX,
-            ..Default::default()
-        }
-    }
+/// Pixel for an accumulated color, which is CIE XYZ for spectral
+/// integrators
+fn display_color(color: &Vector4<f64>, spectral: bool) -> u32 {
+    let rgb = match spectral {
+        true => spectrum::xyz_to_rgb(&color.xyz()).insert_row(3, color.w),
+        false => *color,
+    };
+    color_to_u32(&glm::clamp(&rgb, 0.0, 1.0))
 }
 
 impl Canvas {
@@ -265,6 +265,18 @@ impl Canvas {
         self.width = width;
         self.height = height;
     }
+
+    /// Copy with the first row at the top, as image files want it
+    pub fn to_image(&self) -> image::RgbaImage {
+        let bytes = self
+            .data
+            .iter()
+            .flat_map(|pixel| pixel.to_le_bytes())
+            .collect();
+        let image = image::RgbaImage::from_raw(self.width, self.height, bytes)
+            .expect("canvas size matches its data");
+        image::imageops::flip_vertical(&image)
+    }
 }
 
 impl Default for State {
@@ -275,25 +287,10 @@ impl Default for State {
             canvas_width: 400,
             canvas_height: 270,
             sphere_color: [1.0; 4],
+            integrator: IntegratorKind::default(),
+            turntable: Turntable::default(),
             last_render_time: time::Duration::ZERO,
             error_msg: String::default(),
         }
     }
 }
-
-fn random_unit_vec3f64(seed: &mut u32, use_thread_rng: bool) -> Vector3<f64> {
-    if use_thread_rng {
-        let mut rng = rand::thread_rng();
-        glm::vec3(
-            rng.gen_range(-1.0..1.0),
-            rng.gen_range(-1.0..1.0),
-            rng.gen_range(-1.0..1.0),
-        )
-    } else {
-        Vector3::new(
-            random_f64(seed) * 2.0 - 1.0,
-            random_f64(seed) * 2.0 - 1.0,
-            random_f64(seed) * 2.0 - 1.0,
-        )
-    }
-}
@@ -0,0 +1,162 @@
+//! Progressive renders saved to a file, to go on accumulating in a later run
+//!
+//! A checkpoint holds the accumulated colors and the index of the next
+//! frame. The random numbers of a pixel only depend on its index and the
+//! frame index, so that is all the random state there is to keep. The scene
+//! hash covers the scene, the camera, the integrator and its parameters; a
+//! checkpoint only resumes a render of the same thing.
+
+use std::{
+    error::Error,
+    fmt::Write as _,
+    fs::{self, File},
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::Path,
+};
+
+use nalgebra::Vector4;
+
+use super::RaytracingRenderer;
+use crate::{
+    camera::Camera,
+    scene::{Fnv1a, Scene},
+};
+
+const MAGIC: &[u8; 8] = b"RTCHECK1";
+/// Bytes before the accumulation: magic, size, frame index, slow random
+/// flag and scene hash
+const HEADER_SIZE: u64 = 8 + 4 + 4 + 8 + 1 + 8;
+/// Bytes of an accumulated color
+const PIXEL_SIZE: u64 = 4 * 8;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Checkpoint {
+    pub width: u32,
+    pub height: u32,
+    /// Frame rendered next, one more than the frames accumulated
+    pub frame_index: usize,
+    pub slow_random: bool,
+    pub scene_hash: u64,
+    pub accumulation: Vec<Vector4<f64>>,
+}
+
+impl Checkpoint {
+    /// Writes to a temporary file first, so that a crash while saving
+    /// keeps the previous checkpoint
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), Box<dyn Error>> {
+        let path = path.as_ref();
+        let temporary = path.with_extension("tmp");
+        let mut out = BufWriter::new(File::create(&temporary)?);
+        out.write_all(MAGIC)?;
+        out.write_all(&self.width.to_le_bytes())?;
+        out.write_all(&self.height.to_le_bytes())?;
+        out.write_all(&(self.frame_index as u64).to_le_bytes())?;
+        out.write_all(&[self.slow_random as u8])?;
+        out.write_all(&self.scene_hash.to_le_bytes())?;
+        for color in &self.accumulation {
+            for component in color.iter() {
+                out.write_all(&component.to_le_bytes())?;
+            }
+        }
+        out.into_inner()?.sync_all()?;
+        fs::rename(temporary, path)?;
+        Ok(())
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Checkpoint, Box<dyn Error>> {
+        let file = File::open(path)?;
+        let length = file.metadata()?.len();
+        let mut input = BufReader::new(file);
+        if &read_bytes(&mut input)? != MAGIC {
+            return Err("not a checkpoint file".into());
+        }
+        let width = u32::from_le_bytes(read_bytes(&mut input)?);
+        let height = u32::from_le_bytes(read_bytes(&mut input)?);
+        let frame_index = u64::from_le_bytes(read_bytes(&mut input)?) as usize;
+        let [slow_random] = read_bytes(&mut input)?;
+        let scene_hash = u64::from_le_bytes(read_bytes(&mut input)?);
+
+        // the size comes from the file, check it before allocating for it
+        let pixels = width as u64 * height as u64;
+        let expected = pixels
+            .checked_mul(PIXEL_SIZE)
+            .and_then(|size| size.checked_add(HEADER_SIZE));
+        if expected != Some(length) {
+            return Err(format!("the checkpoint file doesn't hold {width}x{height} pixels").into());
+        }
+        let pixels = pixels as usize;
+        let mut accumulation = Vec::with_capacity(pixels);
+        for _ in 0..pixels {
+            let mut color = Vector4::zeros();
+            for component in color.iter_mut() {
+                *component = f64::from_le_bytes(read_bytes(&mut input)?);
+            }
+            accumulation.push(color);
+        }
+        Ok(Checkpoint {
+            width,
+            height,
+            frame_index,
+            slow_random: slow_random != 0,
+            scene_hash,
+            accumulation,
+        })
+    }
+}
+
+fn read_bytes<const N: usize>(input: &mut impl Read) -> io::Result<[u8; N]> {
+    let mut bytes = [0; N];
+    input.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
+impl RaytracingRenderer {
+    /// Hash of what is being rendered, see `Checkpoint`
+    pub fn render_hash(&self, scene: &Scene, camera: &Camera) -> u64 {
+        let mut hasher = Fnv1a(scene.content_hash());
+        let _ = write!(
+            hasher,
+            "{:?}{}{:?}",
+            camera.pose(),
+            self.integrator.name(),
+            self.integrator.params()
+        );
+        hasher.0
+    }
+
+    pub fn checkpoint(&self, scene: &Scene, camera: &Camera) -> Checkpoint {
+        Checkpoint {
+            width: self.canvas.width,
+            height: self.canvas.height,
+            frame_index: self.frame_index,
+            slow_random: self.settings.slow_random,
+            scene_hash: self.render_hash(scene, camera),
+            accumulation: self.accumulation_data.clone(),
+        }
+    }
+
+    /// Goes on accumulating from `checkpoint`, which must be of the same
+    /// render at the current canvas size
+    pub fn resume(
+        &mut self,
+        checkpoint: Checkpoint,
+        scene: &Scene,
+        camera: &Camera,
+    ) -> Result<(), Box<dyn Error>> {
+        if checkpoint.scene_hash != self.render_hash(scene, camera) {
+            return Err("the checkpoint is of another scene, camera or integrator".into());
+        }
+        if (checkpoint.width, checkpoint.height) != (self.canvas.width, self.canvas.height) {
+            return Err(format!(
+                "the checkpoint is {}x{}, not {}x{}",
+                checkpoint.width, checkpoint.height, self.canvas.width, self.canvas.height
+            )
+            .into());
+        }
+        let frames = checkpoint.frame_index.saturating_sub(1);
+        self.set_accumulation(checkpoint.accumulation, frames);
+        self.settings.slow_random = checkpoint.slow_random;
+        self.settings.accumulate = true;
+        Ok(())
+    }
+}
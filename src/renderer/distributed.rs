@@ -0,0 +1,373 @@
+//! Renders split between worker processes over TCP
+//!
+//! The coordinator hands out batches of frame indices. The random numbers of
+//! a pixel only depend on the frame index, so a worker rendering frames
+//! `start..end` computes exactly the samples a single process would, and the
+//! sums of the batches add up to the same accumulation. A worker that dies
+//! or stops answering gives its batch back to the others, and the
+//! coordinator renders what is left itself when none remain.
+//!
+//! Messages are a little endian `u32` length followed by a YAML `Request`,
+//! or the `Status` of a load. Rendered batches come back as the color sums
+//! of every pixel, four little endian `f64` each.
+
+use std::{
+    collections::VecDeque,
+    error::Error,
+    io::{self, BufReader, BufWriter, Read, Write},
+    net::{TcpListener, TcpStream},
+    ops::Range,
+    path::{Path, PathBuf},
+    process::{self, Child, Command, Stdio},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use nalgebra::Vector4;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use super::{Canvas, RaytracingRenderer, RendererSettings, ACCUMULATION_START};
+use crate::{
+    camera::{Camera, CameraPose},
+    integrator::IntegratorKind,
+    scene::Scene,
+};
+
+/// Time given to spawned workers to connect
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Longest a worker may stay silent before it counts as dead, and its batch
+/// goes back in the queue
+const READ_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// What every worker renders batches of. The scene is a path, so workers
+/// need to see the same files as the coordinator.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Job {
+    pub scene: PathBuf,
+    /// Of the scene's animation
+    pub time: f64,
+    pub camera: CameraPose,
+    pub width: u32,
+    pub height: u32,
+    pub integrator: IntegratorKind,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Request {
+    Load { job: Job },
+    Render { start: usize, end: usize },
+    Quit,
+}
+
+type Status = Result<(), String>;
+
+fn send(out: &mut impl Write, message: &impl Serialize) -> Result<(), Box<dyn Error>> {
+    let text = serde_yaml::to_string(message)?;
+    out.write_all(&(text.len() as u32).to_le_bytes())?;
+    out.write_all(text.as_bytes())?;
+    out.flush()?;
+    Ok(())
+}
+
+fn receive<T: DeserializeOwned>(input: &mut impl Read) -> Result<T, Box<dyn Error>> {
+    let mut length = [0; 4];
+    input.read_exact(&mut length)?;
+    let mut text = vec![0; u32::from_le_bytes(length) as usize];
+    input.read_exact(&mut text)?;
+    Ok(serde_yaml::from_slice(&text)?)
+}
+
+/// Scene, camera and renderer of a worker's job
+struct Loaded {
+    scene: Scene,
+    camera: Camera,
+    renderer: RaytracingRenderer,
+}
+
+impl Loaded {
+    fn new(job: &Job) -> Result<Loaded, Box<dyn Error>> {
+        let mut scene = Scene::load(&job.scene)?;
+        scene.animate(job.time);
+        let mut camera = Camera::new(job.camera.vertical_fov, 0.1, 100.0);
+        camera.on_resize(job.width, job.height);
+        camera.set_pose(&job.camera);
+        let settings = RendererSettings {
+            accumulate: true,
+            use_threads: true,
+            slow_random: false,
+        };
+        let mut renderer = RaytracingRenderer::new(Canvas::new(1, 1), settings);
+        renderer.on_resize(job.width, job.height);
+        renderer.set_integrator(job.integrator);
+        Ok(Loaded {
+            scene,
+            camera,
+            renderer,
+        })
+    }
+
+    /// Color sums of the frames in `frames`
+    fn render(&mut self, frames: Range<usize>) -> Vec<Vector4<f64>> {
+        self.renderer.start_at(frames.start);
+        for _ in frames {
+            self.renderer.render(&self.scene, &self.camera);
+        }
+        self.renderer
+            .accumulation_data
+            .iter()
+            .map(|accumulated| accumulated - ACCUMULATION_START)
+            .collect()
+    }
+}
+
+/// Runs a worker for the coordinator at `address`, until it says to quit
+pub fn work(address: &str) -> Result<(), Box<dyn Error>> {
+    let stream = TcpStream::connect(address)?;
+    stream.set_nodelay(true)?;
+    let mut input = BufReader::new(stream.try_clone()?);
+    let mut out = BufWriter::new(stream);
+    send(&mut out, &process::id())?;
+    let mut loaded = None;
+    loop {
+        match receive(&mut input)? {
+            Request::Load { job } => {
+                let status: Status = match Loaded::new(&job) {
+                    Ok(new) => {
+                        loaded = Some(new);
+                        Ok(())
+                    }
+                    Err(err) => Err(err.to_string()),
+                };
+                send(&mut out, &status)?;
+            }
+            Request::Render { start, end } => {
+                let loaded = loaded.as_mut().ok_or("render before load")?;
+                for color in loaded.render(start..end) {
+                    for component in color.iter() {
+                        out.write_all(&component.to_le_bytes())?;
+                    }
+                }
+                out.flush()?;
+            }
+            Request::Quit => return Ok(()),
+        }
+    }
+}
+
+struct Worker {
+    input: BufReader<TcpStream>,
+    out: BufWriter<TcpStream>,
+    child: Option<Child>,
+}
+
+impl Worker {
+    fn load(&mut self, job: &Job) -> Result<Status, Box<dyn Error>> {
+        send(&mut self.out, &Request::Load { job: job.clone() })?;
+        receive(&mut self.input)
+    }
+
+    fn render(&mut self, frames: Range<usize>, pixels: usize) -> io::Result<Vec<Vector4<f64>>> {
+        let request = Request::Render {
+            start: frames.start,
+            end: frames.end,
+        };
+        send(&mut self.out, &request).map_err(|err| io::Error::other(err.to_string()))?;
+        let mut sums = Vec::with_capacity(pixels);
+        let mut bytes = [0; 32];
+        for _ in 0..pixels {
+            self.input.read_exact(&mut bytes)?;
+            sums.push(Vector4::from_fn(|i, _| {
+                let component = bytes[i * 8..i * 8 + 8].try_into().expect("8 bytes");
+                f64::from_le_bytes(component)
+            }));
+        }
+        Ok(sums)
+    }
+}
+
+pub struct Coordinator {
+    workers: Vec<Worker>,
+}
+
+impl Coordinator {
+    /// Starts `count` processes running `program` with `args` followed by
+    /// the address to connect to, and waits for them
+    pub fn spawn(
+        count: usize,
+        program: &Path,
+        args: &[&str],
+    ) -> Result<Coordinator, Box<dyn Error>> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let address = listener.local_addr()?.to_string();
+        let mut children: VecDeque<Child> = (0..count)
+            .map(|_| {
+                Command::new(program)
+                    .args(args)
+                    .arg(&address)
+                    .stdin(Stdio::null())
+                    .spawn()
+            })
+            .collect::<Result<_, _>>()?;
+
+        listener.set_nonblocking(true)?;
+        let deadline = Instant::now() + CONNECT_TIMEOUT;
+        let mut streams = Vec::new();
+        while streams.len() < count && Instant::now() < deadline {
+            match listener.accept() {
+                Ok((stream, _)) => streams.push(stream),
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => {
+                    thread::sleep(Duration::from_millis(10))
+                }
+                Err(err) => return Err(err.into()),
+            }
+        }
+        if streams.is_empty() {
+            for mut child in children {
+                let _ = child.kill();
+            }
+            return Err("no worker connected".into());
+        }
+
+        let workers = streams
+            .into_iter()
+            .map(|stream| {
+                stream.set_nonblocking(false)?;
+                stream.set_nodelay(true)?;
+                stream.set_read_timeout(Some(READ_TIMEOUT))?;
+                let mut input = BufReader::new(stream.try_clone()?);
+                let id: u32 = receive(&mut input)?;
+                let child = children
+                    .iter()
+                    .position(|child| child.id() == id)
+                    .and_then(|index| children.remove(index));
+                Ok(Worker {
+                    input,
+                    out: BufWriter::new(stream),
+                    child,
+                })
+            })
+            .collect::<Result<_, Box<dyn Error>>>()?;
+        // the ones that never connected
+        for mut child in children {
+            let _ = child.kill();
+        }
+        Ok(Coordinator { workers })
+    }
+
+    /// Workers still connected
+    pub fn worker_count(&self) -> usize {
+        self.workers.len()
+    }
+
+    /// Renders `samples` frames of `job` in batches of `batch` frames, and
+    /// gives `renderer` their accumulation. `scene` and `camera` are the
+    /// job's, for batches left when every worker is gone.
+    pub fn render(
+        &mut self,
+        job: &Job,
+        samples: usize,
+        batch: usize,
+        renderer: &mut RaytracingRenderer,
+        scene: &Scene,
+        camera: &Camera,
+    ) -> Result<(), Box<dyn Error>> {
+        let pixels = job.width as usize * job.height as usize;
+        let batch = batch.max(1);
+        let queue: Mutex<VecDeque<Range<usize>>> = Mutex::new(
+            (1..samples + 1)
+                .step_by(batch)
+                .map(|start| start..(start + batch).min(samples + 1))
+                .collect(),
+        );
+        let sums = Mutex::new(vec![Vector4::zeros(); pixels]);
+        let done = AtomicUsize::new(0);
+
+        let alive: Vec<bool> = thread::scope(|scope| {
+            let threads: Vec<_> = self
+                .workers
+                .iter_mut()
+                .map(|worker| {
+                    let (queue, sums, done) = (&queue, &sums, &done);
+                    scope.spawn(move || -> Result<bool, String> {
+                        match worker.load(job) {
+                            Ok(Ok(())) => {}
+                            Ok(Err(err)) => return Err(err),
+                            Err(_) => return Ok(false),
+                        }
+                        while done.load(Ordering::SeqCst) < samples {
+                            let Some(frames) = queue.lock().unwrap().pop_front() else {
+                                // a batch may come back from a dead worker
+                                thread::sleep(Duration::from_millis(10));
+                                continue;
+                            };
+                            match worker.render(frames.clone(), pixels) {
+                                Ok(batch_sums) => {
+                                    let mut sums = sums.lock().unwrap();
+                                    for (sum, batch_sum) in sums.iter_mut().zip(batch_sums) {
+                                        *sum += batch_sum;
+                                    }
+                                    done.fetch_add(frames.len(), Ordering::SeqCst);
+                                }
+                                Err(_) => {
+                                    queue.lock().unwrap().push_back(frames);
+                                    return Ok(false);
+                                }
+                            }
+                        }
+                        Ok(true)
+                    })
+                })
+                .collect();
+            threads
+                .into_iter()
+                .map(|thread| thread.join().unwrap_or(Ok(false)))
+                .collect::<Result<_, String>>()
+        })?;
+
+        let mut index = 0;
+        self.workers.retain_mut(|worker| {
+            index += 1;
+            if !alive[index - 1] {
+                if let Some(child) = &mut worker.child {
+                    let _ = child.kill();
+                }
+            }
+            alive[index - 1]
+        });
+
+        // what the workers could not render
+        let mut sums = sums.into_inner().unwrap();
+        for frames in queue.into_inner().unwrap() {
+            renderer.start_at(frames.start);
+            for _ in frames {
+                renderer.render(scene, camera);
+            }
+            for (sum, accumulated) in sums.iter_mut().zip(&renderer.accumulation_data) {
+                *sum += accumulated - ACCUMULATION_START;
+            }
+        }
+
+        let accumulation = sums.iter().map(|sum| sum + ACCUMULATION_START).collect();
+        renderer.set_accumulation(accumulation, samples);
+        Ok(())
+    }
+}
+
+impl Drop for Coordinator {
+    fn drop(&mut self) {
+        for worker in &mut self.workers {
+            let _ = send(&mut worker.out, &Request::Quit);
+        }
+        for worker in &mut self.workers {
+            if let Some(child) = &mut worker.child {
+                let _ = child.wait();
+            }
+        }
+    }
+}
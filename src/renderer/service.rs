@@ -0,0 +1,425 @@
+//! HTTP render service. Scenes are queued as jobs and rendered one after the
+//! other, on a thread of their own.
+//!
+//! - `POST /jobs?samples=64&width=320&height=240&integrator=path%20tracing`
+//!   with a scene document as the body queues a job and answers its id.
+//!   Paths in the scene are relative to the server's directory and can't go
+//!   out of it. Documents are limited to 16 MiB, jobs to 4096x4096 pixels
+//!   and 65536 samples.
+//! - `GET /jobs` lists the jobs, `GET /jobs/ID` tells the progress of one.
+//! - `GET /jobs/ID/image.png` is what has been rendered so far.
+//! - `GET /jobs/ID/image.exr` is the finished render, in linear RGB.
+//! - `DELETE /jobs/ID` or `POST /jobs/ID/cancel` cancels a job.
+//!
+//! Answers other than images are JSON.
+
+use std::{
+    collections::VecDeque,
+    error::Error,
+    io::{Cursor, Read},
+    panic::{self, AssertUnwindSafe},
+    path::Path,
+    sync::{Arc, Condvar, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+use exr::prelude::{Image, SpecificChannels, Vec2, WritableImage};
+use image::RgbaImage;
+use nalgebra::Vector4;
+use serde_json::{json, Value};
+use tiny_http::{Header, Method, Request, Response, Server};
+
+use super::{Canvas, RaytracingRenderer, RendererSettings};
+use crate::{
+    camera::Camera,
+    integrator::IntegratorKind,
+    scene::{description::SceneDescription, Scene},
+};
+
+/// Most pixels of a job, to keep its accumulation within a few hundred
+/// megabytes
+const MAX_PIXELS: u64 = 4096 * 4096;
+const MAX_SAMPLES: usize = 1 << 16;
+/// Most bytes of a scene document
+const MAX_BODY: u64 = 16 << 20;
+
+/// Used for what a submitted job leaves out
+#[derive(Clone, Copy, Debug)]
+pub struct JobSettings {
+    pub samples: usize,
+    pub width: u32,
+    pub height: u32,
+    pub integrator: IntegratorKind,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum JobState {
+    Queued,
+    Running,
+    Done,
+    Cancelled,
+    Failed,
+}
+
+struct Job {
+    id: usize,
+    settings: JobSettings,
+    state: JobState,
+    /// Taken by the render thread
+    scene: Option<Scene>,
+    samples_done: usize,
+    started: Option<Instant>,
+    elapsed: Duration,
+    cancel: bool,
+    error: Option<String>,
+    /// So far
+    image: Option<RgbaImage>,
+    /// Linear colors of the finished render, first row at the bottom
+    colors: Option<Vec<Vector4<f64>>>,
+}
+
+#[derive(Default)]
+struct Jobs {
+    jobs: Vec<Job>,
+    queue: VecDeque<usize>,
+}
+
+type Shared = Arc<(Mutex<Jobs>, Condvar)>;
+
+/// Serves requests on `address` until the process ends
+pub fn serve(address: &str, defaults: JobSettings) -> Result<(), Box<dyn Error>> {
+    let server = Server::http(address).map_err(|err| err.to_string())?;
+    serve_on(server, defaults);
+    Ok(())
+}
+
+/// Serves the requests of `server`, for callers that bind it themselves,
+/// e.g. on port 0 to get a free one
+pub fn serve_on(server: Server, defaults: JobSettings) {
+    let shared: Shared = Arc::default();
+    let render_shared = shared.clone();
+    thread::spawn(move || render_jobs(&render_shared));
+    for request in server.incoming_requests() {
+        handle(request, &shared, defaults);
+    }
+}
+
+fn render_jobs(shared: &Shared) {
+    let (jobs, wake) = &**shared;
+    loop {
+        let (id, settings, scene) = {
+            let mut jobs = jobs.lock().unwrap();
+            let id = loop {
+                match jobs.queue.pop_front() {
+                    Some(id) => break id,
+                    None => jobs = wake.wait(jobs).unwrap(),
+                }
+            };
+            let job = &mut jobs.jobs[id];
+            if job.cancel {
+                continue;
+            }
+            job.state = JobState::Running;
+            job.started = Some(Instant::now());
+            let scene = job.scene.take().expect("queued jobs have a scene");
+            (id, job.settings, scene)
+        };
+
+        let rendered =
+            panic::catch_unwind(AssertUnwindSafe(|| render_job(jobs, id, settings, &scene)));
+        let mut jobs = jobs.lock().unwrap();
+        let job = &mut jobs.jobs[id];
+        match rendered {
+            Err(_) => {
+                job.state = JobState::Failed;
+                job.error = Some("the render panicked".to_string());
+            }
+            Ok(colors) if job.state == JobState::Running => {
+                job.state = JobState::Done;
+                job.colors = Some(colors);
+            }
+            Ok(_) => {}
+        }
+    }
+}
+
+/// Renders the samples of a job one at a time, showing the image after each
+/// one, until they are done or the job is cancelled. Answers the linear
+/// colors of the render.
+fn render_job(
+    jobs: &Mutex<Jobs>,
+    id: usize,
+    settings: JobSettings,
+    scene: &Scene,
+) -> Vec<Vector4<f64>> {
+    let mut camera = Camera::new(45.0, 0.1, 100.0);
+    camera.on_resize(settings.width, settings.height);
+    if let Some(pose) = &scene.camera {
+        camera.set_pose(pose);
+    }
+    let mut renderer = RaytracingRenderer::new(
+        Canvas::new(1, 1),
+        RendererSettings {
+            accumulate: true,
+            use_threads: true,
+            slow_random: false,
+        },
+    );
+    renderer.on_resize(settings.width, settings.height);
+    renderer.set_integrator(settings.integrator);
+
+    for sample in 1..=settings.samples {
+        renderer.render(scene, &camera);
+        let image = renderer.canvas.to_image();
+        let mut jobs = jobs.lock().unwrap();
+        let job = &mut jobs.jobs[id];
+        job.samples_done = sample;
+        job.elapsed = job.started.map_or(Duration::ZERO, |start| start.elapsed());
+        job.image = Some(image);
+        if job.cancel {
+            job.state = JobState::Cancelled;
+            break;
+        }
+    }
+    renderer.linear_colors()
+}
+
+/// Answer to a request about a job, images being encoded after the jobs
+/// are unlocked
+enum Answer {
+    Ready(Response<Cursor<Vec<u8>>>),
+    Png(RgbaImage),
+    Exr(JobSettings, Vec<Vector4<f64>>),
+}
+
+fn handle(mut request: Request, shared: &Shared, defaults: JobSettings) {
+    let url = request.url().to_string();
+    let (path, query) = url.split_once('?').unwrap_or((&url, ""));
+    let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+    let (jobs, wake) = &**shared;
+
+    let response = match (request.method(), segments.as_slice()) {
+        (Method::Post, ["jobs"]) => {
+            let mut body = String::new();
+            match request
+                .as_reader()
+                .take(MAX_BODY + 1)
+                .read_to_string(&mut body)
+            {
+                Ok(_) if body.len() as u64 > MAX_BODY => error_response(
+                    413,
+                    &format!("scene documents can't be larger than {MAX_BODY} bytes"),
+                ),
+                Ok(_) => match submit(&body, query, defaults) {
+                    Ok((settings, scene)) => {
+                        let mut jobs = jobs.lock().unwrap();
+                        let id = jobs.jobs.len();
+                        jobs.jobs.push(Job {
+                            id,
+                            settings,
+                            state: JobState::Queued,
+                            scene: Some(scene),
+                            samples_done: 0,
+                            started: None,
+                            elapsed: Duration::ZERO,
+                            cancel: false,
+                            error: None,
+                            image: None,
+                            colors: None,
+                        });
+                        jobs.queue.push_back(id);
+                        wake.notify_one();
+                        json_response(201, &status(&jobs.jobs[id]))
+                    }
+                    Err(err) => error_response(400, &err.to_string()),
+                },
+                Err(err) => error_response(400, &err.to_string()),
+            }
+        }
+        (Method::Get, ["jobs"]) => {
+            let jobs = jobs.lock().unwrap();
+            let list: Vec<Value> = jobs.jobs.iter().map(status).collect();
+            json_response(200, &Value::Array(list))
+        }
+        (method, ["jobs", id, rest @ ..]) => {
+            let mut jobs = jobs.lock().unwrap();
+            let answer = match id
+                .parse::<usize>()
+                .ok()
+                .and_then(|id| jobs.jobs.get_mut(id))
+            {
+                None => Answer::Ready(error_response(404, "no such job")),
+                Some(job) => match (method, rest) {
+                    (Method::Get, []) => Answer::Ready(json_response(200, &status(job))),
+                    (Method::Delete, []) | (Method::Post, ["cancel"]) => {
+                        job.cancel = true;
+                        if job.state == JobState::Queued {
+                            job.state = JobState::Cancelled;
+                        }
+                        Answer::Ready(json_response(200, &status(job)))
+                    }
+                    (Method::Get, ["image.png"]) => match &job.image {
+                        Some(image) => Answer::Png(image.clone()),
+                        None => Answer::Ready(error_response(409, "no sample rendered yet")),
+                    },
+                    (Method::Get, ["image.exr"]) => match &job.colors {
+                        Some(colors) => Answer::Exr(job.settings, colors.clone()),
+                        None => Answer::Ready(error_response(409, "the job is not done")),
+                    },
+                    _ => Answer::Ready(error_response(404, "not found")),
+                },
+            };
+            // encoding takes a while, the render thread shouldn't wait for it
+            drop(jobs);
+            match answer {
+                Answer::Ready(response) => response,
+                Answer::Png(image) => png_response(&image),
+                Answer::Exr(settings, colors) => exr_response(settings, &colors),
+            }
+        }
+        _ => error_response(404, "not found"),
+    };
+    let _ = request.respond(response);
+}
+
+/// Job settings from the query and the scene from the body
+fn submit(
+    body: &str,
+    query: &str,
+    defaults: JobSettings,
+) -> Result<(JobSettings, Scene), Box<dyn Error>> {
+    let mut settings = defaults;
+    for pair in query.split('&').filter(|pair| !pair.is_empty()) {
+        let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+        let value = percent_decode(value);
+        match name {
+            "samples" => settings.samples = value.parse()?,
+            "width" => settings.width = value.parse()?,
+            "height" => settings.height = value.parse()?,
+            "integrator" => {
+                settings.integrator = IntegratorKind::ALL
+                    .into_iter()
+                    .find(|kind| kind.name() == value)
+                    .ok_or(format!("unknown integrator '{value}'"))?
+            }
+            _ => return Err(format!("unknown parameter '{name}'").into()),
+        }
+    }
+    if settings.samples == 0 || settings.width == 0 || settings.height == 0 {
+        return Err("samples, width and height should not be zero".into());
+    }
+    if settings.width as u64 * settings.height as u64 > MAX_PIXELS {
+        return Err(format!("images can't have more than {MAX_PIXELS} pixels").into());
+    }
+    if settings.samples > MAX_SAMPLES {
+        return Err(format!("jobs can't have more than {MAX_SAMPLES} samples").into());
+    }
+
+    let mut description = SceneDescription::from_yaml(body)?;
+    description.load_resources_within(Path::new(""))?;
+    let scene = description.build()?;
+    if let Some(animation) = &scene.animation {
+        animation.check(&scene)?;
+    }
+    Ok((settings, scene))
+}
+
+fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+                continue;
+            }
+            (b'+', _) => decoded.push(b' '),
+            (byte, _) => decoded.push(byte),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+fn status(job: &Job) -> Value {
+    let state = match job.state {
+        JobState::Queued => "queued",
+        JobState::Running => "running",
+        JobState::Done => "done",
+        JobState::Cancelled => "cancelled",
+        JobState::Failed => "failed",
+    };
+    let elapsed = job.elapsed.as_secs_f64();
+    let eta = match (job.state, job.samples_done) {
+        (JobState::Running, done) if done > 0 => {
+            Some(elapsed / done as f64 * (job.settings.samples - done) as f64)
+        }
+        _ => None,
+    };
+    json!({
+        "id": job.id,
+        "state": state,
+        "samples": job.settings.samples,
+        "samples_done": job.samples_done,
+        "width": job.settings.width,
+        "height": job.settings.height,
+        "integrator": job.settings.integrator.name(),
+        "elapsed_seconds": elapsed,
+        "eta_seconds": eta,
+        "error": job.error,
+    })
+}
+
+fn header(name: &str, value: &str) -> Header {
+    Header::from_bytes(name, value).expect("valid header")
+}
+
+fn json_response(code: u16, value: &Value) -> Response<Cursor<Vec<u8>>> {
+    Response::from_data(value.to_string())
+        .with_status_code(code)
+        .with_header(header("Content-Type", "application/json"))
+}
+
+fn error_response(code: u16, message: &str) -> Response<Cursor<Vec<u8>>> {
+    json_response(code, &json!({ "error": message }))
+}
+
+fn png_response(image: &RgbaImage) -> Response<Cursor<Vec<u8>>> {
+    let mut bytes = Vec::new();
+    let encoder = image::codecs::png::PngEncoder::new(&mut bytes);
+    let encoded = encoder.encode(
+        image.as_raw(),
+        image.width(),
+        image.height(),
+        image::ColorType::Rgba8,
+    );
+    match encoded {
+        Ok(()) => Response::from_data(bytes).with_header(header("Content-Type", "image/png")),
+        Err(err) => error_response(500, &err.to_string()),
+    }
+}
+
+fn exr_response(settings: JobSettings, colors: &[Vector4<f64>]) -> Response<Cursor<Vec<u8>>> {
+    let (width, height) = (settings.width as usize, settings.height as usize);
+    let channels = SpecificChannels::rgba(|Vec2(x, y): Vec2<usize>| {
+        // the canvas' first row is at the bottom
+        let color = colors[(height - 1 - y) * width + x].cast::<f32>();
+        (color.x, color.y, color.z, color.w)
+    });
+    let mut bytes = Cursor::new(Vec::new());
+    match Image::from_channels((width, height), channels)
+        .write()
+        .to_buffered(&mut bytes)
+    {
+        Ok(()) => Response::from_data(bytes.into_inner())
+            .with_header(header("Content-Type", "image/x-exr")),
+        Err(err) => error_response(500, &err.to_string()),
+    }
+}
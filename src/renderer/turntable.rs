@@ -0,0 +1,194 @@
+//! Camera orbits around a point rendered to an animated GIF or PNG, for
+//! looking at a scene from every side
+
+use std::{error::Error, f64::consts::TAU, fs::File, io::BufWriter, path::Path};
+
+use image::{codecs::gif, Delay, Frame, RgbaImage};
+use nalgebra::Vector3;
+use serde::{Deserialize, Serialize};
+
+use super::{Canvas, RaytracingRenderer, RendererSettings};
+use crate::{
+    camera::{Camera, CameraPose},
+    integrator::IntegratorKind,
+    scene::Scene,
+};
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Turntable {
+    pub target: Vector3<f64>,
+    pub radius: f64,
+    /// Angle above the horizon, in degrees
+    pub elevation: f64,
+    pub frames: usize,
+    pub samples: usize,
+    pub fps: f64,
+    pub width: u32,
+    pub height: u32,
+    /// An animated PNG when it ends with `.png`, a GIF otherwise
+    pub path: String,
+}
+
+impl Default for Turntable {
+    fn default() -> Self {
+        Turntable {
+            target: Vector3::zeros(),
+            radius: 6.0,
+            elevation: 20.0,
+            frames: 36,
+            samples: 16,
+            fps: 12.0,
+            width: 320,
+            height: 240,
+            path: "turntable.gif".to_string(),
+        }
+    }
+}
+
+impl Turntable {
+    /// Err when the orbit is degenerate: a zero radius, or looking straight
+    /// up or down, along the up vector
+    pub fn check(&self) -> Result<(), Box<dyn Error>> {
+        if self.radius.is_nan() || self.radius <= 0.0 {
+            return Err("the turntable radius should be positive".into());
+        }
+        if self.elevation.is_nan() || self.elevation.abs() >= 90.0 {
+            return Err("the turntable elevation should be between -90 and 90 degrees".into());
+        }
+        Ok(())
+    }
+
+    /// Camera of a frame, going around the y axis, keeping the field of
+    /// view of `base`
+    pub fn pose(&self, frame: usize, base: &CameraPose) -> CameraPose {
+        let angle = TAU * frame as f64 / self.frames.max(1) as f64;
+        let elevation = self.elevation.to_radians();
+        let offset = Vector3::new(
+            elevation.cos() * angle.sin(),
+            elevation.sin(),
+            elevation.cos() * angle.cos(),
+        ) * self.radius;
+        CameraPose {
+            position: self.target + offset,
+            forward_direction: -offset.normalize(),
+            vertical_fov: base.vertical_fov,
+            ..Default::default()
+        }
+    }
+}
+
+/// Turntable being rendered a sample at a time, so a window can keep
+/// drawing in between
+pub struct TurntableJob {
+    pub turntable: Turntable,
+    base: CameraPose,
+    renderer: RaytracingRenderer,
+    camera: Camera,
+    frame: usize,
+    samples: usize,
+    images: Vec<RgbaImage>,
+}
+
+impl TurntableJob {
+    pub fn new(
+        turntable: Turntable,
+        integrator: IntegratorKind,
+        base: CameraPose,
+    ) -> Result<TurntableJob, Box<dyn Error>> {
+        turntable.check()?;
+        let settings = RendererSettings {
+            accumulate: true,
+            use_threads: true,
+            slow_random: false,
+        };
+        let mut renderer = RaytracingRenderer::new(Canvas::new(1, 1), settings);
+        renderer.on_resize(turntable.width, turntable.height);
+        renderer.set_integrator(integrator);
+        let mut camera = Camera::new(base.vertical_fov, 0.1, 100.0);
+        camera.on_resize(turntable.width, turntable.height);
+        camera.set_pose(&turntable.pose(0, &base));
+        Ok(TurntableJob {
+            turntable,
+            base,
+            renderer,
+            camera,
+            frame: 0,
+            samples: 0,
+            images: Vec::new(),
+        })
+    }
+
+    /// Frames done, from 0 to 1
+    pub fn progress(&self) -> f64 {
+        let total = self.turntable.frames * self.turntable.samples;
+        let done = self.frame * self.turntable.samples + self.samples;
+        done as f64 / total.max(1) as f64
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.frame >= self.turntable.frames
+    }
+
+    /// Renders one more sample, moving to the next frame when the current
+    /// one has all of them
+    pub fn step(&mut self, scene: &Scene) {
+        if self.is_done() {
+            return;
+        }
+        self.renderer.render(scene, &self.camera);
+        self.samples += 1;
+        if self.samples < self.turntable.samples.max(1) {
+            return;
+        }
+        self.images.push(self.renderer.canvas.to_image());
+        self.samples = 0;
+        self.frame += 1;
+        self.renderer.reset_frame_index();
+        self.camera
+            .set_pose(&self.turntable.pose(self.frame, &self.base));
+    }
+
+    /// Renders what is left and writes the animation
+    pub fn finish(mut self, scene: &Scene) -> Result<(), Box<dyn Error>> {
+        while !self.is_done() {
+            self.step(scene);
+        }
+        save_animation(&self.turntable.path, &self.images, self.turntable.fps)
+    }
+}
+
+/// Writes frames as an animated PNG when the path ends with `.png`, or as a
+/// looping GIF otherwise
+pub fn save_animation(
+    path: impl AsRef<Path>,
+    images: &[RgbaImage],
+    fps: f64,
+) -> Result<(), Box<dyn Error>> {
+    let path = path.as_ref();
+    let first = images.first().ok_or("no frames to save")?;
+    let file = BufWriter::new(File::create(path)?);
+    let delay_ms = (1000.0 / fps.max(0.01)).round() as u32;
+    match path.extension().and_then(|extension| extension.to_str()) {
+        Some(extension) if extension.eq_ignore_ascii_case("png") => {
+            let mut encoder = png::Encoder::new(file, first.width(), first.height());
+            encoder.set_color(png::ColorType::Rgba);
+            encoder.set_depth(png::BitDepth::Eight);
+            encoder.set_animated(images.len() as u32, 0)?;
+            encoder.set_frame_delay(delay_ms.min(u16::MAX as u32) as u16, 1000)?;
+            let mut writer = encoder.write_header()?;
+            for image in images {
+                writer.write_image_data(image.as_raw())?;
+            }
+            writer.finish()?;
+        }
+        _ => {
+            let mut encoder = gif::GifEncoder::new(file);
+            encoder.set_repeat(gif::Repeat::Infinite)?;
+            encoder.encode_frames(images.iter().map(|image| {
+                Frame::from_parts(image.clone(), 0, 0, Delay::from_numer_denom_ms(delay_ms, 1))
+            }))?;
+        }
+    }
+    Ok(())
+}
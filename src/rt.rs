@@ -1,2 +1,9 @@
+pub mod aabb;
+pub mod bvh;
 pub mod color;
+pub mod noise;
+pub mod onb;
 pub mod ray;
+pub mod roots;
+pub mod spectrum;
+pub mod transform;
@@ -0,0 +1,131 @@
+use nalgebra::Vector3;
+
+use super::ray::Ray;
+
+/// Axis-aligned bounding box
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Aabb {
+    pub min: Vector3<f64>,
+    pub max: Vector3<f64>,
+}
+
+impl Aabb {
+    pub fn new(min: Vector3<f64>, max: Vector3<f64>) -> Aabb {
+        Aabb { min, max }
+    }
+
+    /// Box that contains nothing, the identity for `union`
+    pub fn empty() -> Aabb {
+        Aabb {
+            min: Vector3::repeat(f64::INFINITY),
+            max: Vector3::repeat(f64::NEG_INFINITY),
+        }
+    }
+
+    /// Box that contains everything, for unbounded shapes like planes
+    pub fn infinite() -> Aabb {
+        Aabb {
+            min: Vector3::repeat(f64::NEG_INFINITY),
+            max: Vector3::repeat(f64::INFINITY),
+        }
+    }
+
+    pub fn from_points<'a>(points: impl IntoIterator<Item = &'a Vector3<f64>>) -> Aabb {
+        points
+            .into_iter()
+            .fold(Aabb::empty(), |bounds, point| bounds.grow(point))
+    }
+
+    pub fn around(center: &Vector3<f64>, half_size: &Vector3<f64>) -> Aabb {
+        Aabb::new(center - half_size, center + half_size)
+    }
+
+    pub fn is_finite(&self) -> bool {
+        self.min
+            .iter()
+            .chain(self.max.iter())
+            .all(|v| v.is_finite())
+    }
+
+    pub fn union(&self, other: &Aabb) -> Aabb {
+        Aabb {
+            min: self.min.inf(&other.min),
+            max: self.max.sup(&other.max),
+        }
+    }
+
+    /// Overlap of both boxes, empty (with `min > max`) when they are disjoint
+    pub fn intersection(&self, other: &Aabb) -> Aabb {
+        Aabb {
+            min: self.min.sup(&other.min),
+            max: self.max.inf(&other.max),
+        }
+    }
+
+    pub fn grow(&self, point: &Vector3<f64>) -> Aabb {
+        Aabb {
+            min: self.min.inf(point),
+            max: self.max.sup(point),
+        }
+    }
+
+    pub fn center(&self) -> Vector3<f64> {
+        (self.min + self.max) * 0.5
+    }
+
+    pub fn size(&self) -> Vector3<f64> {
+        self.max - self.min
+    }
+
+    pub fn surface_area(&self) -> f64 {
+        let size = self.size();
+        2.0 * (size.x * size.y + size.y * size.z + size.z * size.x)
+    }
+
+    pub fn corners(&self) -> [Vector3<f64>; 8] {
+        let (a, b) = (self.min, self.max);
+        [
+            Vector3::new(a.x, a.y, a.z),
+            Vector3::new(b.x, a.y, a.z),
+            Vector3::new(a.x, b.y, a.z),
+            Vector3::new(b.x, b.y, a.z),
+            Vector3::new(a.x, a.y, b.z),
+            Vector3::new(b.x, a.y, b.z),
+            Vector3::new(a.x, b.y, b.z),
+            Vector3::new(b.x, b.y, b.z),
+        ]
+    }
+
+    /// Slab test, returns the parametric range of the ray inside the box
+    pub fn intersect(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<(f64, f64)> {
+        let mut t_near = t_min;
+        let mut t_far = t_max;
+
+        for axis in 0..3 {
+            let inverse_direction = 1.0 / ray.direction[axis];
+            let mut t0 = (self.min[axis] - ray.origin[axis]) * inverse_direction;
+            let mut t1 = (self.max[axis] - ray.origin[axis]) * inverse_direction;
+            if inverse_direction < 0.0 {
+                std::mem::swap(&mut t0, &mut t1);
+            }
+            // NaNs from 0 * inf leave the range untouched
+            if t0 > t_near {
+                t_near = t0;
+            }
+            if t1 < t_far {
+                t_far = t1;
+            }
+            if t_far < t_near {
+                return None;
+            }
+        }
+
+        Some((t_near, t_far))
+    }
+}
+
+impl Default for Aabb {
+    fn default() -> Self {
+        Aabb::empty()
+    }
+}
@@ -0,0 +1,292 @@
+use std::cell::Cell;
+
+use rayon::prelude::*;
+
+use super::{aabb::Aabb, ray::Ray};
+use crate::shapes::Hit;
+
+const MAX_LEAF_SIZE: usize = 4;
+const SAH_BINS: usize = 12;
+/// Subtrees with fewer items are built on a single thread
+const PARALLEL_BUILD_SIZE: usize = 4096;
+
+thread_local! {
+    /// Nodes visited by the intersections made on this thread, for the
+    /// traversal cost view
+    static NODE_VISITS: Cell<usize> = const { Cell::new(0) };
+}
+
+/// Number of BVH nodes visited on this thread since the last call, nested
+/// hierarchies (like the triangles of meshes) included
+pub fn take_node_visits() -> usize {
+    NODE_VISITS.with(|visits| visits.replace(0))
+}
+
+/// Bounding volume hierarchy over a list of items, only their bounds are
+/// known to it. The same structure is used for the triangles of a mesh and
+/// for the objects of a scene, which makes instancing a two-level BVH.
+#[derive(Clone, Debug, Default)]
+pub struct Bvh {
+    nodes: Vec<Node>,
+    /// Item indices, leaves refer to contiguous ranges of it
+    items: Vec<usize>,
+}
+
+#[derive(Clone, Debug)]
+struct Node {
+    bounds: Aabb,
+    /// First item for leaves, index of the left child for interior nodes
+    /// (the right one comes right after it)
+    first: usize,
+    /// Number of items, zero for interior nodes
+    count: usize,
+}
+
+impl Node {
+    fn leaf(first: usize, count: usize) -> Node {
+        Node {
+            bounds: Aabb::empty(),
+            first,
+            count,
+        }
+    }
+}
+
+struct BuildItem {
+    index: usize,
+    bounds: Aabb,
+    centroid: nalgebra::Vector3<f64>,
+}
+
+impl Bvh {
+    /// Builds the hierarchy with the surface area heuristic, `bounds[i]` being
+    /// the bounds of item `i`
+    pub fn build(bounds: &[Aabb]) -> Bvh {
+        let mut build_items: Vec<BuildItem> = bounds
+            .par_iter()
+            .enumerate()
+            .map(|(index, bounds)| BuildItem {
+                index,
+                bounds: *bounds,
+                centroid: bounds.center(),
+            })
+            .collect();
+
+        if build_items.is_empty() {
+            return Bvh::default();
+        }
+        // leaves refer to ranges of the partitioned items, so their order is
+        // the final one
+        let nodes = Self::build_subtree(&mut build_items, 0);
+        Bvh {
+            nodes,
+            items: build_items.iter().map(|item| item.index).collect(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn bounds(&self) -> Aabb {
+        self.nodes
+            .first()
+            .map_or_else(Aabb::empty, |node| node.bounds)
+    }
+
+    /// Builds the nodes for `items`, which start at `offset` in the whole
+    /// item list. Big subtrees are built in parallel and then merged, with the
+    /// children of the root placed right after it.
+    fn build_subtree(items: &mut [BuildItem], offset: usize) -> Vec<Node> {
+        if items.len() < PARALLEL_BUILD_SIZE {
+            let mut nodes = Vec::with_capacity(items.len() / MAX_LEAF_SIZE * 2 + 1);
+            nodes.push(Node::leaf(offset, items.len()));
+            Self::build_node(&mut nodes, 0, items, offset);
+            return nodes;
+        }
+
+        let (bounds, split) = Self::split(items);
+        let Some(split) = split else {
+            return vec![Node {
+                bounds,
+                ..Node::leaf(offset, items.len())
+            }];
+        };
+        let (left_items, right_items) = items.split_at_mut(split);
+        let (left, right) = rayon::join(
+            || Self::build_subtree(left_items, offset),
+            || Self::build_subtree(right_items, offset + split),
+        );
+
+        let mut nodes = Vec::with_capacity(left.len() + right.len() + 1);
+        nodes.push(Node {
+            bounds,
+            first: 1,
+            count: 0,
+        });
+        let (left_shift, right_shift) = (2, left.len() + 1);
+        let shifted = |node: &Node, shift: usize| match node.count {
+            0 => Node {
+                first: node.first + shift,
+                ..node.clone()
+            },
+            _ => node.clone(),
+        };
+        nodes.push(shifted(&left[0], left_shift));
+        nodes.push(shifted(&right[0], right_shift));
+        nodes.extend(left[1..].iter().map(|node| shifted(node, left_shift)));
+        nodes.extend(right[1..].iter().map(|node| shifted(node, right_shift)));
+        nodes
+    }
+
+    fn build_node(
+        nodes: &mut Vec<Node>,
+        node_index: usize,
+        items: &mut [BuildItem],
+        offset: usize,
+    ) {
+        let (bounds, split) = Self::split(items);
+        nodes[node_index].bounds = bounds;
+
+        let Some(split) = split else {
+            nodes[node_index].first = offset;
+            nodes[node_index].count = items.len();
+            return;
+        };
+
+        let left = nodes.len();
+        nodes.push(Node::leaf(0, 0));
+        nodes.push(Node::leaf(0, 0));
+        nodes[node_index].first = left;
+        nodes[node_index].count = 0;
+
+        let (left_items, right_items) = items.split_at_mut(split);
+        Self::build_node(nodes, left, left_items, offset);
+        Self::build_node(nodes, left + 1, right_items, offset + split);
+    }
+
+    /// Bounds of the items and where they were split, `None` for a leaf
+    fn split(items: &mut [BuildItem]) -> (Aabb, Option<usize>) {
+        let bounds = items
+            .iter()
+            .fold(Aabb::empty(), |bounds, item| bounds.union(&item.bounds));
+        let split = if items.len() > MAX_LEAF_SIZE {
+            Self::find_split(items, &bounds)
+        } else {
+            None
+        };
+        (bounds, split)
+    }
+
+    /// Partitions the items around the best split found with binned SAH and
+    /// returns where the right child starts, `None` if a leaf is cheaper
+    fn find_split(items: &mut [BuildItem], bounds: &Aabb) -> Option<usize> {
+        let centroid_bounds = items
+            .iter()
+            .fold(Aabb::empty(), |bounds, item| bounds.grow(&item.centroid));
+        let extent = centroid_bounds.size();
+        let bin_of = |item: &BuildItem, axis: usize| {
+            let offset = (item.centroid[axis] - centroid_bounds.min[axis]) / extent[axis];
+            ((offset * SAH_BINS as f64) as usize).min(SAH_BINS - 1)
+        };
+
+        // bin along the three axes in a single pass over the items
+        let mut bin_bounds = [[Aabb::empty(); SAH_BINS]; 3];
+        let mut bin_counts = [[0usize; SAH_BINS]; 3];
+        for item in items.iter() {
+            for axis in 0..3 {
+                let bin = bin_of(item, axis);
+                bin_bounds[axis][bin] = bin_bounds[axis][bin].union(&item.bounds);
+                bin_counts[axis][bin] += 1;
+            }
+        }
+
+        let mut best: Option<(f64, usize, usize)> = None;
+        for axis in 0..3 {
+            if extent[axis] <= 0.0 || !extent[axis].is_finite() {
+                continue;
+            }
+            let (bin_bounds, bin_counts) = (&bin_bounds[axis], &bin_counts[axis]);
+            for split in 1..SAH_BINS {
+                let (left, right) = (bin_bounds[..split].iter(), bin_bounds[split..].iter());
+                let left_bounds = left.fold(Aabb::empty(), |a, b| a.union(b));
+                let right_bounds = right.fold(Aabb::empty(), |a, b| a.union(b));
+                let left_count: usize = bin_counts[..split].iter().sum();
+                let right_count = items.len() - left_count;
+                if left_count == 0 || right_count == 0 {
+                    continue;
+                }
+
+                let cost = left_bounds.surface_area() * left_count as f64
+                    + right_bounds.surface_area() * right_count as f64;
+                if best.is_none_or(|(best_cost, _, _)| cost < best_cost) {
+                    best = Some((cost, axis, split));
+                }
+            }
+        }
+
+        let (cost, axis, split) = best?;
+        let leaf_cost = bounds.surface_area() * items.len() as f64;
+        if cost >= leaf_cost && items.len() <= MAX_LEAF_SIZE * 4 {
+            return None;
+        }
+
+        // partition in place, sorting would make big builds much slower
+        let mut middle = 0;
+        for i in 0..items.len() {
+            if bin_of(&items[i], axis) < split {
+                items.swap(i, middle);
+                middle += 1;
+            }
+        }
+        Some(middle)
+    }
+
+    /// Closest hit among the items, `intersect_item(item, t_min, t_max)` being
+    /// called for the items whose bounds are crossed by the ray
+    pub fn intersect(
+        &self,
+        ray: &Ray,
+        t_min: f64,
+        t_max: f64,
+        mut intersect_item: impl FnMut(usize, f64, f64) -> Option<Hit>,
+    ) -> Option<(usize, Hit)> {
+        if self.nodes.is_empty() {
+            return None;
+        }
+
+        let mut closest = None;
+        let mut t_max = t_max;
+        let mut stack = Vec::with_capacity(64);
+        stack.push(0);
+        let mut visits = 0;
+
+        while let Some(node_index) = stack.pop() {
+            visits += 1;
+            let node: &Node = &self.nodes[node_index];
+            if node.bounds.intersect(ray, t_min, t_max).is_none() {
+                continue;
+            }
+
+            if node.count == 0 {
+                stack.push(node.first);
+                stack.push(node.first + 1);
+                continue;
+            }
+
+            for &item in &self.items[node.first..node.first + node.count] {
+                if let Some(hit) = intersect_item(item, t_min, t_max) {
+                    t_max = hit.distance;
+                    closest = Some((item, hit));
+                }
+            }
+        }
+
+        NODE_VISITS.with(|count| count.set(count.get() + visits));
+        closest
+    }
+}
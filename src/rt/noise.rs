@@ -0,0 +1,110 @@
+//! Gradient and cellular noise over the plane, and gradient noise in space
+//! for volumes. They only depend on the point, so textures built on them
+//! stay the same from frame to frame.
+
+use std::f64::consts::{SQRT_2, TAU};
+
+use nalgebra::{Vector2, Vector3};
+
+use crate::random::{random_f64, random_u32};
+
+/// Perlin gradient noise, in [-1, 1]
+pub fn perlin(p: &Vector2<f64>) -> f64 {
+    let cell = p.map(f64::floor);
+    let f = p - cell;
+    let (x, y) = (cell.x as i32, cell.y as i32);
+
+    let corner = |dx: i32, dy: i32| {
+        let angle = random_f64(&mut cell_seed(x + dx, y + dy)) * TAU;
+        let gradient = Vector2::new(angle.cos(), angle.sin());
+        gradient.dot(&(f - Vector2::new(dx as f64, dy as f64)))
+    };
+    let fade = |t: f64| t * t * t * (t * (t * 6.0 - 15.0) + 10.0);
+    let (u, v) = (fade(f.x), fade(f.y));
+
+    let bottom = lerp(corner(0, 0), corner(1, 0), u);
+    let top = lerp(corner(0, 1), corner(1, 1), u);
+    (lerp(bottom, top, v) * SQRT_2).clamp(-1.0, 1.0)
+}
+
+/// Fractal sum of `octaves` layers of Perlin noise, each one with twice the
+/// frequency and half the amplitude of the previous one, in [-1, 1]
+pub fn fbm(p: &Vector2<f64>, octaves: u32) -> f64 {
+    let (mut sum, mut total, mut amplitude, mut frequency) = (0.0, 0.0, 1.0, 1.0);
+    for _ in 0..octaves.max(1) {
+        sum += perlin(&(p * frequency)) * amplitude;
+        total += amplitude;
+        amplitude *= 0.5;
+        frequency *= 2.0;
+    }
+    sum / total
+}
+
+/// Perlin gradient noise in space, in [-1, 1]
+pub fn perlin_3d(p: &Vector3<f64>) -> f64 {
+    let cell = p.map(f64::floor);
+    let f = p - cell;
+    let (x, y, z) = (cell.x as i32, cell.y as i32, cell.z as i32);
+
+    let corner = |dx: i32, dy: i32, dz: i32| {
+        let mut seed = cell_seed_3d(x + dx, y + dy, z + dz);
+        let z = random_f64(&mut seed) * 2.0 - 1.0;
+        let angle = random_f64(&mut seed) * TAU;
+        let r = (1.0 - z * z).sqrt();
+        let gradient = Vector3::new(r * angle.cos(), r * angle.sin(), z);
+        gradient.dot(&(f - Vector3::new(dx as f64, dy as f64, dz as f64)))
+    };
+    let fade = |t: f64| t * t * t * (t * (t * 6.0 - 15.0) + 10.0);
+    let (u, v, w) = (fade(f.x), fade(f.y), fade(f.z));
+
+    let layer = |dz: i32| {
+        let bottom = lerp(corner(0, 0, dz), corner(1, 0, dz), u);
+        let top = lerp(corner(0, 1, dz), corner(1, 1, dz), u);
+        lerp(bottom, top, v)
+    };
+    (lerp(layer(0), layer(1), w) * 2.0 / 3.0_f64.sqrt()).clamp(-1.0, 1.0)
+}
+
+/// `fbm` over `perlin_3d`, in [-1, 1]
+pub fn fbm_3d(p: &Vector3<f64>, octaves: u32) -> f64 {
+    let (mut sum, mut total, mut amplitude, mut frequency) = (0.0, 0.0, 1.0, 1.0);
+    for _ in 0..octaves.max(1) {
+        sum += perlin_3d(&(p * frequency)) * amplitude;
+        total += amplitude;
+        amplitude *= 0.5;
+        frequency *= 2.0;
+    }
+    sum / total
+}
+
+/// Distance to the closest of a set of scattered points, one in each unit
+/// cell, so it stays in [0, ~1.4]
+pub fn worley(p: &Vector2<f64>) -> f64 {
+    let (x, y) = (p.x.floor() as i32, p.y.floor() as i32);
+    let mut closest = f64::MAX;
+    for dy in -1..=1 {
+        for dx in -1..=1 {
+            let mut seed = cell_seed(x + dx, y + dy);
+            let feature = Vector2::new(
+                (x + dx) as f64 + random_f64(&mut seed),
+                (y + dy) as f64 + random_f64(&mut seed),
+            );
+            closest = closest.min((feature - p).norm());
+        }
+    }
+    closest
+}
+
+fn cell_seed(x: i32, y: i32) -> u32 {
+    let mut seed = (x as u32).wrapping_mul(0x8da6_b343) ^ (y as u32).wrapping_mul(0xd816_3841);
+    random_u32(&mut seed)
+}
+
+fn cell_seed_3d(x: i32, y: i32, z: i32) -> u32 {
+    let mut seed = cell_seed(x, y) ^ (z as u32).wrapping_mul(0x2f69_3b1d);
+    random_u32(&mut seed)
+}
+
+fn lerp(a: f64, b: f64, t: f64) -> f64 {
+    a + (b - a) * t
+}
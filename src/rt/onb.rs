@@ -0,0 +1,33 @@
+use nalgebra::Vector3;
+
+/// Orthonormal basis built around a single direction, used to move between
+/// world space and the local frame of oriented shapes
+pub struct Onb {
+    pub u: Vector3<f64>,
+    pub v: Vector3<f64>,
+    pub w: Vector3<f64>,
+}
+
+impl Onb {
+    /// Duff et al., "Building an Orthonormal Basis, Revisited"
+    pub fn from_w(w: &Vector3<f64>) -> Onb {
+        let w = w.normalize();
+        let sign = 1.0_f64.copysign(w.z);
+        let a = -1.0 / (sign + w.z);
+        let b = w.x * w.y * a;
+
+        Onb {
+            u: Vector3::new(1.0 + sign * w.x * w.x * a, sign * b, -sign * w.x),
+            v: Vector3::new(b, sign + w.y * w.y * a, -w.y),
+            w,
+        }
+    }
+
+    pub fn to_local(&self, a: &Vector3<f64>) -> Vector3<f64> {
+        Vector3::new(a.dot(&self.u), a.dot(&self.v), a.dot(&self.w))
+    }
+
+    pub fn to_world(&self, a: &Vector3<f64>) -> Vector3<f64> {
+        a.x * self.u + a.y * self.v + a.z * self.w
+    }
+}
@@ -3,11 +3,22 @@ use nalgebra::Vector3;
 pub struct Ray {
     pub origin: Vector3<f64>,
     pub direction: Vector3<f64>,
+    /// When the ray was traced, within the camera's shutter interval. Moving
+    /// objects are intersected where they are at that time.
+    pub time: f64,
 }
 
 impl Ray {
     pub fn new(origin: Vector3<f64>, direction: Vector3<f64>) -> Ray {
-        Ray { origin, direction }
+        Ray::with_time(origin, direction, 0.0)
+    }
+
+    pub fn with_time(origin: Vector3<f64>, direction: Vector3<f64>, time: f64) -> Ray {
+        Ray {
+            origin,
+            direction,
+            time,
+        }
     }
 
     pub fn at(&self, t: f64) -> Vector3<f64> {
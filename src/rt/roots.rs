@@ -0,0 +1,236 @@
+//! Real roots of low degree polynomials, following Jochen Schwarze's
+//! "Cubic and Quartic Roots" (Graphics Gems, 1990).
+
+const EPSILON: f64 = 1e-12;
+
+fn is_zero(x: f64) -> bool {
+    x.abs() < EPSILON
+}
+
+/// Roots of `a*x^2 + b*x + c`, smallest first
+pub fn solve_quadratic(a: f64, b: f64, c: f64) -> Option<(f64, f64)> {
+    if is_zero(a) {
+        if is_zero(b) {
+            return None;
+        }
+        let x = -c / b;
+        return Some((x, x));
+    }
+
+    let discriminant = b * b - 4.0 * a * c;
+    if discriminant < 0.0 {
+        return None;
+    }
+
+    // avoids the cancellation of (-b + sqrt(discriminant)) when b is large
+    let q = -0.5 * (b + b.signum() * discriminant.sqrt());
+    let (x0, x1) = if is_zero(q) {
+        (0.0, 0.0)
+    } else {
+        (q / a, c / q)
+    };
+    Some((x0.min(x1), x0.max(x1)))
+}
+
+/// Real roots of `x^3 + a*x^2 + b*x + c`, in no particular order
+pub fn solve_normalized_cubic(a: f64, b: f64, c: f64) -> Vec<f64> {
+    // substitute x = y - a/3 to eliminate the quadric term: y^3 + p*y + q = 0
+    let sq_a = a * a;
+    let p = 1.0 / 3.0 * (-1.0 / 3.0 * sq_a + b);
+    let q = 1.0 / 2.0 * (2.0 / 27.0 * a * sq_a - 1.0 / 3.0 * a * b + c);
+
+    let cb_p = p * p * p;
+    let d = q * q + cb_p;
+
+    let mut roots = if is_zero(d) {
+        if is_zero(q) {
+            vec![0.0]
+        } else {
+            let u = (-q).cbrt();
+            vec![2.0 * u, -u]
+        }
+    } else if d < 0.0 {
+        let phi = 1.0 / 3.0 * (-q / (-cb_p).sqrt()).clamp(-1.0, 1.0).acos();
+        let t = 2.0 * (-p).sqrt();
+        let third = std::f64::consts::FRAC_PI_3;
+        vec![
+            t * phi.cos(),
+            -t * (phi + third).cos(),
+            -t * (phi - third).cos(),
+        ]
+    } else {
+        let sqrt_d = d.sqrt();
+        vec![(sqrt_d - q).cbrt() - (sqrt_d + q).cbrt()]
+    };
+
+    let sub = 1.0 / 3.0 * a;
+    roots.iter_mut().for_each(|root| *root -= sub);
+    roots
+}
+
+/// Real roots of `x^4 + a*x^3 + b*x^2 + c*x + d`, smallest first
+pub fn solve_normalized_quartic(a: f64, b: f64, c: f64, d: f64) -> Vec<f64> {
+    // substitute x = y - a/4 to eliminate the cubic term: y^4 + p*y^2 + q*y + r = 0
+    let sq_a = a * a;
+    let p = -3.0 / 8.0 * sq_a + b;
+    let q = 1.0 / 8.0 * sq_a * a - 1.0 / 2.0 * a * b + c;
+    let r = -3.0 / 256.0 * sq_a * sq_a + 1.0 / 16.0 * sq_a * b - 1.0 / 4.0 * a * c + d;
+
+    let mut roots = Vec::with_capacity(4);
+    if is_zero(r) {
+        // no absolute term: y * (y^3 + p*y + q) = 0
+        roots.push(0.0);
+        roots.extend(solve_normalized_cubic(0.0, p, q));
+    } else {
+        // solve the resolvent cubic and take one of its roots to build two quadratics
+        let z =
+            solve_normalized_cubic(-1.0 / 2.0 * p, -r, 1.0 / 2.0 * r * p - 1.0 / 8.0 * q * q)[0];
+
+        let u = z * z - r;
+        let v = 2.0 * z - p;
+        let u = if is_zero(u) {
+            0.0
+        } else if u > 0.0 {
+            u.sqrt()
+        } else {
+            return roots;
+        };
+        let v = if is_zero(v) {
+            0.0
+        } else if v > 0.0 {
+            v.sqrt()
+        } else {
+            return roots;
+        };
+
+        let v = if q < 0.0 { -v } else { v };
+        if let Some((y0, y1)) = solve_quadratic(1.0, v, z - u) {
+            roots.extend([y0, y1]);
+        }
+        if let Some((y0, y1)) = solve_quadratic(1.0, -v, z + u) {
+            roots.extend([y0, y1]);
+        }
+    }
+
+    let sub = 1.0 / 4.0 * a;
+    roots.iter_mut().for_each(|root| {
+        *root -= sub;
+        // the closed form loses precision quickly, a couple of Newton steps fix it
+        for _ in 0..2 {
+            let x = *root;
+            let f = (((x + a) * x + b) * x + c) * x + d;
+            let df = ((4.0 * x + 3.0 * a) * x + 2.0 * b) * x + c;
+            if !is_zero(df) {
+                *root = x - f / df;
+            }
+        }
+    });
+    roots.sort_by(f64::total_cmp);
+    roots
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Vector3;
+
+    use super::*;
+    use crate::{
+        rt::ray::Ray,
+        shapes::{Shape, Torus},
+    };
+
+    fn assert_roots(roots: &[f64], expected: &[f64]) {
+        assert_eq!(roots.len(), expected.len(), "{roots:?} != {expected:?}");
+        for (root, expected) in roots.iter().zip(expected) {
+            assert!((root - expected).abs() < 1e-6, "{roots:?} != {expected:?}");
+        }
+    }
+
+    fn sorted(mut roots: Vec<f64>) -> Vec<f64> {
+        roots.sort_by(f64::total_cmp);
+        roots
+    }
+
+    #[test]
+    fn cubic_known_roots() {
+        // (x - 1)(x - 2)(x + 3)
+        assert_roots(
+            &sorted(solve_normalized_cubic(0.0, -7.0, 6.0)),
+            &[-3.0, 1.0, 2.0],
+        );
+        // (x - 2)(x^2 + 1)
+        assert_roots(&solve_normalized_cubic(-2.0, 1.0, -2.0), &[2.0]);
+    }
+
+    #[test]
+    fn cubic_repeated_roots() {
+        // (x - 1)^2 (x + 2)
+        assert_roots(
+            &sorted(solve_normalized_cubic(0.0, -3.0, 2.0)),
+            &[-2.0, 1.0],
+        );
+        // (x - 1)^3
+        assert_roots(&solve_normalized_cubic(-3.0, 3.0, -1.0), &[1.0]);
+    }
+
+    #[test]
+    fn quartic_known_roots() {
+        // (x + 2)(x + 1)(x - 1)(x - 3)
+        assert_roots(
+            &solve_normalized_quartic(-1.0, -7.0, 1.0, 6.0),
+            &[-2.0, -1.0, 1.0, 3.0],
+        );
+        // (x^2 - 4)(x^2 + 1)
+        assert_roots(
+            &solve_normalized_quartic(0.0, -3.0, 0.0, -4.0),
+            &[-2.0, 2.0],
+        );
+        // x^4 + 1
+        assert_roots(&solve_normalized_quartic(0.0, 0.0, 0.0, 1.0), &[]);
+    }
+
+    #[test]
+    fn quartic_repeated_roots() {
+        // (x - 1)^2 (x - 2)^2
+        let roots = solve_normalized_quartic(-6.0, 13.0, -12.0, 4.0);
+        assert!(!roots.is_empty());
+        for root in roots {
+            assert!(
+                (root - 1.0).abs() < 1e-6 || (root - 2.0).abs() < 1e-6,
+                "{root}"
+            );
+        }
+        // x^2 (x - 1)(x + 1)
+        assert_roots(
+            &solve_normalized_quartic(0.0, -1.0, 0.0, 0.0),
+            &[-1.0, 0.0, 0.0, 1.0],
+        );
+    }
+
+    #[test]
+    fn grazing_torus_ray() {
+        let torus = Torus::new(Vector3::zeros(), Vector3::y(), 1.0, 0.25);
+        let direction = Vector3::x();
+
+        // just under the top of the tube, the ray crosses it near x = -1
+        let ray = Ray::new(Vector3::new(-3.0, 0.25 - 1e-6, 0.0), direction);
+        let hit = torus.intersect(&ray, 0.0, f64::INFINITY).unwrap();
+        assert!((hit.distance - 2.0).abs() < 1e-2, "{}", hit.distance);
+
+        // just over it, the ray misses
+        let ray = Ray::new(Vector3::new(-3.0, 0.25 + 1e-6, 0.0), direction);
+        assert!(torus.intersect(&ray, 0.0, f64::INFINITY).is_none());
+
+        // through the hole, the ray crosses the tube four times
+        let ray = Ray::new(Vector3::new(-3.0, 0.0, 0.0), direction);
+        assert_roots(
+            &torus
+                .intervals(&ray)
+                .unwrap()
+                .iter()
+                .flat_map(|interval| [interval.enter, interval.exit])
+                .collect::<Vec<_>>(),
+            &[1.75, 2.25, 3.75, 4.25],
+        );
+    }
+}
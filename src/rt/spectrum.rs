@@ -0,0 +1,173 @@
+//! Spectral colors for the hero wavelength mode. Each camera sample carries
+//! four wavelengths spread evenly over the visible range from a random hero
+//! one, and spectra are stored as their values at those wavelengths in a
+//! `Vector4`.
+//!
+//! RGB colors are turned into smooth spectra following Smits: white, cyan,
+//! magenta, yellow, red, green and blue spectra, summed in the amounts given
+//! by the sorted components. Reflectances below 1 stay below 1. Samples go back
+//! to color through the CIE 1931 matching functions, using the multi-lobe fit of
+//! Wyman, Sloan and Shirley, then to linear sRGB white balanced so that a
+//! constant spectrum is white.
+
+use std::sync::OnceLock;
+
+use nalgebra::{Matrix3, Vector3, Vector4};
+
+/// Visible range, in nanometers
+pub const LAMBDA_MIN: f64 = 380.0;
+pub const LAMBDA_MAX: f64 = 720.0;
+
+/// Wavelengths sampled together
+pub const SAMPLES: usize = 4;
+
+/// Wavelength used for colors when rendering in RGB
+pub const LAMBDA_RGB: f64 = 550.0;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Wavelengths {
+    /// In nanometers, the first one is the hero
+    pub lambda: [f64; SAMPLES],
+    pdf: [f64; SAMPLES],
+}
+
+impl Wavelengths {
+    /// The hero wavelength at `u` in [0, 1) over the visible range, the
+    /// others evenly spaced after it
+    pub fn sample(u: f64) -> Wavelengths {
+        let range = LAMBDA_MAX - LAMBDA_MIN;
+        let lambda = std::array::from_fn(|i| {
+            let offset = (u + i as f64 / SAMPLES as f64).fract();
+            LAMBDA_MIN + offset * range
+        });
+        Wavelengths {
+            lambda,
+            pdf: [1.0 / range; SAMPLES],
+        }
+    }
+
+    pub fn hero(&self) -> f64 {
+        self.lambda[0]
+    }
+
+    /// Only keeps the hero wavelength, for when the path goes somewhere that
+    /// depends on it, like through a dispersive surface
+    pub fn terminate_secondary(&mut self) {
+        if self.pdf[1] == 0.0 {
+            return;
+        }
+        self.pdf[0] /= SAMPLES as f64;
+        self.pdf[1..].fill(0.0);
+    }
+
+    /// Color seen through a spectrum sampled at these wavelengths
+    pub fn to_xyz(&self, spectrum: &Vector4<f64>) -> Vector3<f64> {
+        let mut xyz = Vector3::zeros();
+        for i in 0..SAMPLES {
+            if self.pdf[i] > 0.0 {
+                xyz += cie_xyz(self.lambda[i]) * (spectrum[i] / self.pdf[i]);
+            }
+        }
+        xyz / (SAMPLES as f64 * normalization().0)
+    }
+}
+
+/// CIE 1931 color matching functions
+pub fn cie_xyz(lambda: f64) -> Vector3<f64> {
+    let g = |mean: f64, below: f64, above: f64| {
+        let t = (lambda - mean) / if lambda < mean { below } else { above };
+        (-0.5 * t * t).exp()
+    };
+    Vector3::new(
+        1.056 * g(599.8, 37.9, 31.0) + 0.362 * g(442.0, 16.0, 26.7) - 0.065 * g(501.1, 20.4, 26.2),
+        0.821 * g(568.8, 46.9, 40.5) + 0.286 * g(530.9, 16.3, 31.1),
+        1.217 * g(437.0, 11.8, 36.0) + 0.681 * g(459.0, 26.0, 13.8),
+    )
+}
+
+/// Integral of y over the visible range, which is the luminance of a
+/// constant 1, and that spectrum's RGB before white balancing
+fn normalization() -> &'static (f64, Vector3<f64>) {
+    static NORMALIZATION: OnceLock<(f64, Vector3<f64>)> = OnceLock::new();
+    NORMALIZATION.get_or_init(|| {
+        let xyz = integrate(cie_xyz);
+        (xyz.y, xyz_to_linear_srgb(&(xyz / xyz.y)))
+    })
+}
+
+fn integrate(f: impl Fn(f64) -> Vector3<f64>) -> Vector3<f64> {
+    const STEPS: usize = 1000;
+    let step = (LAMBDA_MAX - LAMBDA_MIN) / STEPS as f64;
+    (0..STEPS)
+        .map(|i| f(LAMBDA_MIN + (i as f64 + 0.5) * step) * step)
+        .sum()
+}
+
+fn xyz_to_linear_srgb(xyz: &Vector3<f64>) -> Vector3<f64> {
+    #[rustfmt::skip]
+    let matrix = Matrix3::new(
+        3.2404542, -1.5371385, -0.4985314,
+        -0.9692660, 1.8760108, 0.0415560,
+        0.0556434, -0.2040259, 1.0572252,
+    );
+    matrix * xyz
+}
+
+/// Linear sRGB, white balanced so a constant spectrum of 1 gives white
+pub fn xyz_to_rgb(xyz: &Vector3<f64>) -> Vector3<f64> {
+    xyz_to_linear_srgb(xyz).component_div(&normalization().1)
+}
+
+/// Smooth step from 0 below `start` to 1 above `end`
+fn rise(lambda: f64, start: f64, end: f64) -> f64 {
+    let t = ((lambda - start) / (end - start)).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+fn blue(lambda: f64) -> f64 {
+    1.0 - rise(lambda, 480.0, 510.0)
+}
+
+fn red(lambda: f64) -> f64 {
+    rise(lambda, 575.0, 605.0)
+}
+
+fn green(lambda: f64) -> f64 {
+    1.0 - blue(lambda) - red(lambda)
+}
+
+/// Value at `lambda` of a spectrum with the color `rgb`. Components between
+/// 0 and 1 give a spectrum between 0 and 1.
+pub fn rgb_to_spectrum(rgb: &Vector3<f64>, lambda: f64) -> f64 {
+    let (r, g, b) = (rgb.x.max(0.0), rgb.y.max(0.0), rgb.z.max(0.0));
+    // the smallest component is white, the middle one the mix of the two
+    // largest and the rest the largest alone
+    let (white, mix, mix_amount, single, single_amount) = if r <= g && r <= b {
+        if g <= b {
+            (r, 1.0 - red(lambda), g - r, blue(lambda), b - g)
+        } else {
+            (r, 1.0 - red(lambda), b - r, green(lambda), g - b)
+        }
+    } else if g <= r && g <= b {
+        if r <= b {
+            (g, 1.0 - green(lambda), r - g, blue(lambda), b - r)
+        } else {
+            (g, 1.0 - green(lambda), b - g, red(lambda), r - b)
+        }
+    } else if r <= g {
+        (b, 1.0 - blue(lambda), r - b, green(lambda), g - r)
+    } else {
+        (b, 1.0 - blue(lambda), g - b, red(lambda), r - g)
+    };
+    white + mix * mix_amount + single * single_amount
+}
+
+/// `rgb_to_spectrum` at every wavelength, the alpha channel is ignored
+pub fn upsample(rgb: &Vector4<f64>, wavelengths: &Wavelengths) -> Vector4<f64> {
+    let rgb = rgb.xyz();
+    Vector4::from(
+        wavelengths
+            .lambda
+            .map(|lambda| rgb_to_spectrum(&rgb, lambda)),
+    )
+}
@@ -0,0 +1,142 @@
+use nalgebra::{Matrix3, Matrix4, Point3, UnitQuaternion, Vector3};
+
+use super::ray::Ray;
+
+/// Affine transform applied as scale, then rotation, then translation
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Transform {
+    pub translation: Vector3<f64>,
+    pub rotation: UnitQuaternion<f64>,
+    pub scale: Vector3<f64>,
+}
+
+impl Transform {
+    pub fn new(
+        translation: Vector3<f64>,
+        rotation: UnitQuaternion<f64>,
+        scale: Vector3<f64>,
+    ) -> Transform {
+        Transform {
+            translation,
+            rotation,
+            scale,
+        }
+    }
+
+    pub fn from_translation(translation: Vector3<f64>) -> Transform {
+        Transform {
+            translation,
+            ..Default::default()
+        }
+    }
+
+    /// Decomposes an affine matrix, any shear it has is lost
+    pub fn from_matrix(matrix: &Matrix4<f64>) -> Transform {
+        let linear: Matrix3<f64> = matrix.fixed_view::<3, 3>(0, 0).into_owned();
+        let mut scale = Vector3::from_fn(|i, _| linear.column(i).norm());
+        if linear.determinant() < 0.0 {
+            scale.x = -scale.x;
+        }
+        let rotation = Matrix3::from_fn(|row, column| linear[(row, column)] / scale[column]);
+        Transform {
+            translation: matrix.fixed_view::<3, 1>(0, 3).into_owned(),
+            rotation: UnitQuaternion::from_matrix(&rotation),
+            scale,
+        }
+    }
+
+    pub fn matrix(&self) -> Matrix4<f64> {
+        Matrix4::new_translation(&self.translation)
+            * self.rotation.to_homogeneous()
+            * Matrix4::new_nonuniform_scaling(&self.scale)
+    }
+
+    /// Rotation as (roll, pitch, yaw) in degrees, the way it is edited
+    pub fn euler_degrees(&self) -> Vector3<f64> {
+        let (roll, pitch, yaw) = self.rotation.euler_angles();
+        Vector3::new(roll, pitch, yaw).map(f64::to_degrees)
+    }
+
+    pub fn set_euler_degrees(&mut self, angles: &Vector3<f64>) {
+        let angles = angles.map(f64::to_radians);
+        self.rotation = UnitQuaternion::from_euler_angles(angles.x, angles.y, angles.z);
+    }
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Transform {
+            translation: Vector3::zeros(),
+            rotation: UnitQuaternion::identity(),
+            scale: Vector3::repeat(1.0),
+        }
+    }
+}
+
+/// A transform with its matrices precomputed, ready to move rays and
+/// geometry between object and world space
+#[derive(Clone, Copy, Debug)]
+pub struct TransformMatrices {
+    pub object_to_world: Matrix4<f64>,
+    pub world_to_object: Matrix4<f64>,
+    /// Inverse transpose of the linear part, for transforming normals
+    pub normal_matrix: Matrix3<f64>,
+}
+
+impl TransformMatrices {
+    pub fn new(transform: &Transform) -> TransformMatrices {
+        TransformMatrices::from_matrix(transform.matrix())
+    }
+
+    /// Matrices of any affine transform, shear included
+    pub fn from_matrix(object_to_world: Matrix4<f64>) -> TransformMatrices {
+        let world_to_object = object_to_world
+            .try_inverse()
+            .unwrap_or_else(Matrix4::identity);
+        let normal_matrix = world_to_object.fixed_view::<3, 3>(0, 0).transpose();
+        TransformMatrices {
+            object_to_world,
+            world_to_object,
+            normal_matrix,
+        }
+    }
+
+    /// The ray in object space, its direction is not normalized so distances
+    /// along it match the ones in world space
+    pub fn ray_to_object(&self, ray: &Ray) -> Ray {
+        Ray::with_time(
+            self.world_to_object
+                .transform_point(&Point3::from(ray.origin))
+                .coords,
+            self.world_to_object.transform_vector(&ray.direction),
+            ray.time,
+        )
+    }
+
+    pub fn point_to_world(&self, point: &Vector3<f64>) -> Vector3<f64> {
+        self.object_to_world
+            .transform_point(&Point3::from(*point))
+            .coords
+    }
+
+    pub fn vector_to_world(&self, vector: &Vector3<f64>) -> Vector3<f64> {
+        self.object_to_world.transform_vector(vector)
+    }
+
+    pub fn normal_to_world(&self, normal: &Vector3<f64>) -> Vector3<f64> {
+        (self.normal_matrix * normal).normalize()
+    }
+
+    /// How much a small area around a point with the given object space
+    /// normal grows when moved to world space
+    pub fn area_scale(&self, normal: &Vector3<f64>) -> f64 {
+        let linear = self.object_to_world.fixed_view::<3, 3>(0, 0);
+        linear.determinant().abs() * (self.normal_matrix * normal).norm()
+    }
+}
+
+impl Default for TransformMatrices {
+    fn default() -> Self {
+        TransformMatrices::new(&Transform::default())
+    }
+}
@@ -1,31 +1,315 @@
-use nalgebra::{Vector3, Vector4};
+use std::{
+    error::Error,
+    fmt::{self, Write},
+    path::Path,
+};
 
+use nalgebra::{Vector2, Vector3, Vector4};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    camera::CameraPose,
+    rt::{aabb::Aabb, bvh::Bvh, onb::Onb, ray::Ray},
+    shapes::{Hit, Interval, Shape},
+};
+
+pub mod animation;
+pub mod description;
+pub mod gltf;
+pub mod ior;
+pub mod light;
+pub mod medium;
+pub mod texture;
+
+pub use animation::Animation;
+pub use ior::Ior;
+pub use light::Light;
+pub use medium::Medium;
+pub use texture::Texture;
+
+pub use crate::shapes::{
+    Capsule, Cone, Cuboid, Cylinder, Disk, Instance, Mesh, Plane, Rect, Sphere, Torus,
+};
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
 pub struct Material {
     pub albedo: Vector4<f64>,
     pub roughness: f64,
     pub metallic: f64,
     pub emission_color: Vector4<f64>,
     pub emission_power: f64,
+    /// Multiplies `albedo` when set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub albedo_texture: Option<Texture>,
+    /// Multiplies `roughness` when set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub roughness_texture: Option<Texture>,
+    /// Multiplies `metallic` when set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub metallic_texture: Option<Texture>,
+    /// Multiplies `emission_color` when set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub emission_texture: Option<Texture>,
+    /// Tangent space normals, +y pointing where `v` grows
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub normal_map: Option<Texture>,
+    /// Heights in the red channel
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bump_map: Option<Texture>,
+    /// Height of a white bump map texel, in world units
+    pub bump_strength: f64,
+    /// Chance for light to go through the surface instead of bouncing off
+    /// it, only the path tracers refract
+    pub transmission: f64,
+    /// Used by transmissive materials
+    pub ior: Ior,
 }
 
-pub struct Sphere {
-    pub position: Vector3<f64>,
-    pub radius: f64,
+/// A shape placed in the scene with the material it is rendered with
+pub struct Object {
+    pub shape: Box<dyn Shape>,
     pub material_index: usize,
 }
 
+/// Medium filling the inside of a closed shape, whose surface is invisible
+pub struct Volume {
+    pub shape: Box<dyn Shape>,
+    pub medium: Medium,
+}
+
+#[derive(Default)]
 pub struct Scene {
-    pub spheres: Vec<Sphere>,
+    pub objects: Vec<Object>,
     pub materials: Vec<Material>,
+    pub lights: Vec<Light>,
+    /// Medium filling all of space, like fog
+    pub medium: Option<Medium>,
+    pub volumes: Vec<Volume>,
+    /// Viewpoint the scene was authored with, if it has one
+    pub camera: Option<CameraPose>,
+    pub animation: Option<Animation>,
+    acceleration: Acceleration,
+}
+
+/// 64 bit FNV-1a, fed with formatted text, which unlike the standard hasher
+/// gives the same hash in every build
+pub(crate) struct Fnv1a(pub u64);
+
+impl Default for Fnv1a {
+    fn default() -> Self {
+        Fnv1a(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl Write for Fnv1a {
+    fn write_str(&mut self, text: &str) -> fmt::Result {
+        for byte in text.bytes() {
+            self.0 = (self.0 ^ byte as u64).wrapping_mul(0x0100_0000_01b3);
+        }
+        Ok(())
+    }
+}
+
+/// Top level of the BVH, over the objects that existed on the last `update`
+#[derive(Default)]
+struct Acceleration {
+    bvh: Bvh,
+    /// Object index of every BVH item
+    bounded: Vec<usize>,
+    /// Objects with infinite bounds, tested one by one
+    unbounded: Vec<usize>,
+    object_count: usize,
+}
+
+impl Object {
+    pub fn new(shape: impl Shape + 'static, material_index: usize) -> Object {
+        Object {
+            shape: Box::new(shape),
+            material_index,
+        }
+    }
 }
 
-impl Sphere {
-    pub fn new(position: Vector3<f64>, radius: f64) -> Sphere {
-        Sphere {
-            position,
-            radius,
-            material_index: 0,
+impl Volume {
+    pub fn new(shape: impl Shape + 'static, medium: Medium) -> Volume {
+        Volume {
+            shape: Box::new(shape),
+            medium,
+        }
+    }
+
+    /// Stretches of the ray inside the shape. Shapes that can't tell are
+    /// walked from surface to surface, using the normals to know whether the
+    /// ray goes in or out, so closed meshes work too.
+    pub fn intervals(&self, ray: &Ray) -> Vec<Interval> {
+        if let Some(intervals) = self.shape.intervals(ray) {
+            return intervals;
+        }
+
+        const MAX_CROSSINGS: usize = 64;
+        let mut intervals = Vec::new();
+        let mut enter = None;
+        let mut t = 0.0;
+        for _ in 0..MAX_CROSSINGS {
+            let Some(hit) = self.shape.intersect(ray, t, f64::MAX) else {
+                break;
+            };
+            let normal = self.shape.surface(ray, &hit).normal;
+            if normal.dot(&ray.direction) > 0.0 {
+                // leaving, the ray started inside if it never went in
+                let start = enter.take().unwrap_or(f64::NEG_INFINITY);
+                intervals.push(Interval::new(start, hit.distance));
+            } else if enter.is_none() {
+                enter = Some(hit.distance);
+            }
+            t = hit.distance + 1e-6 * hit.distance.abs().max(1.0);
         }
+        intervals
+    }
+}
+
+impl Scene {
+    pub fn new(materials: Vec<Material>) -> Scene {
+        Scene {
+            materials,
+            ..Default::default()
+        }
+    }
+
+    pub fn add(&mut self, shape: impl Shape + 'static, material_index: usize) -> usize {
+        self.objects.push(Object::new(shape, material_index));
+        self.objects.len() - 1
+    }
+
+    /// Hash of what the scene looks like: its objects, materials, lights and
+    /// media. It only depends on the scene, so it can be compared across
+    /// runs, for example to know whether a checkpoint belongs to it.
+    pub fn content_hash(&self) -> u64 {
+        let mut hasher = Fnv1a::default();
+        let shapes = self
+            .objects
+            .iter()
+            .map(|object| (&object.shape, Some(object.material_index)))
+            .chain(self.volumes.iter().map(|volume| (&volume.shape, None)));
+        for (shape, material) in shapes {
+            // shapes without parameters, like meshes, are told apart by
+            // their bounds
+            let _ = write!(
+                hasher,
+                "{}{:?}{:?}{:?};",
+                shape.name(),
+                shape.params(),
+                shape.bounds(),
+                material
+            );
+        }
+        let media = self.volumes.iter().map(|volume| &volume.medium);
+        let _ = write!(
+            hasher,
+            "{:?}{:?}{:?}{:?}",
+            self.materials,
+            self.lights,
+            self.medium,
+            media.collect::<Vec<_>>()
+        );
+        hasher.0
+    }
+
+    /// Poses the animated parameters at `time`, in seconds, and rebuilds the
+    /// acceleration structure when objects moved
+    pub fn animate(&mut self, time: f64) {
+        let Some(animation) = self.animation.take() else {
+            return;
+        };
+        animation.apply(self, time);
+        if animation
+            .tracks
+            .iter()
+            .any(|track| matches!(track.target, animation::Target::Object { .. }))
+        {
+            self.update();
+        }
+        self.animation = Some(animation);
+    }
+
+    /// Rebuilds the acceleration structure, needed after objects are edited
+    /// or removed. Objects added since the last update are still found, only
+    /// more slowly.
+    pub fn update(&mut self) {
+        let mut acceleration = Acceleration {
+            object_count: self.objects.len(),
+            ..Default::default()
+        };
+        let mut bounds = Vec::with_capacity(self.objects.len());
+        for (i, object) in self.objects.iter().enumerate() {
+            let object_bounds = object.shape.bounds();
+            if object_bounds.is_finite() {
+                acceleration.bounded.push(i);
+                bounds.push(object_bounds);
+            } else {
+                acceleration.unbounded.push(i);
+            }
+        }
+        acceleration.bvh = Bvh::build(&bounds);
+        self.acceleration = acceleration;
+    }
+
+    pub fn bounds(&self) -> Aabb {
+        self.objects
+            .iter()
+            .map(|object| object.shape.bounds())
+            .filter(Aabb::is_finite)
+            .fold(Aabb::empty(), |bounds, object| bounds.union(&object))
+    }
+
+    /// Closest object hit by the ray within `(t_min, t_max)`, with its index
+    pub fn intersect(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<(usize, Hit)> {
+        let acceleration = &self.acceleration;
+        let mut closest = acceleration
+            .bvh
+            .intersect(ray, t_min, t_max, |item, t_min, t_max| {
+                self.objects
+                    .get(acceleration.bounded[item])?
+                    .shape
+                    .intersect(ray, t_min, t_max)
+            })
+            .map(|(item, hit)| (acceleration.bounded[item], hit));
+        let mut t_max = closest.map_or(t_max, |(_, hit)| hit.distance);
+
+        let not_indexed = acceleration.object_count.min(self.objects.len())..self.objects.len();
+        let others = acceleration.unbounded.iter().copied().chain(not_indexed);
+        for i in others {
+            let Some(object) = self.objects.get(i) else {
+                continue;
+            };
+            if let Some(hit) = object.shape.intersect(ray, t_min, t_max) {
+                t_max = hit.distance;
+                closest = Some((i, hit));
+            }
+        }
+        closest
+    }
+
+    /// Media along the ray before `t_max`, with the stretch each one fills
+    pub fn media(&self, ray: &Ray, t_max: f64) -> Vec<(&Medium, Interval)> {
+        let mut media = Vec::new();
+        if let Some(medium) = &self.medium {
+            media.push((medium, Interval::new(0.0, t_max)));
+        }
+        for volume in &self.volumes {
+            if volume.shape.bounds().intersect(ray, 0.0, t_max).is_none() {
+                continue;
+            }
+            media.extend(
+                volume
+                    .intervals(ray)
+                    .into_iter()
+                    .filter(|interval| interval.exit > 0.0 && interval.enter < t_max)
+                    .map(|interval| (&volume.medium, interval)),
+            );
+        }
+        media
     }
 }
 
@@ -33,6 +317,114 @@ impl Material {
     pub fn get_emission(&self) -> Vector4<f64> {
         self.emission_color * self.emission_power
     }
+
+    pub fn albedo_at(&self, uv: &Vector2<f64>) -> Vector4<f64> {
+        match &self.albedo_texture {
+            Some(texture) => self.albedo.component_mul(&texture.sample(uv)),
+            None => self.albedo,
+        }
+    }
+
+    pub fn roughness_at(&self, uv: &Vector2<f64>) -> f64 {
+        match &self.roughness_texture {
+            Some(texture) => self.roughness * texture.sample(uv).x,
+            None => self.roughness,
+        }
+    }
+
+    pub fn metallic_at(&self, uv: &Vector2<f64>) -> f64 {
+        match &self.metallic_texture {
+            Some(texture) => self.metallic * texture.sample(uv).x,
+            None => self.metallic,
+        }
+    }
+
+    pub fn emission_at(&self, uv: &Vector2<f64>) -> Vector4<f64> {
+        match &self.emission_texture {
+            Some(texture) => self.get_emission().component_mul(&texture.sample(uv)),
+            None => self.get_emission(),
+        }
+    }
+
+    /// Normal used for shading after applying the normal and bump maps.
+    /// `derivatives` are the shape's `uv_derivatives`, they orient the maps.
+    pub fn shading_normal(
+        &self,
+        normal: &Vector3<f64>,
+        uv: &Vector2<f64>,
+        derivatives: Option<[Vector3<f64>; 2]>,
+    ) -> Vector3<f64> {
+        if self.normal_map.is_none() && self.bump_map.is_none() {
+            return *normal;
+        }
+
+        // tangent frame with u along dp/du and v on the side dp/dv points to
+        let (frame, lengths) = match derivatives {
+            Some([dpdu, dpdv]) => {
+                let tangent = (dpdu - normal * normal.dot(&dpdu)).normalize();
+                let bitangent = normal.cross(&tangent);
+                let handedness = bitangent.dot(&dpdv).signum();
+                let frame = Onb {
+                    u: tangent,
+                    v: bitangent * handedness,
+                    w: *normal,
+                };
+                (frame, Vector2::new(dpdu.norm(), dpdv.norm()))
+            }
+            None => (Onb::from_w(normal), Vector2::new(1.0, 1.0)),
+        };
+        if !frame.u.iter().all(|x| x.is_finite()) {
+            return *normal;
+        }
+
+        let mut local = match &self.normal_map {
+            Some(map) => (map.sample(uv).xyz() * 2.0).add_scalar(-1.0),
+            None => Vector3::z(),
+        };
+        if let Some(map) = &self.bump_map {
+            // slopes in world units from finite differences of the heights
+            const DELTA: f64 = 1.0 / 1024.0;
+            let height =
+                |du: f64, dv: f64| map.sample(&(uv + Vector2::new(du, dv))).x * self.bump_strength;
+            let center = height(0.0, 0.0);
+            let slope_u = (height(DELTA, 0.0) - center) / (DELTA * lengths.x);
+            let slope_v = (height(0.0, DELTA) - center) / (DELTA * lengths.y);
+            local = local.normalize();
+            local += Vector3::new(-slope_u, -slope_v, 0.0) * local.z;
+        }
+
+        let perturbed = frame.to_world(&local).normalize();
+        if perturbed.iter().all(|x| x.is_finite()) {
+            perturbed
+        } else {
+            *normal
+        }
+    }
+
+    /// Reads the images of the material's textures, see `Texture::load`
+    pub fn load_textures(&mut self, base: &Path) -> Result<(), Box<dyn Error>> {
+        // normal and bump maps hold data rather than colors
+        for texture in [&mut self.normal_map, &mut self.bump_map]
+            .into_iter()
+            .flatten()
+        {
+            if let Texture::Image(image) = texture {
+                image.linear = true;
+            }
+        }
+        let textures = [
+            &mut self.albedo_texture,
+            &mut self.roughness_texture,
+            &mut self.metallic_texture,
+            &mut self.emission_texture,
+            &mut self.normal_map,
+            &mut self.bump_map,
+        ];
+        for texture in textures.into_iter().flatten() {
+            texture.load(base)?;
+        }
+        Ok(())
+    }
 }
 
 impl Default for Material {
@@ -43,16 +435,15 @@ impl Default for Material {
             metallic: 0.0,
             emission_color: Vector4::new(0.0, 0.0, 0.0, 1.0),
             emission_power: 0.0,
-        }
-    }
-}
-
-impl Default for Sphere {
-    fn default() -> Self {
-        Self {
-            position: Default::default(),
-            radius: 0.5,
-            material_index: 0,
+            albedo_texture: None,
+            roughness_texture: None,
+            metallic_texture: None,
+            emission_texture: None,
+            normal_map: None,
+            bump_map: None,
+            bump_strength: 0.02,
+            transmission: 0.0,
+            ior: Ior::default(),
         }
     }
 }
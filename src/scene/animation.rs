@@ -0,0 +1,337 @@
+//! Keyframe animation of scene parameters. Every track moves one parameter of
+//! an object, a material or the camera through keys sorted by time, and the
+//! scene is posed at a time with `Scene::animate`.
+//!
+//! ```yaml
+//! animation:
+//!   fps: 24.0
+//!   tracks:
+//!     - target: { type: object, object: 0, param: position }
+//!       keys:
+//!         - { time: 0.0, value: [0.0, 0.0, 0.0], interpolation: bezier }
+//!         - { time: 2.0, value: [0.0, 1.0, 0.0] }
+//!     - target: { type: material, material: 1, param: emission_power }
+//!       keys:
+//!         - { time: 0.0, value: 0.0, interpolation: step }
+//!         - { time: 1.0, value: 5.0 }
+//! ```
+
+use std::error::Error;
+
+use nalgebra::{Vector3, Vector4};
+use serde::{Deserialize, Serialize};
+
+use crate::{camera::CameraPose, shapes::Param};
+
+use super::{Material, Scene};
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Animation {
+    /// Frames per second of rendered sequences
+    #[serde(default = "default_fps")]
+    pub fps: f64,
+    #[serde(default)]
+    pub tracks: Vec<Track>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Track {
+    pub target: Target,
+    /// Sorted by time, the value is held before the first one and after
+    /// the last one
+    pub keys: Vec<Key>,
+}
+
+/// Parameter moved by a track
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Target {
+    /// One of the shape's parameters, by the name shown in the object editor
+    Object { object: usize, param: String },
+    /// `albedo`, `roughness`, `metallic`, `emission_color`, `emission_power`,
+    /// `transmission` or `bump_strength`
+    Material { material: usize, param: String },
+    /// `position`, `forward_direction` or `vertical_fov`
+    Camera { param: String },
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Key {
+    /// In seconds
+    pub time: f64,
+    pub value: Value,
+    /// How the value goes from this key to the next one
+    #[serde(default)]
+    pub interpolation: Interpolation,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Value {
+    Scalar(f64),
+    Vector(Vec<f64>),
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Interpolation {
+    #[default]
+    Linear,
+    /// Smooth curve through the keys, with handles following the
+    /// neighboring keys like a Catmull-Rom spline, and flat at the ends
+    Bezier,
+    /// Holds the value until the next key
+    Step,
+}
+
+fn default_fps() -> f64 {
+    24.0
+}
+
+impl Default for Animation {
+    fn default() -> Self {
+        Animation {
+            fps: default_fps(),
+            tracks: Vec::new(),
+        }
+    }
+}
+
+impl Value {
+    pub fn components(&self) -> &[f64] {
+        match self {
+            Value::Scalar(value) => std::slice::from_ref(value),
+            Value::Vector(values) => values,
+        }
+    }
+}
+
+impl Animation {
+    /// Time of the last key
+    pub fn duration(&self) -> f64 {
+        self.tracks
+            .iter()
+            .filter_map(|track| track.keys.last())
+            .map(|key| key.time)
+            .fold(0.0, f64::max)
+    }
+
+    pub fn moves_camera(&self) -> bool {
+        self.tracks
+            .iter()
+            .any(|track| matches!(track.target, Target::Camera { .. }))
+    }
+
+    /// Number of frames until the last key, included
+    pub fn frame_count(&self) -> usize {
+        (self.duration() * self.fps).floor() as usize + 1
+    }
+
+    pub fn frame_time(&self, frame: usize) -> f64 {
+        frame as f64 / self.fps
+    }
+
+    /// Errors on tracks whose target does not exist in `scene` or can't take
+    /// their values
+    pub fn check(&self, scene: &Scene) -> Result<(), Box<dyn Error>> {
+        let mut scene_copy = Scene {
+            materials: scene.materials.clone(),
+            camera: scene.camera,
+            ..Default::default()
+        };
+        for (index, track) in self.tracks.iter().enumerate() {
+            if track
+                .keys
+                .windows(2)
+                .any(|keys| keys[0].time > keys[1].time)
+            {
+                return Err(format!("animation track {index}: keys are not sorted by time").into());
+            }
+            for key in &track.keys {
+                let applied = match &track.target {
+                    Target::Object { object, param } => {
+                        let Some(object) = scene.objects.get(*object) else {
+                            return Err(
+                                format!("animation track {index}: no object {object}").into()
+                            );
+                        };
+                        shape_param(object.shape.params(), param, key.value.components()).is_some()
+                    }
+                    _ => track.target.apply(&mut scene_copy, key.value.components()),
+                };
+                if !applied {
+                    return Err(format!(
+                        "animation track {index}: can't set {:?} to {:?}",
+                        track.target,
+                        key.value.components()
+                    )
+                    .into());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Sets every animated parameter to its value at `time`. Moved objects
+    /// need a `Scene::update` afterwards.
+    pub fn apply(&self, scene: &mut Scene, time: f64) {
+        for track in &self.tracks {
+            if let Some(value) = track.sample(time) {
+                track.target.apply(scene, &value);
+            }
+        }
+    }
+}
+
+impl Track {
+    /// Interpolated value at `time`, `None` without keys
+    pub fn sample(&self, time: f64) -> Option<Vec<f64>> {
+        let next = self.keys.partition_point(|key| key.time <= time);
+        if next == 0 || next == self.keys.len() {
+            let key = self.keys.get(next.saturating_sub(1))?;
+            return Some(key.value.components().to_vec());
+        }
+        let (start, end) = (&self.keys[next - 1], &self.keys[next]);
+        let (a, b) = (start.value.components(), end.value.components());
+        if a.len() != b.len() {
+            return Some(a.to_vec());
+        }
+        let t = match end.time > start.time {
+            true => (time - start.time) / (end.time - start.time),
+            false => 1.0,
+        };
+        let value = match start.interpolation {
+            Interpolation::Step => a.to_vec(),
+            Interpolation::Linear => (0..a.len()).map(|i| a[i] + (b[i] - a[i]) * t).collect(),
+            Interpolation::Bezier => {
+                let before = self.tangent(next - 1);
+                let after = self.tangent(next);
+                let span = end.time - start.time;
+                (0..a.len())
+                    .map(|i| {
+                        let handle_a = a[i] + before[i] * span / 3.0;
+                        let handle_b = b[i] - after[i] * span / 3.0;
+                        let s = 1.0 - t;
+                        s * s * s * a[i]
+                            + 3.0 * s * s * t * handle_a
+                            + 3.0 * s * t * t * handle_b
+                            + t * t * t * b[i]
+                    })
+                    .collect()
+            }
+        };
+        Some(value)
+    }
+
+    /// Slope of the curve at a key, from its neighbors
+    fn tangent(&self, index: usize) -> Vec<f64> {
+        let value = self.keys[index].value.components();
+        let (Some(previous), Some(next)) = (
+            index.checked_sub(1).and_then(|i| self.keys.get(i)),
+            self.keys.get(index + 1),
+        ) else {
+            return vec![0.0; value.len()];
+        };
+        let (a, b) = (previous.value.components(), next.value.components());
+        let span = next.time - previous.time;
+        if a.len() != value.len() || b.len() != value.len() || span <= 0.0 {
+            return vec![0.0; value.len()];
+        }
+        (0..value.len()).map(|i| (b[i] - a[i]) / span).collect()
+    }
+}
+
+impl Target {
+    /// Sets the parameter, false when it doesn't exist or `value` has the
+    /// wrong number of components
+    pub fn apply(&self, scene: &mut Scene, value: &[f64]) -> bool {
+        match self {
+            Target::Object { object, param } => {
+                let Some(object) = scene.objects.get_mut(*object) else {
+                    return false;
+                };
+                match shape_param(object.shape.params(), param, value) {
+                    Some(new) => object.shape.set_param(param, new),
+                    None => false,
+                }
+            }
+            Target::Material { material, param } => match scene.materials.get_mut(*material) {
+                Some(material) => set_material_param(material, param, value),
+                None => false,
+            },
+            Target::Camera { param } => {
+                let pose = scene.camera.get_or_insert_with(CameraPose::default);
+                set_camera_param(pose, param, value)
+            }
+        }
+    }
+}
+
+fn vector3(value: &[f64]) -> Option<Vector3<f64>> {
+    (value.len() == 3).then(|| Vector3::from_column_slice(value))
+}
+
+fn scalar(value: &[f64]) -> Option<f64> {
+    match value {
+        [value] => Some(*value),
+        _ => None,
+    }
+}
+
+/// Colors can leave out the alpha
+fn color(value: &[f64], current: &Vector4<f64>) -> Option<Vector4<f64>> {
+    match value.len() {
+        3 => Some(Vector3::from_column_slice(value).insert_row(3, current.w)),
+        4 => Some(Vector4::from_column_slice(value)),
+        _ => None,
+    }
+}
+
+/// The shape parameter `name` set to `value`, keeping its kind
+fn shape_param(params: Vec<(&'static str, Param)>, name: &str, value: &[f64]) -> Option<Param> {
+    let (_, current) = params.into_iter().find(|(param, _)| *param == name)?;
+    Some(match current {
+        Param::Point(_) => Param::Point(vector3(value)?),
+        Param::Direction(_) => Param::Direction(vector3(value)?),
+        Param::Rotation(_) => Param::Rotation(vector3(value)?),
+        Param::Scale(_) => Param::Scale(vector3(value)?),
+        Param::Length(_) => Param::Length(scalar(value)?),
+        Param::Fraction(_) => Param::Fraction(scalar(value)?.clamp(0.0, 1.0)),
+        Param::Count(_) => Param::Count(scalar(value)?.round().max(0.0) as usize),
+        Param::Flag(_) => Param::Flag(scalar(value)? >= 0.5),
+    })
+}
+
+fn set_material_param(material: &mut Material, name: &str, value: &[f64]) -> bool {
+    let set = |target: &mut f64| scalar(value).map(|value| *target = value);
+    let done = match name {
+        "albedo" => color(value, &material.albedo).map(|albedo| material.albedo = albedo),
+        "emission_color" => color(value, &material.emission_color)
+            .map(|emission| material.emission_color = emission),
+        "roughness" => set(&mut material.roughness),
+        "metallic" => set(&mut material.metallic),
+        "emission_power" => set(&mut material.emission_power),
+        "transmission" => set(&mut material.transmission),
+        "bump_strength" => set(&mut material.bump_strength),
+        _ => None,
+    };
+    done.is_some()
+}
+
+/// Sets a camera parameter as camera tracks do, false when `name` or the
+/// number of components is wrong, or when the pose would be degenerate
+pub fn set_camera_param(pose: &mut CameraPose, name: &str, value: &[f64]) -> bool {
+    let mut new = *pose;
+    let done = match name {
+        "position" => vector3(value).map(|position| new.position = position),
+        "forward_direction" => vector3(value).map(|direction| new.forward_direction = direction),
+        "vertical_fov" => scalar(value).map(|fov| new.vertical_fov = fov),
+        _ => None,
+    };
+    if done.is_none() || new.check().is_err() {
+        return false;
+    }
+    new.forward_direction = new.forward_direction.normalize();
+    *pose = new;
+    true
+}
@@ -0,0 +1,510 @@
+//! Scene files: a YAML document listing the materials and the objects of a
+//! scene, for example
+//!
+//! ```yaml
+//! materials:
+//!   - albedo: [1.0, 0.0, 1.0, 1.0]
+//!     roughness: 0.0
+//!   - albedo_texture: { type: image, path: wood.png, wrap: mirror }
+//!     roughness_texture:
+//!       type: checker
+//!       scale: 8.0
+//!       even: [0.2, 0.2, 0.2, 1.0]
+//!       odd: [1.0, 1.0, 1.0, 1.0]
+//!   - transmission: 1.0
+//!     roughness: 0.0
+//!     ior: { type: cauchy, a: 1.5046, b: 0.0042 }
+//! camera:
+//!   position: [0.0, 1.0, 6.0]
+//!   forward_direction: [0.0, -0.1, -1.0]
+//!   shutter: [0.0, 1.0]
+//!   end: { position: [0.5, 1.0, 6.0], forward_direction: [0.0, -0.1, -1.0] }
+//! lights:
+//!   - type: point
+//!     position: [0.0, 4.0, 0.0]
+//!     color: [1.0, 1.0, 1.0]
+//!     intensity: 20.0
+//! meshes:
+//!   bunny: { path: bunny.ply }
+//! objects:
+//!   - material: 0
+//!     shape: { type: sphere, position: [0.0, 0.0, 0.0], radius: 1.0 }
+//!   - shape:
+//!       type: instance
+//!       shape: { type: mesh, mesh: bunny }
+//!       translation: [1.0, 0.0, 0.0]
+//!       scale: [2.0, 2.0, 2.0]
+//!   - material: 1
+//!     shape: { type: mesh, path: teapot.ply }
+//!   - shape:
+//!       type: sdf
+//!       sdf: union
+//!       smoothness: 0.3
+//!       left: { sdf: sphere, center: [0.0, 1.0, 0.0], radius: 0.5 }
+//!       right: { sdf: box, center: [0.0, 0.5, 0.0], half_size: [0.4, 0.4, 0.4] }
+//!   - shape: { type: sphere, position: [-2.0, 0.0, 0.0], radius: 0.5, end_position: [-2.0, 1.0, 0.0] }
+//!   - shape:
+//!       type: csg
+//!       operation: difference
+//!       left: { type: sphere, position: [2.0, 0.0, 0.0], radius: 1.0 }
+//!       right: { type: box, min: [1.0, 0.0, -1.0], max: [3.0, 1.0, 1.0] }
+//! medium:
+//!   scattering: [0.02, 0.02, 0.02]
+//! volumes:
+//!   - shape: { type: sphere, position: [0.0, 1.0, -2.0], radius: 1.0 }
+//!     medium:
+//!       absorption: [0.1, 0.4, 0.8]
+//!       scattering: [2.0, 2.0, 2.0]
+//!       anisotropy: 0.6
+//!       density: { type: noise, scale: 2.0 }
+//!   - medium:
+//!       scattering: [4.0, 4.0, 4.0]
+//!       density:
+//!         type: grid
+//!         path: smoke.vol
+//!         translation: [0.0, 1.0, 0.0]
+//!         scale: [2.0, 2.0, 2.0]
+//! animation:
+//!   tracks:
+//!     - target: { type: camera, param: position }
+//!       keys:
+//!         - { time: 0.0, value: [0.0, 1.0, 6.0], interpolation: bezier }
+//!         - { time: 4.0, value: [3.0, 1.0, 5.0] }
+//! ```
+//!
+//! Meshes are read once and shared by every `mesh` shape naming them, or
+//! giving the same path, so instances of a mesh don't copy it. Volumes
+//! without a shape fill the box of their voxel grid. Animations are
+//! described in the `animation` module.
+
+use std::{
+    collections::BTreeMap,
+    error::Error,
+    fmt, fs,
+    path::{Component, Path, PathBuf},
+    sync::Arc,
+};
+
+use nalgebra::Vector3;
+use serde::{Deserialize, Serialize};
+
+use super::{medium::Density, Animation, Light, Material, Medium, Object, Scene, Texture, Volume};
+use crate::{
+    camera::CameraPose,
+    rt::transform::Transform,
+    shapes::{
+        Capsule, Cone, Csg, CsgOperation, Cuboid, Cylinder, Disk, Instance, Mesh, Plane, Rect, Sdf,
+        SdfShape, Shape, Sphere, Torus,
+    },
+};
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct SceneDescription {
+    #[serde(default)]
+    pub materials: Vec<Material>,
+    /// Shared geometry, by name
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub meshes: BTreeMap<String, MeshFile>,
+    #[serde(default)]
+    pub objects: Vec<ObjectDescription>,
+    #[serde(default)]
+    pub lights: Vec<Light>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub camera: Option<CameraPose>,
+    /// Fills all of space
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub medium: Option<Medium>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub volumes: Vec<VolumeDescription>,
+    /// See the `animation` module
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub animation: Option<Animation>,
+}
+
+/// PLY file of a mesh. Only the path is stored in scene files, the mesh is
+/// read by `load_resources`.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct MeshFile {
+    pub path: PathBuf,
+    #[serde(skip)]
+    pub mesh: Option<Arc<Mesh>>,
+}
+
+impl fmt::Debug for MeshFile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MeshFile")
+            .field("path", &self.path)
+            .field("loaded", &self.mesh.is_some())
+            .finish()
+    }
+}
+
+impl PartialEq for MeshFile {
+    fn eq(&self, other: &Self) -> bool {
+        let same_mesh = match (&self.mesh, &other.mesh) {
+            (Some(a), Some(b)) => Arc::ptr_eq(a, b),
+            (a, b) => a.is_none() && b.is_none(),
+        };
+        self.path == other.path && same_mesh
+    }
+}
+
+impl MeshFile {
+    /// Reads the mesh, a relative path starts at `base`
+    pub fn load(&mut self, base: &Path) -> Result<(), Box<dyn Error>> {
+        let mesh = Mesh::load_ply(base.join(&self.path))
+            .map_err(|err| format!("can't read '{}': {}", self.path.display(), err))?;
+        self.mesh = Some(Arc::new(mesh));
+        Ok(())
+    }
+}
+
+type Meshes = BTreeMap<String, MeshFile>;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ObjectDescription {
+    pub shape: ShapeDescription,
+    #[serde(default)]
+    pub material: usize,
+}
+
+/// Medium inside a closed shape
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct VolumeDescription {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub shape: Option<ShapeDescription>,
+    #[serde(default)]
+    pub medium: Medium,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ShapeDescription {
+    Sphere(Sphere),
+    Plane(Plane),
+    Disk(Disk),
+    #[serde(rename = "rectangle")]
+    Rect(Rect),
+    #[serde(rename = "box")]
+    Cuboid(Cuboid),
+    Cylinder(Cylinder),
+    Cone(Cone),
+    Capsule(Capsule),
+    Torus(Torus),
+    Sdf(Sdf),
+    /// One of the scene's `meshes` by name, or a PLY file of its own
+    Mesh {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        mesh: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        path: Option<PathBuf>,
+    },
+    Instance {
+        shape: Box<ShapeDescription>,
+        #[serde(default)]
+        translation: Vector3<f64>,
+        /// Euler angles in degrees
+        #[serde(default)]
+        rotation: Vector3<f64>,
+        #[serde(default = "unit_scale")]
+        scale: Vector3<f64>,
+    },
+    /// Boolean combination of shapes that have an inside
+    Csg {
+        #[serde(default)]
+        operation: CsgOperation,
+        left: Box<ShapeDescription>,
+        right: Box<ShapeDescription>,
+    },
+}
+
+fn unit_scale() -> Vector3<f64> {
+    Vector3::repeat(1.0)
+}
+
+impl SceneDescription {
+    pub fn from_yaml(text: &str) -> Result<SceneDescription, serde_yaml::Error> {
+        serde_yaml::from_str(text)
+    }
+
+    pub fn to_yaml(&self) -> Result<String, serde_yaml::Error> {
+        serde_yaml::to_string(self)
+    }
+
+    /// Reads a scene file and the images of its textures and the voxel
+    /// grids of its media, which are relative to it
+    pub fn load(path: impl AsRef<Path>) -> Result<SceneDescription, Box<dyn Error>> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path)?;
+        let mut description = SceneDescription::from_yaml(&text)?;
+        description.load_resources(path.parent().unwrap_or(Path::new("")))?;
+        Ok(description)
+    }
+
+    /// Reads the meshes, the images of the textures and the voxel grids of
+    /// the media, with relative paths starting at `base`
+    pub fn load_resources(&mut self, base: &Path) -> Result<(), Box<dyn Error>> {
+        self.name_mesh_files();
+        for mesh in self.meshes.values_mut() {
+            mesh.load(base)?;
+        }
+        for material in &mut self.materials {
+            material.load_textures(base)?;
+        }
+        let media = self
+            .medium
+            .iter_mut()
+            .chain(self.volumes.iter_mut().map(|volume| &mut volume.medium));
+        for medium in media {
+            medium.load(base)?;
+        }
+        Ok(())
+    }
+
+    /// Reads the resources like `load_resources`, refusing absolute paths and
+    /// paths going up with `..`, for documents that may not read any file
+    pub fn load_resources_within(&mut self, base: &Path) -> Result<(), Box<dyn Error>> {
+        self.name_mesh_files();
+        let meshes = self.meshes.values().map(|mesh| &mesh.path);
+        let textures = self
+            .materials
+            .iter()
+            .flat_map(|material| {
+                [
+                    &material.albedo_texture,
+                    &material.roughness_texture,
+                    &material.metallic_texture,
+                    &material.emission_texture,
+                    &material.normal_map,
+                    &material.bump_map,
+                ]
+            })
+            .filter_map(|texture| match texture {
+                Some(Texture::Image(image)) => Some(&image.path),
+                _ => None,
+            });
+        let grids = self
+            .medium
+            .iter()
+            .chain(self.volumes.iter().map(|volume| &volume.medium))
+            .filter_map(|medium| match &medium.density {
+                Density::Grid(grid) => Some(&grid.path),
+                _ => None,
+            });
+        for path in meshes.chain(textures).chain(grids) {
+            let within = path
+                .components()
+                .all(|component| matches!(component, Component::Normal(_) | Component::CurDir));
+            if !within {
+                return Err(
+                    format!("'{}' is outside of the scene's directory", path.display()).into(),
+                );
+            }
+        }
+        self.load_resources(base)
+    }
+
+    /// Moves the paths of mesh shapes to `meshes`, so shapes giving the same
+    /// path share the mesh
+    fn name_mesh_files(&mut self) {
+        let shapes = self
+            .objects
+            .iter_mut()
+            .map(|object| &mut object.shape)
+            .chain(
+                self.volumes
+                    .iter_mut()
+                    .filter_map(|volume| volume.shape.as_mut()),
+            );
+        for shape in shapes {
+            shape.name_mesh_files(&mut self.meshes);
+        }
+    }
+
+    /// Fails when an object uses a material that isn't in the list, or a
+    /// mesh that isn't loaded
+    pub fn build(self) -> Result<Scene, Box<dyn Error>> {
+        let meshes = &self.meshes;
+        let material_count = self.materials.len();
+        let mut scene = Scene::new(self.materials);
+        scene.objects = self
+            .objects
+            .into_iter()
+            .enumerate()
+            .map(|(i, object)| {
+                if object.material >= material_count {
+                    return Err(format!(
+                        "object {} ({}) uses material {}, which doesn't exist",
+                        i,
+                        object.shape.name(),
+                        object.material
+                    )
+                    .into());
+                }
+                Ok(Object {
+                    shape: object.shape.build(meshes)?,
+                    material_index: object.material,
+                })
+            })
+            .collect::<Result<_, Box<dyn Error>>>()?;
+        scene.lights = self.lights;
+        scene.camera = self.camera;
+        scene.medium = self.medium;
+        scene.animation = self.animation;
+        scene.volumes = Vec::with_capacity(self.volumes.len());
+        for volume in self.volumes {
+            let shape = match volume.shape {
+                Some(shape) => shape.build(meshes)?,
+                None => match &volume.medium.density {
+                    Density::Grid(grid) => match grid.bounds_shape() {
+                        Some(shape) => shape,
+                        None => continue,
+                    },
+                    _ => continue,
+                },
+            };
+            scene.volumes.push(Volume {
+                shape,
+                medium: volume.medium,
+            });
+        }
+        scene.update();
+        Ok(scene)
+    }
+}
+
+impl ShapeDescription {
+    /// Type of the shape, as written in scene files
+    pub fn name(&self) -> &'static str {
+        match self {
+            ShapeDescription::Sphere(_) => "sphere",
+            ShapeDescription::Plane(_) => "plane",
+            ShapeDescription::Disk(_) => "disk",
+            ShapeDescription::Rect(_) => "rectangle",
+            ShapeDescription::Cuboid(_) => "box",
+            ShapeDescription::Cylinder(_) => "cylinder",
+            ShapeDescription::Cone(_) => "cone",
+            ShapeDescription::Capsule(_) => "capsule",
+            ShapeDescription::Torus(_) => "torus",
+            ShapeDescription::Sdf(_) => "sdf",
+            ShapeDescription::Mesh { .. } => "mesh",
+            ShapeDescription::Instance { .. } => "instance",
+            ShapeDescription::Csg { .. } => "csg",
+        }
+    }
+
+    /// Fails when the shape uses a mesh that isn't in `meshes`, or isn't
+    /// loaded
+    pub fn build(self, meshes: &Meshes) -> Result<Box<dyn Shape>, Box<dyn Error>> {
+        Ok(match self {
+            ShapeDescription::Sphere(sphere) => Box::new(sphere),
+            ShapeDescription::Plane(plane) => Box::new(plane),
+            ShapeDescription::Disk(disk) => Box::new(disk),
+            ShapeDescription::Rect(rect) => Box::new(rect),
+            ShapeDescription::Cuboid(cuboid) => Box::new(cuboid),
+            ShapeDescription::Cylinder(cylinder) => Box::new(cylinder),
+            ShapeDescription::Cone(cone) => Box::new(cone),
+            ShapeDescription::Capsule(capsule) => Box::new(capsule),
+            ShapeDescription::Torus(torus) => Box::new(torus),
+            ShapeDescription::Sdf(sdf) => Box::new(SdfShape::new(sdf)),
+            // placed where the file has it
+            ShapeDescription::Mesh { .. } => {
+                Box::new(Instance::new(self.shared(meshes)?, Transform::default()))
+            }
+            ShapeDescription::Instance {
+                shape,
+                translation,
+                rotation,
+                scale,
+            } => {
+                let mut transform = Transform {
+                    translation,
+                    scale,
+                    ..Default::default()
+                };
+                transform.set_euler_degrees(&rotation);
+                Box::new(Instance::new(shape.shared(meshes)?, transform))
+            }
+            ShapeDescription::Csg {
+                operation,
+                left,
+                right,
+            } => Box::new(Csg::new(
+                operation,
+                left.build(meshes)?,
+                right.build(meshes)?,
+            )),
+        })
+    }
+
+    /// Adds the files of `mesh` shapes given by path to `meshes`, named by
+    /// their path, so that shapes with the same file share it
+    fn name_mesh_files(&mut self, meshes: &mut Meshes) {
+        match self {
+            ShapeDescription::Mesh { mesh: None, path } => {
+                if let Some(path) = path.take() {
+                    let name = path.display().to_string();
+                    meshes
+                        .entry(name.clone())
+                        .or_insert(MeshFile { path, mesh: None });
+                    *self = ShapeDescription::Mesh {
+                        mesh: Some(name),
+                        path: None,
+                    };
+                }
+            }
+            ShapeDescription::Instance { shape, .. } => shape.name_mesh_files(meshes),
+            ShapeDescription::Csg { left, right, .. } => {
+                left.name_mesh_files(meshes);
+                right.name_mesh_files(meshes);
+            }
+            _ => {}
+        }
+    }
+
+    /// Shape to share between instances, meshes aren't copied
+    fn shared(self, meshes: &Meshes) -> Result<Arc<dyn Shape>, Box<dyn Error>> {
+        match self {
+            ShapeDescription::Mesh { mesh: None, .. } => {
+                Err("mesh shapes need a mesh name or a path".into())
+            }
+            ShapeDescription::Mesh {
+                mesh: Some(mesh), ..
+            } => {
+                let file = meshes
+                    .get(&mesh)
+                    .ok_or_else(|| format!("no mesh named '{mesh}'"))?;
+                let loaded = file
+                    .mesh
+                    .clone()
+                    .ok_or_else(|| format!("mesh '{mesh}' isn't loaded"))?;
+                Ok(loaded)
+            }
+            shape => Ok(Arc::from(shape.build(meshes)?)),
+        }
+    }
+}
+
+impl Scene {
+    /// Reads a scene file, see `SceneDescription`, a glTF file when the
+    /// extension is `.gltf` or `.glb`, or a single PLY mesh
+    pub fn load(path: impl AsRef<Path>) -> Result<Scene, Box<dyn Error>> {
+        let path = path.as_ref();
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("gltf" | "glb") => Scene::load_gltf(path),
+            Some("ply") => {
+                let mut scene = Scene::new(vec![Material::default()]);
+                scene.add(Mesh::load_ply(path)?, 0);
+                scene.update();
+                Ok(scene)
+            }
+            _ => {
+                let scene = SceneDescription::load(path)?.build()?;
+                if let Some(animation) = &scene.animation {
+                    animation.check(&scene)?;
+                }
+                Ok(scene)
+            }
+        }
+    }
+}
@@ -0,0 +1,306 @@
+//! glTF 2.0 import: meshes placed by the node hierarchy, the first
+//! perspective camera, `KHR_lights_punctual` lights and metallic-roughness
+//! materials
+
+use std::{error::Error, fs, path::Path, sync::Arc};
+
+use base64::Engine;
+use gltf::{
+    buffer::Source, camera::Projection, image, khr_lights_punctual, mesh::Mode, texture, Document,
+    Gltf,
+};
+use nalgebra::{Matrix4, Vector2, Vector3, Vector4};
+
+use super::{
+    texture::{Image, ImageTexture, WrapMode},
+    Light, Material, Scene, Texture,
+};
+use crate::{
+    camera::CameraPose,
+    shapes::{Instance, Mesh, Shape},
+};
+
+/// Primitives of a glTF mesh, loaded once and instanced by every node using it
+type Primitives = Vec<(Arc<dyn Shape>, usize)>;
+
+impl Scene {
+    /// Imports the default scene of a `.gltf` or `.glb` file
+    pub fn load_gltf(path: impl AsRef<Path>) -> Result<Scene, Box<dyn Error>> {
+        let path = path.as_ref();
+        let Gltf { document, blob } = Gltf::open(path)?;
+        let base = path.parent().unwrap_or(Path::new(""));
+        let buffers = load_buffers(&document, blob, base)?;
+        let images = load_images(&document, &buffers, base)?;
+
+        let mut materials: Vec<Material> = document
+            .materials()
+            .map(|material| convert_material(material, &images))
+            .collect();
+        let default_material = materials.len();
+        materials.push(Material::default());
+        let mut scene = Scene::new(materials);
+
+        let meshes: Vec<Primitives> = document
+            .meshes()
+            .map(|mesh| {
+                mesh.primitives()
+                    .filter(|primitive| primitive.mode() == Mode::Triangles)
+                    .filter_map(|primitive| {
+                        let material = primitive.material().index().unwrap_or(default_material);
+                        let mesh = convert_primitive(&primitive, &buffers)?;
+                        Some(mesh.map(|mesh| (Arc::new(mesh) as Arc<dyn Shape>, material)))
+                    })
+                    .collect::<Result<_, _>>()
+            })
+            .collect::<Result<_, _>>()?;
+
+        if let Some(root) = document
+            .default_scene()
+            .or_else(|| document.scenes().next())
+        {
+            for node in root.nodes() {
+                import_node(&mut scene, &meshes, &node, &Matrix4::identity());
+            }
+        }
+        scene.update();
+        Ok(scene)
+    }
+}
+
+/// None when the primitive has no positions, an error when its indices or
+/// attributes don't match them
+fn convert_primitive(
+    primitive: &gltf::Primitive,
+    buffers: &[Vec<u8>],
+) -> Option<Result<Mesh, Box<dyn Error>>> {
+    let reader = primitive.reader(|buffer| buffers.get(buffer.index()).map(Vec::as_slice));
+    let positions: Vec<_> = reader.read_positions()?.map(vector3).collect();
+    let normals: Vec<_> = reader
+        .read_normals()
+        .map(|normals| normals.map(vector3).collect())
+        .unwrap_or_default();
+    let uvs: Vec<_> = reader
+        .read_tex_coords(0)
+        .map(|uvs| {
+            uvs.into_f32()
+                // glTF images start at the top
+                .map(|[u, v]| Vector2::new(u as f64, 1.0 - v as f64))
+                .collect()
+        })
+        .unwrap_or_default();
+    let colors: Vec<_> = reader
+        .read_colors(0)
+        .map(|colors| {
+            colors
+                .into_rgba_f32()
+                .map(|c| Vector4::from(c).cast())
+                .collect()
+        })
+        .unwrap_or_default();
+    let indices: Vec<u32> = match reader.read_indices() {
+        Some(indices) => indices.into_u32().collect(),
+        None => (0..positions.len() as u32).collect(),
+    };
+
+    let vertex_count = positions.len();
+    let attribute_counts = [normals.len(), uvs.len(), colors.len()];
+    if attribute_counts
+        .iter()
+        .any(|&count| count != 0 && count != vertex_count)
+    {
+        return Some(Err("primitive attributes don't match its positions".into()));
+    }
+    if indices.iter().any(|&i| i as usize >= vertex_count) {
+        return Some(Err("primitive refers to a vertex that doesn't exist".into()));
+    }
+    let triangles = indices
+        .chunks_exact(3)
+        .map(|triangle| [triangle[0], triangle[1], triangle[2]])
+        .collect();
+
+    let mut mesh = Mesh::with_attributes(positions, normals, uvs, triangles);
+    mesh.colors = colors;
+    Some(Ok(mesh))
+}
+
+fn import_node(scene: &mut Scene, meshes: &[Primitives], node: &gltf::Node, parent: &Matrix4<f64>) {
+    let matrix = parent * Matrix4::from(node.transform().matrix()).cast::<f64>();
+    let position = matrix.fixed_view::<3, 1>(0, 3).into_owned();
+    // cameras and lights point down their local -z axis
+    let forward = -matrix.fixed_view::<3, 1>(0, 2).normalize();
+
+    if let Some(mesh) = node.mesh() {
+        for (shape, material) in &meshes[mesh.index()] {
+            scene.add(Instance::from_matrix(shape.clone(), matrix), *material);
+        }
+    }
+
+    if let Some(light) = node.light() {
+        scene.lights.push(convert_light(&light, position, forward));
+    }
+
+    if let (None, Some(camera)) = (scene.camera, node.camera()) {
+        if let Projection::Perspective(perspective) = camera.projection() {
+            scene.camera = Some(CameraPose {
+                position,
+                forward_direction: forward,
+                vertical_fov: (perspective.yfov() as f64).to_degrees(),
+                ..Default::default()
+            });
+        }
+    }
+
+    for child in node.children() {
+        import_node(scene, meshes, &child, &matrix);
+    }
+}
+
+fn convert_material(material: gltf::Material, images: &[Arc<Image>]) -> Material {
+    let pbr = material.pbr_metallic_roughness();
+    let wrap = |texture: &gltf::Texture| match texture.sampler().wrap_s() {
+        texture::WrappingMode::ClampToEdge => WrapMode::Clamp,
+        texture::WrappingMode::MirroredRepeat => WrapMode::Mirror,
+        texture::WrappingMode::Repeat => WrapMode::Repeat,
+    };
+    let convert_texture = |texture: gltf::Texture| {
+        let image = images[texture.source().index()].clone();
+        Texture::Image(ImageTexture::new(image, wrap(&texture)))
+    };
+    // roughness is in the green channel and metallic in the blue one
+    let metallic_roughness = pbr.metallic_roughness_texture().map(|info| info.texture());
+    let channel_texture = |channel: usize| {
+        let texture = metallic_roughness.as_ref()?;
+        let image = images[texture.source().index()].channel(channel);
+        Some(Texture::Image(ImageTexture::new(
+            Arc::new(image),
+            wrap(texture),
+        )))
+    };
+    let emissive = Vector3::from(material.emissive_factor()).cast::<f64>();
+    let emission_power = if emissive == Vector3::zeros() {
+        0.0
+    } else {
+        material.emissive_strength().unwrap_or(1.0) as f64
+    };
+    Material {
+        albedo: Vector4::from(pbr.base_color_factor()).cast(),
+        roughness: pbr.roughness_factor() as f64,
+        metallic: pbr.metallic_factor() as f64,
+        emission_color: emissive.insert_row(3, 1.0),
+        emission_power,
+        albedo_texture: pbr
+            .base_color_texture()
+            .map(|info| convert_texture(info.texture())),
+        roughness_texture: channel_texture(1),
+        metallic_texture: channel_texture(2),
+        emission_texture: material
+            .emissive_texture()
+            .map(|info| convert_texture(info.texture())),
+        normal_map: material
+            .normal_texture()
+            .map(|normal| convert_texture(normal.texture())),
+        ..Default::default()
+    }
+}
+
+fn convert_light(
+    light: &khr_lights_punctual::Light,
+    position: Vector3<f64>,
+    direction: Vector3<f64>,
+) -> Light {
+    let color = Vector3::from(light.color()).cast();
+    let intensity = light.intensity() as f64;
+    match light.kind() {
+        khr_lights_punctual::Kind::Point => Light::Point {
+            position,
+            color,
+            intensity,
+        },
+        khr_lights_punctual::Kind::Spot {
+            inner_cone_angle,
+            outer_cone_angle,
+        } => Light::Spot {
+            position,
+            direction,
+            color,
+            intensity,
+            inner_cone_angle: inner_cone_angle as f64,
+            outer_cone_angle: outer_cone_angle as f64,
+        },
+        khr_lights_punctual::Kind::Directional => Light::Directional {
+            direction,
+            color,
+            intensity,
+        },
+    }
+}
+
+fn load_buffers(
+    document: &Document,
+    mut blob: Option<Vec<u8>>,
+    base: &Path,
+) -> Result<Vec<Vec<u8>>, Box<dyn Error>> {
+    document
+        .buffers()
+        .map(|buffer| match buffer.source() {
+            Source::Bin => Ok(blob.take().ok_or("missing binary chunk")?),
+            Source::Uri(uri) => read_uri(uri, base),
+        })
+        .collect()
+}
+
+/// Images used by normal and metallic-roughness textures hold linear data,
+/// the others are assumed to be sRGB colors
+fn load_images(
+    document: &Document,
+    buffers: &[Vec<u8>],
+    base: &Path,
+) -> Result<Vec<Arc<Image>>, Box<dyn Error>> {
+    let linear_images: Vec<usize> = document
+        .materials()
+        .flat_map(|material| {
+            let normal = material.normal_texture().map(|info| info.texture());
+            let metallic_roughness = material
+                .pbr_metallic_roughness()
+                .metallic_roughness_texture()
+                .map(|info| info.texture());
+            [normal, metallic_roughness]
+        })
+        .flatten()
+        .map(|texture| texture.source().index())
+        .collect();
+    document
+        .images()
+        .map(|image| {
+            let linear = linear_images.contains(&image.index());
+            let image = match image.source() {
+                image::Source::View { view, .. } => {
+                    let bytes = buffers
+                        .get(view.buffer().index())
+                        .and_then(|buffer| buffer.get(view.offset()..view.offset() + view.length()))
+                        .ok_or("image is outside of its buffer")?;
+                    Image::decode(bytes, linear)?
+                }
+                image::Source::Uri { uri, .. } => Image::decode(&read_uri(uri, base)?, linear)?,
+            };
+            Ok(Arc::new(image))
+        })
+        .collect()
+}
+
+/// Contents of a `data:` uri or of a file relative to the glTF file
+fn read_uri(uri: &str, base: &Path) -> Result<Vec<u8>, Box<dyn Error>> {
+    match uri.strip_prefix("data:") {
+        Some(data) => {
+            let (_, encoded) = data
+                .split_once(";base64,")
+                .ok_or("only base64 data uris are supported")?;
+            Ok(base64::engine::general_purpose::STANDARD.decode(encoded)?)
+        }
+        None => Ok(fs::read(base.join(uri))?),
+    }
+}
+
+fn vector3(v: [f32; 3]) -> Vector3<f64> {
+    Vector3::from(v).cast()
+}
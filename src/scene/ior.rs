@@ -0,0 +1,53 @@
+use serde::{Deserialize, Serialize};
+
+/// Index of refraction of a dielectric, which can change with the wavelength
+/// (dispersion). Wavelengths are in nanometers, but the formulas take them
+/// in micrometers as they are usually tabulated.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Ior {
+    Constant {
+        value: f64,
+    },
+    /// `a + b / λ²`
+    Cauchy {
+        a: f64,
+        b: f64,
+    },
+    /// `sqrt(1 + Σ bᵢ λ² / (λ² - cᵢ))`
+    Sellmeier {
+        b: [f64; 3],
+        c: [f64; 3],
+    },
+}
+
+impl Default for Ior {
+    fn default() -> Self {
+        Ior::Constant { value: 1.5 }
+    }
+}
+
+impl Ior {
+    /// BK7 crown glass
+    pub const BK7: Ior = Ior::Sellmeier {
+        b: [1.039_612_12, 0.231_792_344, 1.010_469_45],
+        c: [0.006_000_698_67, 0.020_017_914_4, 103.560_653],
+    };
+
+    pub fn at(&self, lambda: f64) -> f64 {
+        let lambda_squared = (lambda / 1000.0).powi(2);
+        match self {
+            Ior::Constant { value } => *value,
+            Ior::Cauchy { a, b } => a + b / lambda_squared,
+            Ior::Sellmeier { b, c } => (1.0
+                + (0..3)
+                    .map(|i| b[i] * lambda_squared / (lambda_squared - c[i]))
+                    .sum::<f64>())
+            .sqrt(),
+        }
+    }
+
+    pub fn is_dispersive(&self) -> bool {
+        !matches!(self, Ior::Constant { .. })
+    }
+}
@@ -0,0 +1,103 @@
+use nalgebra::{Vector3, Vector4};
+use serde::{Deserialize, Serialize};
+
+/// Infinitely small light source, lit surfaces are found with shadow rays
+/// since rays can never hit these
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Light {
+    Point {
+        position: Vector3<f64>,
+        color: Vector3<f64>,
+        intensity: f64,
+    },
+    Spot {
+        position: Vector3<f64>,
+        direction: Vector3<f64>,
+        color: Vector3<f64>,
+        intensity: f64,
+        /// Angle from the direction where the falloff starts, in radians
+        inner_cone_angle: f64,
+        /// Angle from the direction where the light ends, in radians
+        outer_cone_angle: f64,
+    },
+    /// Light coming from infinitely far away, like the sun
+    Directional {
+        direction: Vector3<f64>,
+        color: Vector3<f64>,
+        intensity: f64,
+    },
+}
+
+/// Light arriving at a point
+pub struct LightSample {
+    /// Normalized direction towards the light
+    pub direction: Vector3<f64>,
+    /// Distance to the light, for shadow rays
+    pub distance: f64,
+    pub radiance: Vector4<f64>,
+}
+
+impl Light {
+    /// Light arriving at `point` ignoring occlusion, `None` when the point is
+    /// outside of what the light reaches
+    pub fn illuminate(&self, point: &Vector3<f64>) -> Option<LightSample> {
+        match self {
+            Light::Point {
+                position,
+                color,
+                intensity,
+            } => {
+                let to_light = position - point;
+                let distance = to_light.norm();
+                Some(LightSample {
+                    direction: to_light / distance,
+                    distance,
+                    radiance: radiance(color, intensity / (distance * distance)),
+                })
+            }
+            Light::Spot {
+                position,
+                direction,
+                color,
+                intensity,
+                inner_cone_angle,
+                outer_cone_angle,
+            } => {
+                let to_light = position - point;
+                let distance = to_light.norm();
+                let to_light = to_light / distance;
+
+                // smooth falloff between the cones, as glTF suggests
+                let cos_angle = -to_light.dot(&direction.normalize());
+                let (cos_outer, cos_inner) = (outer_cone_angle.cos(), inner_cone_angle.cos());
+                let falloff =
+                    ((cos_angle - cos_outer) / (cos_inner - cos_outer).max(1e-6)).clamp(0.0, 1.0);
+                if falloff == 0.0 {
+                    return None;
+                }
+                Some(LightSample {
+                    direction: to_light,
+                    distance,
+                    radiance: radiance(
+                        color,
+                        intensity * falloff * falloff / (distance * distance),
+                    ),
+                })
+            }
+            Light::Directional {
+                direction,
+                color,
+                intensity,
+            } => Some(LightSample {
+                direction: -direction.normalize(),
+                distance: f64::INFINITY,
+                radiance: radiance(color, *intensity),
+            }),
+        }
+    }
+}
+
+fn radiance(color: &Vector3<f64>, power: f64) -> Vector4<f64> {
+    (color * power).insert_row(3, 0.0)
+}
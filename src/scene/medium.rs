@@ -0,0 +1,287 @@
+//! Participating media: fog, smoke and anything else light travels through
+//! and scatters inside of instead of bouncing off a surface.
+//!
+//! Collisions are sampled with delta tracking against a majorant of the
+//! extinction and transmittance is estimated with ratio tracking, so
+//! heterogeneous media need no ray marching step size.
+
+use std::{error::Error, f64::consts::PI, path::Path};
+
+use nalgebra::Vector3;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    random::random_f64,
+    rt::{noise::fbm_3d, onb::Onb, ray::Ray},
+    shapes::Interval,
+};
+
+pub mod grid;
+
+pub use grid::{GridDensity, VoxelGrid};
+
+/// Coefficients are per unit of world distance, one for each color channel
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Medium {
+    pub absorption: Vector3<f64>,
+    pub scattering: Vector3<f64>,
+    /// Henyey-Greenstein asymmetry, from -1 (backwards) through 0
+    /// (isotropic) to 1 (forwards)
+    pub anisotropy: f64,
+    /// Scales both coefficients through space
+    pub density: Density,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Density {
+    #[default]
+    Uniform,
+    /// Fractal Perlin noise in space, from 0 to 1
+    Noise {
+        #[serde(default = "default_scale")]
+        scale: f64,
+        #[serde(default = "default_octaves")]
+        octaves: u32,
+    },
+    /// Values of a voxel grid file, see `grid`
+    Grid(GridDensity),
+}
+
+/// What happened to light going through media along a ray
+pub enum Collision<'a> {
+    /// Scattered at `distance` by `medium`
+    Scattered {
+        distance: f64,
+        medium: &'a Medium,
+        /// Throughput correction for the chromatic coefficients
+        weight: Vector3<f64>,
+    },
+    Absorbed,
+    /// Reached the end of the ray
+    Passed {
+        weight: Vector3<f64>,
+    },
+}
+
+/// Gives up on paths that keep hitting null collisions, they carry almost
+/// no light by then
+const MAX_STEPS: usize = 1024;
+
+fn default_scale() -> f64 {
+    1.0
+}
+
+fn default_octaves() -> u32 {
+    4
+}
+
+impl Medium {
+    /// Density at `point`, between 0 and `max_density`
+    pub fn density_at(&self, point: &Vector3<f64>) -> f64 {
+        match &self.density {
+            Density::Uniform => 1.0,
+            Density::Noise { scale, octaves } => {
+                (fbm_3d(&(point * *scale), *octaves) * 0.5 + 0.5).clamp(0.0, 1.0)
+            }
+            Density::Grid(grid) => grid.density_at(point),
+        }
+    }
+
+    pub fn max_density(&self) -> f64 {
+        match &self.density {
+            Density::Uniform | Density::Noise { .. } => 1.0,
+            Density::Grid(grid) => grid.max_density(),
+        }
+    }
+
+    /// Upper bound of the extinction anywhere in the medium
+    pub fn majorant(&self) -> f64 {
+        (self.absorption + self.scattering).max() * self.max_density()
+    }
+
+    /// Reads voxel grids, relative paths start at `base`
+    pub fn load(&mut self, base: &Path) -> Result<(), Box<dyn Error>> {
+        match &mut self.density {
+            Density::Grid(grid) => grid.load(base),
+            _ => Ok(()),
+        }
+    }
+
+    /// Henyey-Greenstein phase function between the direction light was
+    /// going and the one it leaves in, both normalized
+    pub fn phase(&self, incoming: &Vector3<f64>, outgoing: &Vector3<f64>) -> f64 {
+        let g = self.anisotropy;
+        let denominator = 1.0 + g * g - 2.0 * g * incoming.dot(outgoing);
+        (1.0 - g * g) / (4.0 * PI * denominator * denominator.sqrt())
+    }
+
+    /// Direction scattered light leaves in, distributed like `phase` so its
+    /// weight is one
+    pub fn sample_phase(&self, incoming: &Vector3<f64>, seed: &mut u32) -> Vector3<f64> {
+        let g = self.anisotropy;
+        let (u1, u2) = (random_f64(seed), random_f64(seed));
+        let cos_theta = if g.abs() < 1e-3 {
+            1.0 - 2.0 * u1
+        } else {
+            let s = (1.0 - g * g) / (1.0 + g - 2.0 * g * u1);
+            ((1.0 + g * g - s * s) / (2.0 * g)).clamp(-1.0, 1.0)
+        };
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        let phi = 2.0 * PI * u2;
+        let local = Vector3::new(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta);
+        Onb::from_w(incoming).to_world(&local)
+    }
+}
+
+impl Default for Medium {
+    fn default() -> Self {
+        Medium {
+            absorption: Vector3::zeros(),
+            scattering: Vector3::repeat(0.5),
+            anisotropy: 0.0,
+            density: Density::Uniform,
+        }
+    }
+}
+
+/// Finds the first real collision along `ray` before `t_max`, in the media
+/// filling each of the `segments`. Spectral delta tracking: events are picked
+/// with the coefficients averaged over the channels, weighted by how much
+/// light each one still carries, and the weight makes up for the difference.
+pub fn sample_collision<'a>(
+    segments: &[(&'a Medium, Interval)],
+    ray: &Ray,
+    t_max: f64,
+    seed: &mut u32,
+) -> Collision<'a> {
+    let mut weight = Vector3::repeat(1.0);
+    let Some((majorant, start, end)) = extent(segments, ray, t_max) else {
+        return Collision::Passed { weight };
+    };
+
+    let mut t = start;
+    for _ in 0..MAX_STEPS {
+        t -= (1.0 - random_f64(seed)).ln() / majorant;
+        if t >= end {
+            return Collision::Passed { weight };
+        }
+
+        let point = ray.at(t);
+        let (absorption, scattering) = coefficients(segments, ray, t, &point);
+        let null = Vector3::repeat(majorant) - absorption - scattering;
+        // probabilities follow the channels that still carry light
+        let total = weight.sum() * majorant;
+        let p_absorb = absorption.dot(&weight) / total;
+        let p_scatter = scattering.dot(&weight) / total;
+        let p_null = null.dot(&weight) / total;
+
+        let u = random_f64(seed);
+        if u < p_absorb {
+            return Collision::Absorbed;
+        } else if u < p_absorb + p_scatter {
+            // pick the medium that scattered in proportion to its share
+            let mut pick = random_f64(seed) * scattering.mean();
+            let mut medium = segments[0].0;
+            for (candidate, interval) in segments {
+                if t < interval.enter || t >= interval.exit {
+                    continue;
+                }
+                medium = candidate;
+                pick -= candidate.scattering.mean() * candidate.density_at(&point) * ray_scale(ray);
+                if pick <= 0.0 {
+                    break;
+                }
+            }
+            weight.component_mul_assign(&(scattering / (majorant * p_scatter)));
+            return Collision::Scattered {
+                distance: t,
+                medium,
+                weight,
+            };
+        } else {
+            weight.component_mul_assign(&(null / (majorant * p_null)));
+        }
+    }
+    Collision::Absorbed
+}
+
+/// Fraction of light getting through the media along `ray` up to `t_max`,
+/// estimated with ratio tracking
+pub fn transmittance(
+    segments: &[(&Medium, Interval)],
+    ray: &Ray,
+    t_max: f64,
+    seed: &mut u32,
+) -> Vector3<f64> {
+    let mut transmittance = Vector3::repeat(1.0);
+    let Some((majorant, start, end)) = extent(segments, ray, t_max) else {
+        return transmittance;
+    };
+
+    let mut t = start;
+    for _ in 0..MAX_STEPS {
+        t -= (1.0 - random_f64(seed)).ln() / majorant;
+        if t >= end {
+            return transmittance;
+        }
+        let point = ray.at(t);
+        let (absorption, scattering) = coefficients(segments, ray, t, &point);
+        transmittance
+            .component_mul_assign(&(Vector3::repeat(1.0) - (absorption + scattering) / majorant));
+        if transmittance.max() < 1e-4 {
+            break;
+        }
+    }
+    Vector3::zeros()
+}
+
+/// Combined majorant and the part of the ray covered by any of the segments,
+/// all in units of the ray direction
+fn extent(segments: &[(&Medium, Interval)], ray: &Ray, t_max: f64) -> Option<(f64, f64, f64)> {
+    let majorant = segments
+        .iter()
+        .map(|(medium, _)| medium.majorant())
+        .sum::<f64>()
+        * ray_scale(ray);
+    let start = segments
+        .iter()
+        .map(|(_, interval)| interval.enter)
+        .fold(f64::INFINITY, f64::min)
+        .max(0.0);
+    let end = segments
+        .iter()
+        .map(|(_, interval)| interval.exit)
+        .fold(f64::NEG_INFINITY, f64::max)
+        .min(t_max);
+    (majorant > 0.0 && start < end).then_some((majorant, start, end))
+}
+
+/// Absorption and scattering of all the media at `t`, per unit of the ray
+/// direction
+fn coefficients(
+    segments: &[(&Medium, Interval)],
+    ray: &Ray,
+    t: f64,
+    point: &Vector3<f64>,
+) -> (Vector3<f64>, Vector3<f64>) {
+    segments
+        .iter()
+        .filter(|(_, interval)| interval.enter <= t && t < interval.exit)
+        .fold(
+            (Vector3::zeros(), Vector3::zeros()),
+            |(absorption, scattering), (medium, _)| {
+                let density = medium.density_at(point) * ray_scale(ray);
+                (
+                    absorption + medium.absorption * density,
+                    scattering + medium.scattering * density,
+                )
+            },
+        )
+}
+
+/// World distance covered by one unit of the ray parameter
+fn ray_scale(ray: &Ray) -> f64 {
+    ray.direction.norm()
+}
@@ -0,0 +1,267 @@
+//! Dense voxel grids of densities, read from two kinds of files:
+//!
+//! - Mitsuba `.vol` grids: the bytes `VOL` and the version `3`, then little
+//!   endian `i32`s for the encoding (1 for `f32`, 3 for `u8`), the x, y and z
+//!   resolutions and the channel count, six `f32`s for the box the grid fills
+//!   (min x, y, z then max x, y, z) and the values. Only the first channel is
+//!   used.
+//! - Headerless `.raw` files (any other extension) holding nothing but the
+//!   values, whose resolution and type come from the scene file. The grid
+//!   fills a box centered on the origin whose longest side is 1.
+//!
+//! Values go x first, then y, then z. Integer values are scaled to [0, 1].
+
+use std::{
+    error::Error,
+    fmt::{self, Debug},
+    fs,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use nalgebra::{Matrix4, Point3, Vector3};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    rt::transform::Transform,
+    shapes::{Cuboid, Instance, Shape},
+};
+
+/// Density from a voxel grid file placed in the scene
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct GridDensity {
+    pub path: PathBuf,
+    /// Voxel counts of `.raw` files
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub resolution: Option<[usize; 3]>,
+    /// Type of the values of `.raw` files
+    #[serde(default)]
+    pub value_type: ValueType,
+    /// Multiplies the values of the grid
+    #[serde(default = "unit")]
+    pub multiplier: f64,
+    #[serde(default)]
+    pub translation: Vector3<f64>,
+    /// Euler angles in degrees
+    #[serde(default)]
+    pub rotation: Vector3<f64>,
+    #[serde(default = "unit_scale")]
+    pub scale: Vector3<f64>,
+    #[serde(skip)]
+    pub grid: Option<Arc<VoxelGrid>>,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ValueType {
+    #[default]
+    U8,
+    U16,
+    F32,
+}
+
+/// Values of a grid and the box they fill, in the grid's own space
+#[derive(PartialEq)]
+pub struct VoxelGrid {
+    pub resolution: [usize; 3],
+    pub min: Vector3<f64>,
+    pub max: Vector3<f64>,
+    values: Vec<f32>,
+    max_value: f64,
+    /// Set when the grid is placed in the scene
+    world_to_grid: Matrix4<f64>,
+}
+
+fn unit() -> f64 {
+    1.0
+}
+
+fn unit_scale() -> Vector3<f64> {
+    Vector3::repeat(1.0)
+}
+
+impl GridDensity {
+    pub fn transform(&self) -> Transform {
+        let mut transform = Transform {
+            translation: self.translation,
+            scale: self.scale,
+            ..Default::default()
+        };
+        transform.set_euler_degrees(&self.rotation);
+        transform
+    }
+
+    /// Reads the grid file, a relative path starts at `base`
+    pub fn load(&mut self, base: &Path) -> Result<(), Box<dyn Error>> {
+        let bytes = fs::read(base.join(&self.path))
+            .map_err(|err| format!("can't read '{}': {}", self.path.display(), err))?;
+        let mut grid = if bytes.starts_with(b"VOL") {
+            VoxelGrid::from_vol(&bytes)?
+        } else {
+            let resolution = self.resolution.ok_or("raw voxel grids need a resolution")?;
+            VoxelGrid::from_raw(&bytes, resolution, self.value_type)?
+        };
+        grid.world_to_grid = self
+            .transform()
+            .matrix()
+            .try_inverse()
+            .ok_or("voxel grid transform can't be inverted")?;
+        self.grid = Some(Arc::new(grid));
+        Ok(())
+    }
+
+    /// Zero until the grid is loaded
+    pub fn density_at(&self, point: &Vector3<f64>) -> f64 {
+        match &self.grid {
+            Some(grid) => grid.sample_world(point) * self.multiplier,
+            None => 0.0,
+        }
+    }
+
+    pub fn max_density(&self) -> f64 {
+        self.grid
+            .as_ref()
+            .map_or(0.0, |grid| grid.max_value * self.multiplier)
+    }
+
+    /// Box the grid fills in the scene, once it is loaded
+    pub fn bounds_shape(&self) -> Option<Box<dyn Shape>> {
+        let grid = self.grid.as_ref()?;
+        let cuboid: Arc<dyn Shape> = Arc::new(Cuboid::new(grid.min, grid.max));
+        Some(Box::new(Instance::new(cuboid, self.transform())))
+    }
+}
+
+impl VoxelGrid {
+    pub fn from_vol(bytes: &[u8]) -> Result<VoxelGrid, Box<dyn Error>> {
+        const HEADER: usize = 48;
+        if bytes.len() < HEADER || &bytes[..3] != b"VOL" || bytes[3] != 3 {
+            return Err("not a version 3 .vol file".into());
+        }
+        let int = |i: usize| i32::from_le_bytes(bytes[4 + 4 * i..8 + 4 * i].try_into().unwrap());
+        let float =
+            |i: usize| f32::from_le_bytes(bytes[24 + 4 * i..28 + 4 * i].try_into().unwrap()) as f64;
+
+        let value_type = match int(0) {
+            1 => ValueType::F32,
+            3 => ValueType::U8,
+            encoding => return Err(format!("unsupported .vol encoding {}", encoding).into()),
+        };
+        let resolution = [int(1), int(2), int(3)].map(|n| n.max(0) as usize);
+        let channels = int(4).max(1) as usize;
+        let values = decode(&bytes[HEADER..], value_type, resolution, channels)?;
+        Ok(VoxelGrid::new(
+            resolution,
+            Vector3::new(float(0), float(1), float(2)),
+            Vector3::new(float(3), float(4), float(5)),
+            values,
+        ))
+    }
+
+    pub fn from_raw(
+        bytes: &[u8],
+        resolution: [usize; 3],
+        value_type: ValueType,
+    ) -> Result<VoxelGrid, Box<dyn Error>> {
+        let values = decode(bytes, value_type, resolution, 1)?;
+        let size = Vector3::from(resolution.map(|n| n as f64));
+        let half_size = size / size.max() / 2.0;
+        Ok(VoxelGrid::new(resolution, -half_size, half_size, values))
+    }
+
+    fn new(
+        resolution: [usize; 3],
+        min: Vector3<f64>,
+        max: Vector3<f64>,
+        values: Vec<f32>,
+    ) -> VoxelGrid {
+        let max_value = values.iter().copied().fold(0.0, f32::max) as f64;
+        VoxelGrid {
+            resolution,
+            min,
+            max,
+            values,
+            max_value,
+            world_to_grid: Matrix4::identity(),
+        }
+    }
+
+    /// Trilinear lookup of a point in the grid's space, zero outside of it
+    pub fn sample(&self, point: &Vector3<f64>) -> f64 {
+        let relative = (point - self.min).component_div(&(self.max - self.min));
+        if relative.iter().any(|x| !(0.0..=1.0).contains(x)) {
+            return 0.0;
+        }
+        let [nx, ny, nz] = self.resolution;
+        let voxel = relative.component_mul(&Vector3::new(nx as f64, ny as f64, nz as f64))
+            - Vector3::repeat(0.5);
+        let base = voxel.map(f64::floor);
+        let f = voxel - base;
+
+        let value = |dx: i64, dy: i64, dz: i64| {
+            let x = (base.x as i64 + dx).clamp(0, nx as i64 - 1) as usize;
+            let y = (base.y as i64 + dy).clamp(0, ny as i64 - 1) as usize;
+            let z = (base.z as i64 + dz).clamp(0, nz as i64 - 1) as usize;
+            self.values[(z * ny + y) * nx + x] as f64
+        };
+        let lerp = |a: f64, b: f64, t: f64| a + (b - a) * t;
+        let layer = |dz: i64| {
+            let front = lerp(value(0, 0, dz), value(1, 0, dz), f.x);
+            let back = lerp(value(0, 1, dz), value(1, 1, dz), f.x);
+            lerp(front, back, f.y)
+        };
+        lerp(layer(0), layer(1), f.z)
+    }
+
+    fn sample_world(&self, point: &Vector3<f64>) -> f64 {
+        let local = self.world_to_grid.transform_point(&Point3::from(*point));
+        self.sample(&local.coords)
+    }
+}
+
+impl Debug for VoxelGrid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let [x, y, z] = self.resolution;
+        write!(f, "VoxelGrid({}x{}x{})", x, y, z)
+    }
+}
+
+/// First channel of every voxel, integers scaled to [0, 1]
+fn decode(
+    bytes: &[u8],
+    value_type: ValueType,
+    resolution: [usize; 3],
+    channels: usize,
+) -> Result<Vec<f32>, Box<dyn Error>> {
+    let count = resolution
+        .iter()
+        .try_fold(1usize, |count, &size| count.checked_mul(size))
+        .ok_or("voxel grid is too large")?;
+    if count == 0 {
+        return Err("voxel grid is empty".into());
+    }
+    let size = match value_type {
+        ValueType::U8 => 1,
+        ValueType::U16 => 2,
+        ValueType::F32 => 4,
+    };
+    let stride = size * channels;
+    let length = count.checked_mul(stride).ok_or("voxel grid is too large")?;
+    if bytes.len() < length {
+        return Err(format!(
+            "voxel grid needs {} bytes of values, the file has {}",
+            length,
+            bytes.len()
+        )
+        .into());
+    }
+    Ok(bytes
+        .chunks_exact(stride)
+        .take(count)
+        .map(|voxel| match value_type {
+            ValueType::U8 => voxel[0] as f32 / u8::MAX as f32,
+            ValueType::U16 => u16::from_le_bytes([voxel[0], voxel[1]]) as f32 / u16::MAX as f32,
+            ValueType::F32 => f32::from_le_bytes([voxel[0], voxel[1], voxel[2], voxel[3]]),
+        })
+        .collect())
+}
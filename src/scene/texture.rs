@@ -0,0 +1,274 @@
+use std::{
+    error::Error,
+    fmt::{self, Debug},
+    fs,
+    io::Cursor,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use image::{codecs::hdr::HdrDecoder, ImageFormat};
+use nalgebra::{Vector2, Vector4};
+use serde::{Deserialize, Serialize};
+
+use crate::rt::noise::{fbm, worley};
+
+/// Color that varies over a surface, looked up with the texture coordinates
+/// of the hit. Scalar parameters like roughness use the red channel.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Texture {
+    Image(ImageTexture),
+    Checker {
+        #[serde(default = "default_scale")]
+        scale: f64,
+        even: Vector4<f64>,
+        odd: Vector4<f64>,
+    },
+    /// Blend from `start` to `end` as `uv · direction` goes from 0 to 1
+    Gradient {
+        start: Vector4<f64>,
+        end: Vector4<f64>,
+        #[serde(default = "default_direction")]
+        direction: Vector2<f64>,
+    },
+    Perlin {
+        #[serde(default = "default_scale")]
+        scale: f64,
+        #[serde(default = "default_octaves")]
+        octaves: u32,
+        low: Vector4<f64>,
+        high: Vector4<f64>,
+    },
+    /// Cellular noise, `low` at the cell centers
+    Worley {
+        #[serde(default = "default_scale")]
+        scale: f64,
+        low: Vector4<f64>,
+        high: Vector4<f64>,
+    },
+}
+
+/// How texture coordinates outside of [0, 1] are handled
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WrapMode {
+    #[default]
+    Repeat,
+    Clamp,
+    Mirror,
+}
+
+/// Image file sampled with bilinear filtering. Only the path is stored in
+/// scene files, the pixels are read by `load`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ImageTexture {
+    pub path: PathBuf,
+    #[serde(default)]
+    pub wrap: WrapMode,
+    /// Whether 8 and 16 bit images hold linear values (e.g. roughness) instead
+    /// of sRGB colors, HDR images are always linear
+    #[serde(default)]
+    pub linear: bool,
+    #[serde(skip)]
+    pub image: Option<Arc<Image>>,
+}
+
+/// Decoded pixels in linear color, row by row from the top
+#[derive(PartialEq)]
+pub struct Image {
+    pub width: u32,
+    pub height: u32,
+    pixels: Vec<Vector4<f32>>,
+}
+
+fn default_scale() -> f64 {
+    1.0
+}
+
+fn default_direction() -> Vector2<f64> {
+    Vector2::new(1.0, 0.0)
+}
+
+fn default_octaves() -> u32 {
+    4
+}
+
+impl Texture {
+    pub fn sample(&self, uv: &Vector2<f64>) -> Vector4<f64> {
+        match self {
+            Texture::Image(texture) => texture.sample(uv),
+            Texture::Checker { scale, even, odd } => {
+                let cell = (uv * *scale).map(f64::floor);
+                if (cell.x + cell.y).rem_euclid(2.0) < 1.0 {
+                    *even
+                } else {
+                    *odd
+                }
+            }
+            Texture::Gradient {
+                start,
+                end,
+                direction,
+            } => start.lerp(end, uv.dot(direction).clamp(0.0, 1.0)),
+            Texture::Perlin {
+                scale,
+                octaves,
+                low,
+                high,
+            } => low.lerp(high, fbm(&(uv * *scale), *octaves) * 0.5 + 0.5),
+            Texture::Worley { scale, low, high } => low.lerp(high, worley(&(uv * *scale)).min(1.0)),
+        }
+    }
+
+    /// Reads the pixels of image textures, relative paths start at `base`
+    pub fn load(&mut self, base: &Path) -> Result<(), Box<dyn Error>> {
+        match self {
+            Texture::Image(texture) => texture.load(base),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl ImageTexture {
+    /// Texture of an image that didn't come from a file of its own
+    pub fn new(image: Arc<Image>, wrap: WrapMode) -> ImageTexture {
+        ImageTexture {
+            path: PathBuf::new(),
+            wrap,
+            linear: false,
+            image: Some(image),
+        }
+    }
+
+    pub fn load(&mut self, base: &Path) -> Result<(), Box<dyn Error>> {
+        let bytes = fs::read(base.join(&self.path))
+            .map_err(|err| format!("can't read '{}': {}", self.path.display(), err))?;
+        self.image = Some(Arc::new(Image::decode(&bytes, self.linear)?));
+        Ok(())
+    }
+
+    /// Magenta until the image is loaded, to make missing textures obvious
+    pub fn sample(&self, uv: &Vector2<f64>) -> Vector4<f64> {
+        match &self.image {
+            Some(image) => image.sample(uv, self.wrap),
+            None => Vector4::new(1.0, 0.0, 1.0, 1.0),
+        }
+    }
+}
+
+impl Image {
+    /// Decodes any format supported by the `image` crate, 8 and 16 bit
+    /// images are converted from sRGB unless `linear` is set
+    pub fn decode(bytes: &[u8], linear: bool) -> Result<Image, Box<dyn Error>> {
+        if image::guess_format(bytes)? == ImageFormat::Hdr {
+            let decoder = HdrDecoder::new(Cursor::new(bytes))?;
+            let metadata = decoder.metadata();
+            let pixels = decoder
+                .read_image_hdr()?
+                .into_iter()
+                .map(|pixel| Vector4::new(pixel[0], pixel[1], pixel[2], 1.0))
+                .collect();
+            return Ok(Image {
+                width: metadata.width,
+                height: metadata.height,
+                pixels,
+            });
+        }
+
+        let image = image::load_from_memory(bytes)?.to_rgba16();
+        let to_linear = |value: u16| {
+            let value = value as f32 / u16::MAX as f32;
+            if linear {
+                value
+            } else {
+                srgb_to_linear(value)
+            }
+        };
+        let pixels = image
+            .pixels()
+            .map(|pixel| {
+                let [r, g, b, a] = pixel.0;
+                Vector4::new(
+                    to_linear(r),
+                    to_linear(g),
+                    to_linear(b),
+                    a as f32 / u16::MAX as f32,
+                )
+            })
+            .collect();
+        Ok(Image {
+            width: image.width(),
+            height: image.height(),
+            pixels,
+        })
+    }
+
+    /// Image of one channel of this one, in the red, green and blue
+    /// channels
+    pub fn channel(&self, channel: usize) -> Image {
+        Image {
+            width: self.width,
+            height: self.height,
+            pixels: self
+                .pixels
+                .iter()
+                .map(|pixel| {
+                    let value = pixel[channel];
+                    Vector4::new(value, value, value, 1.0)
+                })
+                .collect(),
+        }
+    }
+
+    /// Bilinear lookup, with `v` going up from the bottom row
+    pub fn sample(&self, uv: &Vector2<f64>, wrap: WrapMode) -> Vector4<f64> {
+        let x = uv.x * self.width as f64 - 0.5;
+        let y = (1.0 - uv.y) * self.height as f64 - 0.5;
+        let (x0, y0) = (x.floor(), y.floor());
+        let (tx, ty) = (x - x0, y - y0);
+        let (x0, y0) = (x0 as i64, y0 as i64);
+
+        let texel = |x: i64, y: i64| {
+            let x = wrap.apply(x, self.width as i64);
+            let y = wrap.apply(y, self.height as i64);
+            self.pixels[y * self.width as usize + x].cast::<f64>()
+        };
+        let top = texel(x0, y0).lerp(&texel(x0 + 1, y0), tx);
+        let bottom = texel(x0, y0 + 1).lerp(&texel(x0 + 1, y0 + 1), tx);
+        top.lerp(&bottom, ty)
+    }
+}
+
+impl Debug for Image {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Image({}x{})", self.width, self.height)
+    }
+}
+
+impl WrapMode {
+    /// Texel index for `i`, in an image `size` texels wide
+    fn apply(self, i: i64, size: i64) -> usize {
+        let i = match self {
+            WrapMode::Repeat => i.rem_euclid(size),
+            WrapMode::Clamp => i.clamp(0, size - 1),
+            WrapMode::Mirror => {
+                let i = i.rem_euclid(2 * size);
+                if i < size {
+                    i
+                } else {
+                    2 * size - 1 - i
+                }
+            }
+        };
+        i as usize
+    }
+}
+
+fn srgb_to_linear(value: f32) -> f32 {
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
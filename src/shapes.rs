@@ -0,0 +1,245 @@
+use std::f64::consts::PI;
+
+use nalgebra::{Vector2, Vector3, Vector4};
+
+use crate::{
+    random::random_f64,
+    rt::{aabb::Aabb, onb::Onb, ray::Ray, roots::solve_quadratic},
+};
+
+pub mod capsule;
+pub mod cone;
+pub mod csg;
+pub mod cuboid;
+pub mod cylinder;
+pub mod disk;
+pub mod instance;
+pub mod mesh;
+pub mod plane;
+pub mod rect;
+pub mod sdf;
+pub mod sphere;
+pub mod torus;
+
+pub use capsule::Capsule;
+pub use cone::Cone;
+pub use csg::{Csg, CsgOperation};
+pub use cuboid::Cuboid;
+pub use cylinder::Cylinder;
+pub use disk::Disk;
+pub use instance::Instance;
+pub use mesh::Mesh;
+pub use plane::Plane;
+pub use rect::Rect;
+pub use sdf::{Sdf, SdfShape};
+pub use sphere::Sphere;
+pub use torus::Torus;
+
+/// Where a ray met a shape, before any shading information is computed
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Hit {
+    /// Ray parameter of the intersection, in units of the ray direction
+    pub distance: f64,
+    /// Shape specific index (a triangle in a mesh, for example) handed back to
+    /// `Shape::surface`
+    pub primitive: usize,
+}
+
+/// Geometric information at a point on a shape
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Surface {
+    /// Unit normal, pointing out of the shape
+    pub normal: Vector3<f64>,
+    pub uv: Vector2<f64>,
+}
+
+/// Point picked on the surface of a shape
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ShapeSample {
+    pub position: Vector3<f64>,
+    pub normal: Vector3<f64>,
+    /// Probability density of the sample, with respect to surface area
+    pub pdf: f64,
+}
+
+/// Stretch of a ray between entering and leaving a closed shape, the
+/// distances can be negative
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Interval {
+    pub enter: f64,
+    pub exit: f64,
+}
+
+impl Interval {
+    pub fn new(enter: f64, exit: f64) -> Interval {
+        Interval { enter, exit }
+    }
+
+    /// Smallest interval containing all the crossings of a convex shape
+    pub(crate) fn spanning(crossings: impl Iterator<Item = f64>) -> Option<Interval> {
+        let (enter, exit) = crossings.fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), t| {
+            (lo.min(t), hi.max(t))
+        });
+        (enter < exit).then_some(Interval { enter, exit })
+    }
+}
+
+/// Editable parameter of a shape or an integrator, the variant tells how it
+/// should be edited
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Param {
+    Point(Vector3<f64>),
+    Direction(Vector3<f64>),
+    Length(f64),
+    Flag(bool),
+    /// Euler angles in degrees
+    Rotation(Vector3<f64>),
+    Scale(Vector3<f64>),
+    Count(usize),
+    /// Between 0 and 1
+    Fraction(f64),
+}
+
+/// Anything a ray can hit
+///
+/// Implement it to add new primitives, the renderer only talks to shapes
+/// through this trait.
+pub trait Shape: Send + Sync {
+    /// Short name of the kind of shape, used in the user interface
+    fn name(&self) -> &'static str;
+
+    /// Closest intersection with a distance in `(t_min, t_max)`
+    fn intersect(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<Hit>;
+
+    /// World space bounds, `Aabb::infinite()` for unbounded shapes
+    fn bounds(&self) -> Aabb;
+
+    /// Normal and texture coordinates where `hit` was found along `ray`
+    fn surface(&self, ray: &Ray, hit: &Hit) -> Surface;
+
+    /// Every stretch of the ray's line inside the shape, sorted and
+    /// disjoint, `None` for shapes without an inside (or that can't tell).
+    /// Used for constructive solid geometry.
+    fn intervals(&self, _ray: &Ray) -> Option<Vec<Interval>> {
+        None
+    }
+
+    /// How the position changes with the texture coordinates at `hit`, as
+    /// `[dp/du, dp/dv]`, to orient normal and bump maps. `None` lets the
+    /// renderer pick any frame around the normal.
+    fn uv_derivatives(&self, _ray: &Ray, _hit: &Hit) -> Option<[Vector3<f64>; 2]> {
+        None
+    }
+
+    /// Color at `hit` multiplying the material albedo, for shapes with per
+    /// vertex colors
+    fn color(&self, _ray: &Ray, _hit: &Hit) -> Option<Vector4<f64>> {
+        None
+    }
+
+    /// Picks a point uniformly distributed over the surface where it is at
+    /// `time`, `None` when the shape can't be sampled (e.g. it has an
+    /// infinite area)
+    fn sample(&self, _time: f64, _seed: &mut u32) -> Option<ShapeSample> {
+        None
+    }
+
+    /// Current values of the editable parameters
+    fn params(&self) -> Vec<(&'static str, Param)> {
+        Vec::new()
+    }
+
+    /// Updates a parameter returned by `params`, false if there is no
+    /// parameter with that name and type
+    fn set_param(&mut self, _name: &str, _value: Param) -> bool {
+        false
+    }
+}
+
+/// Smallest root of `a*t^2 + b*t + c` within `(t_min, t_max)` accepted by `inside`
+pub(crate) fn closest_quadratic_root(
+    a: f64,
+    b: f64,
+    c: f64,
+    t_min: f64,
+    t_max: f64,
+    inside: impl Fn(f64) -> bool,
+) -> Option<f64> {
+    let (t0, t1) = solve_quadratic(a, b, c)?;
+    [t0, t1]
+        .into_iter()
+        .find(|&t| t > t_min && t < t_max && inside(t))
+}
+
+/// Distance along the ray to the plane through `point` with the given normal
+pub(crate) fn hit_plane_at(ray: &Ray, point: &Vector3<f64>, normal: &Vector3<f64>) -> Option<f64> {
+    let denominator = normal.dot(&ray.direction);
+    if denominator.abs() < 1e-12 {
+        return None;
+    }
+    Some((point - ray.origin).dot(normal) / denominator)
+}
+
+/// Distance to a disk of `radius` centered on the local z axis at height `z`
+pub(crate) fn hit_local_cap(
+    origin: &Vector3<f64>,
+    direction: &Vector3<f64>,
+    z: f64,
+    radius: f64,
+) -> Option<f64> {
+    if direction.z.abs() < 1e-12 {
+        return None;
+    }
+    let t = (z - origin.z) / direction.z;
+    let x = origin.x + t * direction.x;
+    let y = origin.y + t * direction.y;
+    (x * x + y * y <= radius * radius).then_some(t)
+}
+
+/// Angle around the local z axis, mapped to [0, 1]
+pub(crate) fn azimuth(p: &Vector3<f64>) -> f64 {
+    0.5 + p.y.atan2(p.x) / (2.0 * PI)
+}
+
+/// Planar mapping of a disk of `radius` on the local xy plane to [0, 1]
+pub(crate) fn cap_uv(p: &Vector3<f64>, radius: f64) -> Vector2<f64> {
+    Vector2::new(p.x / radius, p.y / radius).add_scalar(1.0) * 0.5
+}
+
+/// Half extents of a disk of `radius` facing the unit vector `normal`
+pub(crate) fn disk_extent(normal: &Vector3<f64>, radius: f64) -> Vector3<f64> {
+    normal.map(|n| radius * (1.0 - n * n).max(0.0).sqrt())
+}
+
+/// Uniformly distributed point on a disk of `radius` on the local xy plane
+pub(crate) fn sample_local_disk(seed: &mut u32, radius: f64) -> Vector3<f64> {
+    let r = radius * random_f64(seed).sqrt();
+    let phi = 2.0 * PI * random_f64(seed);
+    Vector3::new(r * phi.cos(), r * phi.sin(), 0.0)
+}
+
+/// Uniformly distributed unit vector
+pub(crate) fn sample_unit_sphere(seed: &mut u32) -> Vector3<f64> {
+    let z = 1.0 - 2.0 * random_f64(seed);
+    let r = (1.0 - z * z).max(0.0).sqrt();
+    let phi = 2.0 * PI * random_f64(seed);
+    Vector3::new(r * phi.cos(), r * phi.sin(), z)
+}
+
+/// Point on a disk cap of an oriented shape, sampled uniformly
+pub(crate) fn sample_cap(
+    seed: &mut u32,
+    frame: &Onb,
+    center: &Vector3<f64>,
+    z: f64,
+    radius: f64,
+    normal: Vector3<f64>,
+    pdf: f64,
+) -> ShapeSample {
+    let p = sample_local_disk(seed, radius) + Vector3::new(0.0, 0.0, z);
+    ShapeSample {
+        position: center + frame.to_world(&p),
+        normal,
+        pdf,
+    }
+}
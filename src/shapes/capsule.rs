@@ -0,0 +1,170 @@
+use std::f64::consts::PI;
+
+use nalgebra::{Vector2, Vector3};
+use serde::{Deserialize, Serialize};
+
+use super::{azimuth, sample_unit_sphere, Hit, Interval, Param, Shape, ShapeSample, Surface};
+use crate::{
+    random::random_f64,
+    rt::{aabb::Aabb, onb::Onb, ray::Ray},
+};
+
+/// Every point within `radius` of the segment between `start` and `end`
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Capsule {
+    pub start: Vector3<f64>,
+    pub end: Vector3<f64>,
+    pub radius: f64,
+}
+
+impl Capsule {
+    pub fn new(start: Vector3<f64>, end: Vector3<f64>, radius: f64) -> Capsule {
+        Capsule { start, end, radius }
+    }
+}
+
+impl Capsule {
+    /// Where the line of the ray enters and leaves the capsule. It is the union
+    /// of a cylinder and two spheres, all convex, so it is entered at the first
+    /// of their entry points and left at the last of their exit points.
+    fn interval(&self, ray: &Ray) -> Option<Interval> {
+        let length = ray.direction.norm();
+        let rd = ray.direction / length;
+        let r2 = self.radius * self.radius;
+
+        let mut roots = Vec::with_capacity(6);
+        for center in [self.start, self.end] {
+            let oc = ray.origin - center;
+            let b = rd.dot(&oc);
+            let h = b * b - (oc.dot(&oc) - r2);
+            if h >= 0.0 {
+                roots.extend([-b - h.sqrt(), -b + h.sqrt()]);
+            }
+        }
+
+        // body, following Inigo Quilez's capsule intersection
+        let ba = self.end - self.start;
+        let oa = ray.origin - self.start;
+        let baba = ba.dot(&ba);
+        let bard = ba.dot(&rd);
+        let baoa = ba.dot(&oa);
+        let a = baba - bard * bard;
+        let b = baba * rd.dot(&oa) - baoa * bard;
+        let c = baba * oa.dot(&oa) - baoa * baoa - r2 * baba;
+        let h = b * b - a * c;
+        if h >= 0.0 && a.abs() > 1e-12 {
+            roots.extend(
+                [(-b - h.sqrt()) / a, (-b + h.sqrt()) / a]
+                    .into_iter()
+                    .filter(|t| (0.0..=baba).contains(&(baoa + t * bard))),
+            );
+        }
+
+        let enter = roots.iter().copied().reduce(f64::min)?;
+        let exit = roots.iter().copied().reduce(f64::max)?;
+        Some(Interval::new(enter / length, exit / length))
+    }
+}
+
+impl Default for Capsule {
+    fn default() -> Self {
+        Self::new(
+            Vector3::new(0.0, -0.5, 0.0),
+            Vector3::new(0.0, 0.5, 0.0),
+            0.25,
+        )
+    }
+}
+
+impl Shape for Capsule {
+    fn name(&self) -> &'static str {
+        "capsule"
+    }
+
+    fn intersect(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<Hit> {
+        let interval = self.interval(ray)?;
+        let distance = [interval.enter, interval.exit]
+            .into_iter()
+            .find(|&t| t > t_min && t < t_max)?;
+        Some(Hit {
+            distance,
+            primitive: 0,
+        })
+    }
+
+    fn intervals(&self, ray: &Ray) -> Option<Vec<Interval>> {
+        Some(self.interval(ray).into_iter().collect())
+    }
+
+    fn bounds(&self) -> Aabb {
+        let half_size = Vector3::repeat(self.radius);
+        Aabb::around(&self.start, &half_size).union(&Aabb::around(&self.end, &half_size))
+    }
+
+    fn surface(&self, ray: &Ray, hit: &Hit) -> Surface {
+        let ba = self.end - self.start;
+        let pa = ray.at(hit.distance) - self.start;
+        let h = (pa.dot(&ba) / ba.norm_squared()).clamp(0.0, 1.0);
+        let normal = (pa - h * ba) / self.radius;
+
+        // v runs from the tip of the start cap to the tip of the end cap
+        let p = Onb::from_w(&ba).to_local(&pa);
+        let v = (p.z + self.radius) / (ba.norm() + 2.0 * self.radius);
+        Surface {
+            normal,
+            uv: Vector2::new(azimuth(&p), v),
+        }
+    }
+
+    fn sample(&self, _time: f64, seed: &mut u32) -> Option<ShapeSample> {
+        let ba = self.end - self.start;
+        let length = ba.norm();
+        let body_area = 2.0 * PI * self.radius * length;
+        let caps_area = 4.0 * PI * self.radius * self.radius;
+        let pdf = 1.0 / (body_area + caps_area);
+
+        if random_f64(seed) * (body_area + caps_area) < body_area {
+            let frame = Onb::from_w(&ba);
+            let phi = 2.0 * PI * random_f64(seed);
+            let local_normal = Vector3::new(phi.cos(), phi.sin(), 0.0);
+            let normal = frame.to_world(&local_normal);
+            return Some(ShapeSample {
+                position: self.start + ba * random_f64(seed) + normal * self.radius,
+                normal,
+                pdf,
+            });
+        }
+
+        // the two hemispherical caps together make up a whole sphere
+        let normal = sample_unit_sphere(seed);
+        let center = if normal.dot(&ba) < 0.0 {
+            self.start
+        } else {
+            self.end
+        };
+        Some(ShapeSample {
+            position: center + normal * self.radius,
+            normal,
+            pdf,
+        })
+    }
+
+    fn params(&self) -> Vec<(&'static str, Param)> {
+        vec![
+            ("start", Param::Point(self.start)),
+            ("end", Param::Point(self.end)),
+            ("radius", Param::Length(self.radius)),
+        ]
+    }
+
+    fn set_param(&mut self, name: &str, value: Param) -> bool {
+        match (name, value) {
+            ("start", Param::Point(start)) => self.start = start,
+            ("end", Param::Point(end)) => self.end = end,
+            ("radius", Param::Length(radius)) => self.radius = radius,
+            _ => return false,
+        }
+        true
+    }
+}
@@ -0,0 +1,194 @@
+use std::f64::consts::PI;
+
+use nalgebra::{Vector2, Vector3};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    azimuth, cap_uv, closest_quadratic_root, disk_extent, hit_local_cap, sample_cap, Hit, Interval,
+    Param, Shape, ShapeSample, Surface,
+};
+use crate::{
+    random::random_f64,
+    rt::{aabb::Aabb, onb::Onb, ray::Ray, roots::solve_quadratic},
+};
+
+/// Cone with its base centered at `center` and its apex `height` units along `axis`
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Cone {
+    pub center: Vector3<f64>,
+    pub axis: Vector3<f64>,
+    pub radius: f64,
+    pub height: f64,
+    pub capped: bool,
+}
+
+impl Cone {
+    pub fn new(center: Vector3<f64>, axis: Vector3<f64>, radius: f64, height: f64) -> Cone {
+        Cone {
+            center,
+            axis,
+            radius,
+            height,
+            capped: true,
+        }
+    }
+
+    fn slant_height(&self) -> f64 {
+        self.radius.hypot(self.height)
+    }
+}
+
+impl Default for Cone {
+    fn default() -> Self {
+        Self::new(Default::default(), Vector3::new(0.0, 1.0, 0.0), 0.5, 1.0)
+    }
+}
+
+impl Shape for Cone {
+    fn name(&self) -> &'static str {
+        "cone"
+    }
+
+    fn intersect(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<Hit> {
+        // base on the local xy plane, apex at z = height
+        let frame = Onb::from_w(&self.axis);
+        let o = frame.to_local(&(ray.origin - self.center));
+        let d = frame.to_local(&ray.direction);
+        let k = self.radius / self.height;
+        let k2 = k * k;
+        let apex_distance = self.height - o.z;
+
+        let side = closest_quadratic_root(
+            d.x * d.x + d.y * d.y - k2 * d.z * d.z,
+            2.0 * (o.x * d.x + o.y * d.y + k2 * apex_distance * d.z),
+            o.x * o.x + o.y * o.y - k2 * apex_distance * apex_distance,
+            t_min,
+            t_max,
+            |t| (0.0..=self.height).contains(&(o.z + t * d.z)),
+        );
+        let base = self
+            .capped
+            .then(|| hit_local_cap(&o, &d, 0.0, self.radius))
+            .flatten()
+            .filter(|&t| t > t_min && t < t_max);
+
+        let distance = [side, base].into_iter().flatten().min_by(f64::total_cmp)?;
+        Some(Hit {
+            distance,
+            primitive: 0,
+        })
+    }
+
+    /// Only a capped cone encloses a volume
+    fn intervals(&self, ray: &Ray) -> Option<Vec<Interval>> {
+        if !self.capped {
+            return None;
+        }
+        let frame = Onb::from_w(&self.axis);
+        let o = frame.to_local(&(ray.origin - self.center));
+        let d = frame.to_local(&ray.direction);
+        let k = self.radius / self.height;
+        let k2 = k * k;
+        let apex_distance = self.height - o.z;
+
+        let side = solve_quadratic(
+            d.x * d.x + d.y * d.y - k2 * d.z * d.z,
+            2.0 * (o.x * d.x + o.y * d.y + k2 * apex_distance * d.z),
+            o.x * o.x + o.y * o.y - k2 * apex_distance * apex_distance,
+        )
+        .map(|(t0, t1)| [t0, t1])
+        .into_iter()
+        .flatten()
+        .filter(|t| (0.0..=self.height).contains(&(o.z + t * d.z)));
+        let base = hit_local_cap(&o, &d, 0.0, self.radius);
+
+        Some(Interval::spanning(side.chain(base)).into_iter().collect())
+    }
+
+    fn bounds(&self) -> Aabb {
+        let axis = self.axis.normalize();
+        let base = Aabb::around(&self.center, &disk_extent(&axis, self.radius));
+        base.grow(&(self.center + axis * self.height))
+    }
+
+    fn surface(&self, ray: &Ray, hit: &Hit) -> Surface {
+        let frame = Onb::from_w(&self.axis);
+        let p = frame.to_local(&(ray.at(hit.distance) - self.center));
+
+        if self.capped && p.z <= 1e-6 * self.height {
+            return Surface {
+                normal: -frame.w,
+                uv: cap_uv(&p, self.radius),
+            };
+        }
+
+        let k = self.radius / self.height;
+        let gradient = Vector3::new(p.x, p.y, k * k * (self.height - p.z));
+        Surface {
+            normal: frame.to_world(&gradient).normalize(),
+            uv: Vector2::new(azimuth(&p), p.z / self.height),
+        }
+    }
+
+    fn sample(&self, _time: f64, seed: &mut u32) -> Option<ShapeSample> {
+        let frame = Onb::from_w(&self.axis);
+        let side_area = PI * self.radius * self.slant_height();
+        let base_area = if self.capped {
+            PI * self.radius * self.radius
+        } else {
+            0.0
+        };
+        let pdf = 1.0 / (side_area + base_area);
+
+        if random_f64(seed) * (side_area + base_area) >= side_area {
+            return Some(sample_cap(
+                seed,
+                &frame,
+                &self.center,
+                0.0,
+                self.radius,
+                -frame.w,
+                pdf,
+            ));
+        }
+
+        // the lateral area grows linearly with the distance from the apex
+        let s = random_f64(seed).sqrt();
+        let phi = 2.0 * PI * random_f64(seed);
+        let r = self.radius * s;
+        let p = Vector3::new(r * phi.cos(), r * phi.sin(), self.height * (1.0 - s));
+        let normal = Vector3::new(
+            self.height * phi.cos(),
+            self.height * phi.sin(),
+            self.radius,
+        );
+        Some(ShapeSample {
+            position: self.center + frame.to_world(&p),
+            normal: frame.to_world(&normal).normalize(),
+            pdf,
+        })
+    }
+
+    fn params(&self) -> Vec<(&'static str, Param)> {
+        vec![
+            ("base center", Param::Point(self.center)),
+            ("axis", Param::Direction(self.axis)),
+            ("radius", Param::Length(self.radius)),
+            ("height", Param::Length(self.height)),
+            ("capped", Param::Flag(self.capped)),
+        ]
+    }
+
+    fn set_param(&mut self, name: &str, value: Param) -> bool {
+        match (name, value) {
+            ("base center", Param::Point(center)) => self.center = center,
+            ("axis", Param::Direction(axis)) => self.axis = axis,
+            ("radius", Param::Length(radius)) => self.radius = radius,
+            ("height", Param::Length(height)) => self.height = height,
+            ("capped", Param::Flag(capped)) => self.capped = capped,
+            _ => return false,
+        }
+        true
+    }
+}
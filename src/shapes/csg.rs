@@ -0,0 +1,143 @@
+use serde::{Deserialize, Serialize};
+
+use super::{Hit, Interval, Shape, Surface};
+use crate::rt::{aabb::Aabb, ray::Ray};
+
+/// How the insides of the two operands of a `Csg` are combined
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CsgOperation {
+    #[default]
+    Union,
+    Intersection,
+    /// Left operand with the right one carved out of it
+    Difference,
+}
+
+impl CsgOperation {
+    fn contains(self, in_left: bool, in_right: bool) -> bool {
+        match self {
+            CsgOperation::Union => in_left || in_right,
+            CsgOperation::Intersection => in_left && in_right,
+            CsgOperation::Difference => in_left && !in_right,
+        }
+    }
+}
+
+/// Point where a ray enters or leaves the combined shape
+struct Boundary {
+    distance: f64,
+    /// Whether the surface there belongs to the right operand
+    right: bool,
+}
+
+/// Boolean combination of two shapes, computed from the intervals where a
+/// ray is inside each of them. Operands without an inside (see
+/// `Shape::intervals`) are treated as empty.
+pub struct Csg {
+    pub operation: CsgOperation,
+    pub left: Box<dyn Shape>,
+    pub right: Box<dyn Shape>,
+}
+
+impl Csg {
+    pub fn new(operation: CsgOperation, left: Box<dyn Shape>, right: Box<dyn Shape>) -> Csg {
+        Csg {
+            operation,
+            left,
+            right,
+        }
+    }
+
+    /// Sweeps the endpoints of both operands' intervals in order, keeping the
+    /// ones where being inside the combined shape changes
+    fn boundaries(&self, ray: &Ray) -> Vec<Boundary> {
+        let endpoints = |shape: &dyn Shape, right: bool| {
+            shape
+                .intervals(ray)
+                .unwrap_or_default()
+                .into_iter()
+                .flat_map(move |interval| [(interval.enter, right), (interval.exit, right)])
+        };
+        let mut events: Vec<_> = endpoints(self.left.as_ref(), false)
+            .chain(endpoints(self.right.as_ref(), true))
+            .collect();
+        events.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        let (mut in_left, mut in_right, mut inside) = (false, false, false);
+        let mut boundaries = Vec::new();
+        for (distance, right) in events {
+            if right {
+                in_right = !in_right;
+            } else {
+                in_left = !in_left;
+            }
+            if self.operation.contains(in_left, in_right) != inside {
+                inside = !inside;
+                boundaries.push(Boundary { distance, right });
+            }
+        }
+        boundaries
+    }
+}
+
+impl Shape for Csg {
+    fn name(&self) -> &'static str {
+        "csg"
+    }
+
+    fn intersect(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<Hit> {
+        let distance = self
+            .boundaries(ray)
+            .into_iter()
+            .map(|boundary| boundary.distance)
+            .find(|&t| t > t_min && t < t_max)?;
+        Some(Hit {
+            distance,
+            primitive: 0,
+        })
+    }
+
+    fn intervals(&self, ray: &Ray) -> Option<Vec<Interval>> {
+        let boundaries = self.boundaries(ray);
+        Some(
+            boundaries
+                .chunks_exact(2)
+                .map(|pair| Interval::new(pair[0].distance, pair[1].distance))
+                .collect(),
+        )
+    }
+
+    fn bounds(&self) -> Aabb {
+        let (left, right) = (self.left.bounds(), self.right.bounds());
+        match self.operation {
+            CsgOperation::Union => left.union(&right),
+            CsgOperation::Intersection => left.intersection(&right),
+            CsgOperation::Difference => left,
+        }
+    }
+
+    fn surface(&self, ray: &Ray, hit: &Hit) -> Surface {
+        let boundaries = self.boundaries(ray);
+        let Some(boundary) = boundaries.iter().min_by(|a, b| {
+            let a = (a.distance - hit.distance).abs();
+            let b = (b.distance - hit.distance).abs();
+            a.total_cmp(&b)
+        }) else {
+            return self.left.surface(ray, hit);
+        };
+
+        if !boundary.right {
+            return self.left.surface(ray, hit);
+        }
+        let surface = self.right.surface(ray, hit);
+        match self.operation {
+            // the carved out surface faces into the right operand
+            CsgOperation::Difference => Surface {
+                normal: -surface.normal,
+                ..surface
+            },
+            _ => surface,
+        }
+    }
+}
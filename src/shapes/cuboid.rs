@@ -0,0 +1,137 @@
+use nalgebra::{Vector2, Vector3};
+use serde::{Deserialize, Serialize};
+
+use super::{Hit, Interval, Param, Shape, ShapeSample, Surface};
+use crate::{
+    random::random_f64,
+    rt::{aabb::Aabb, ray::Ray},
+};
+
+/// Axis-aligned box between the `min` and `max` corners
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Cuboid {
+    pub min: Vector3<f64>,
+    pub max: Vector3<f64>,
+}
+
+impl Cuboid {
+    pub fn new(min: Vector3<f64>, max: Vector3<f64>) -> Cuboid {
+        Cuboid { min, max }
+    }
+
+    pub fn center(&self) -> Vector3<f64> {
+        (self.min + self.max) * 0.5
+    }
+
+    /// Parametric range of the ray inside the box, which may start behind the origin
+    pub fn interval(&self, ray: &Ray) -> Option<Interval> {
+        let (enter, exit) =
+            Aabb::new(self.min, self.max).intersect(ray, f64::NEG_INFINITY, f64::INFINITY)?;
+        Some(Interval::new(enter, exit))
+    }
+
+    /// Outward normal of the face closest to `point`
+    pub fn normal_at(&self, point: &Vector3<f64>) -> Vector3<f64> {
+        let local = (point - self.center()).component_div(&((self.max - self.min) * 0.5));
+        let axis = local.iamax();
+        let mut normal = Vector3::zeros();
+        normal[axis] = local[axis].signum();
+        normal
+    }
+}
+
+impl Default for Cuboid {
+    fn default() -> Self {
+        Self::new(Vector3::new(-0.5, -0.5, -0.5), Vector3::new(0.5, 0.5, 0.5))
+    }
+}
+
+impl Shape for Cuboid {
+    fn name(&self) -> &'static str {
+        "box"
+    }
+
+    fn intersect(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<Hit> {
+        let interval = self.interval(ray)?;
+        let distance = [interval.enter, interval.exit]
+            .into_iter()
+            .find(|&t| t > t_min && t < t_max)?;
+        Some(Hit {
+            distance,
+            primitive: 0,
+        })
+    }
+
+    fn intervals(&self, ray: &Ray) -> Option<Vec<Interval>> {
+        Some(self.interval(ray).into_iter().collect())
+    }
+
+    fn bounds(&self) -> Aabb {
+        Aabb::new(self.min, self.max)
+    }
+
+    fn surface(&self, ray: &Ray, hit: &Hit) -> Surface {
+        let point = ray.at(hit.distance);
+        let normal = self.normal_at(&point);
+        let axis = normal.iamax();
+        // each face is mapped with the two remaining axes
+        let local = (point - self.min).component_div(&(self.max - self.min));
+        let uv = Vector2::new(local[(axis + 1) % 3], local[(axis + 2) % 3]);
+        Surface { normal, uv }
+    }
+
+    fn sample(&self, _time: f64, seed: &mut u32) -> Option<ShapeSample> {
+        let size = self.max - self.min;
+        let face_areas = [size.y * size.z, size.z * size.x, size.x * size.y];
+        let area = 2.0 * face_areas.iter().sum::<f64>();
+
+        // pick one of the six faces proportionally to its area
+        let mut target = random_f64(seed) * area * 0.5;
+        let mut axis = 2;
+        for (i, face_area) in face_areas.iter().enumerate() {
+            if target < *face_area {
+                axis = i;
+                break;
+            }
+            target -= face_area;
+        }
+        let positive = random_f64(seed) < 0.5;
+
+        let mut position = self.min
+            + size.component_mul(&Vector3::new(
+                random_f64(seed),
+                random_f64(seed),
+                random_f64(seed),
+            ));
+        position[axis] = if positive {
+            self.max[axis]
+        } else {
+            self.min[axis]
+        };
+        let mut normal = Vector3::zeros();
+        normal[axis] = if positive { 1.0 } else { -1.0 };
+
+        Some(ShapeSample {
+            position,
+            normal,
+            pdf: 1.0 / area,
+        })
+    }
+
+    fn params(&self) -> Vec<(&'static str, Param)> {
+        vec![
+            ("min", Param::Point(self.min)),
+            ("max", Param::Point(self.max)),
+        ]
+    }
+
+    fn set_param(&mut self, name: &str, value: Param) -> bool {
+        match (name, value) {
+            ("min", Param::Point(min)) => self.min = min,
+            ("max", Param::Point(max)) => self.max = max,
+            _ => return false,
+        }
+        true
+    }
+}
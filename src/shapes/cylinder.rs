@@ -0,0 +1,190 @@
+use std::f64::consts::PI;
+
+use nalgebra::{Vector2, Vector3};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    azimuth, cap_uv, closest_quadratic_root, disk_extent, hit_local_cap, sample_cap, Hit, Interval,
+    Param, Shape, ShapeSample, Surface,
+};
+use crate::{
+    random::random_f64,
+    rt::{aabb::Aabb, onb::Onb, ray::Ray, roots::solve_quadratic},
+};
+
+/// Cylinder of the given `height` centered at `center`, along `axis`
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Cylinder {
+    pub center: Vector3<f64>,
+    pub axis: Vector3<f64>,
+    pub radius: f64,
+    pub height: f64,
+    pub capped: bool,
+}
+
+impl Cylinder {
+    pub fn new(center: Vector3<f64>, axis: Vector3<f64>, radius: f64, height: f64) -> Cylinder {
+        Cylinder {
+            center,
+            axis,
+            radius,
+            height,
+            capped: true,
+        }
+    }
+}
+
+impl Default for Cylinder {
+    fn default() -> Self {
+        Self::new(Default::default(), Vector3::new(0.0, 1.0, 0.0), 0.5, 1.0)
+    }
+}
+
+impl Shape for Cylinder {
+    fn name(&self) -> &'static str {
+        "cylinder"
+    }
+
+    fn intersect(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<Hit> {
+        // work in a frame where the cylinder is centered at the origin along z
+        let frame = Onb::from_w(&self.axis);
+        let o = frame.to_local(&(ray.origin - self.center));
+        let d = frame.to_local(&ray.direction);
+        let half_height = self.height * 0.5;
+
+        let side = closest_quadratic_root(
+            d.x * d.x + d.y * d.y,
+            2.0 * (o.x * d.x + o.y * d.y),
+            o.x * o.x + o.y * o.y - self.radius * self.radius,
+            t_min,
+            t_max,
+            |t| (o.z + t * d.z).abs() <= half_height,
+        );
+        let caps = [-half_height, half_height]
+            .into_iter()
+            .filter(|_| self.capped)
+            .filter_map(|z| hit_local_cap(&o, &d, z, self.radius))
+            .filter(|&t| t > t_min && t < t_max);
+
+        let distance = side.into_iter().chain(caps).min_by(f64::total_cmp)?;
+        Some(Hit {
+            distance,
+            primitive: 0,
+        })
+    }
+
+    /// Only a capped cylinder encloses a volume
+    fn intervals(&self, ray: &Ray) -> Option<Vec<Interval>> {
+        if !self.capped {
+            return None;
+        }
+        let frame = Onb::from_w(&self.axis);
+        let o = frame.to_local(&(ray.origin - self.center));
+        let d = frame.to_local(&ray.direction);
+        let half_height = self.height * 0.5;
+
+        let side = solve_quadratic(
+            d.x * d.x + d.y * d.y,
+            2.0 * (o.x * d.x + o.y * d.y),
+            o.x * o.x + o.y * o.y - self.radius * self.radius,
+        )
+        .map(|(t0, t1)| [t0, t1])
+        .into_iter()
+        .flatten()
+        .filter(|t| (o.z + t * d.z).abs() <= half_height);
+        let caps = [-half_height, half_height]
+            .into_iter()
+            .filter_map(|z| hit_local_cap(&o, &d, z, self.radius));
+
+        Some(Interval::spanning(side.chain(caps)).into_iter().collect())
+    }
+
+    fn bounds(&self) -> Aabb {
+        let axis = self.axis.normalize();
+        let half_size = axis.abs() * self.height * 0.5 + disk_extent(&axis, self.radius);
+        Aabb::around(&self.center, &half_size)
+    }
+
+    fn surface(&self, ray: &Ray, hit: &Hit) -> Surface {
+        let frame = Onb::from_w(&self.axis);
+        let p = frame.to_local(&(ray.at(hit.distance) - self.center));
+        let half_height = self.height * 0.5;
+
+        let on_cap = self.capped && p.z.abs() >= half_height - 1e-6 * self.height;
+        if on_cap {
+            return Surface {
+                normal: frame.w * p.z.signum(),
+                uv: cap_uv(&p, self.radius),
+            };
+        }
+
+        Surface {
+            normal: frame.to_world(&Vector3::new(p.x, p.y, 0.0)).normalize(),
+            uv: Vector2::new(azimuth(&p), (p.z + half_height) / self.height),
+        }
+    }
+
+    fn sample(&self, _time: f64, seed: &mut u32) -> Option<ShapeSample> {
+        let frame = Onb::from_w(&self.axis);
+        let side_area = 2.0 * PI * self.radius * self.height;
+        let cap_area = if self.capped {
+            PI * self.radius * self.radius
+        } else {
+            0.0
+        };
+        let pdf = 1.0 / (side_area + 2.0 * cap_area);
+        let half_height = self.height * 0.5;
+
+        let target = random_f64(seed) * (side_area + 2.0 * cap_area);
+        if target >= side_area {
+            let z = if target >= side_area + cap_area {
+                half_height
+            } else {
+                -half_height
+            };
+            let normal = frame.w * z.signum();
+            return Some(sample_cap(
+                seed,
+                &frame,
+                &self.center,
+                z,
+                self.radius,
+                normal,
+                pdf,
+            ));
+        }
+
+        let phi = 2.0 * PI * random_f64(seed);
+        let z = (random_f64(seed) - 0.5) * self.height;
+        let local_normal = Vector3::new(phi.cos(), phi.sin(), 0.0);
+        let p = local_normal * self.radius + Vector3::new(0.0, 0.0, z);
+        Some(ShapeSample {
+            position: self.center + frame.to_world(&p),
+            normal: frame.to_world(&local_normal),
+            pdf,
+        })
+    }
+
+    fn params(&self) -> Vec<(&'static str, Param)> {
+        vec![
+            ("center", Param::Point(self.center)),
+            ("axis", Param::Direction(self.axis)),
+            ("radius", Param::Length(self.radius)),
+            ("height", Param::Length(self.height)),
+            ("capped", Param::Flag(self.capped)),
+        ]
+    }
+
+    fn set_param(&mut self, name: &str, value: Param) -> bool {
+        match (name, value) {
+            ("center", Param::Point(center)) => self.center = center,
+            ("axis", Param::Direction(axis)) => self.axis = axis,
+            ("radius", Param::Length(radius)) => self.radius = radius,
+            ("height", Param::Length(height)) => self.height = height,
+            ("capped", Param::Flag(capped)) => self.capped = capped,
+            _ => return false,
+        }
+        true
+    }
+}
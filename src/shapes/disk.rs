@@ -0,0 +1,99 @@
+use std::f64::consts::PI;
+
+use nalgebra::Vector3;
+use serde::{Deserialize, Serialize};
+
+use super::{
+    cap_uv, disk_extent, hit_plane_at, sample_cap, Hit, Param, Shape, ShapeSample, Surface,
+};
+use crate::rt::{aabb::Aabb, onb::Onb, ray::Ray};
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Disk {
+    pub center: Vector3<f64>,
+    pub normal: Vector3<f64>,
+    pub radius: f64,
+}
+
+impl Disk {
+    pub fn new(center: Vector3<f64>, normal: Vector3<f64>, radius: f64) -> Disk {
+        Disk {
+            center,
+            normal,
+            radius,
+        }
+    }
+}
+
+impl Default for Disk {
+    fn default() -> Self {
+        Self::new(Default::default(), Vector3::new(0.0, 1.0, 0.0), 0.5)
+    }
+}
+
+impl Shape for Disk {
+    fn name(&self) -> &'static str {
+        "disk"
+    }
+
+    fn intersect(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<Hit> {
+        let distance = hit_plane_at(ray, &self.center, &self.normal)?;
+        if distance <= t_min || distance >= t_max {
+            return None;
+        }
+        let offset = ray.at(distance) - self.center;
+        (offset.norm_squared() <= self.radius * self.radius).then_some(Hit {
+            distance,
+            primitive: 0,
+        })
+    }
+
+    fn bounds(&self) -> Aabb {
+        Aabb::around(
+            &self.center,
+            &disk_extent(&self.normal.normalize(), self.radius),
+        )
+    }
+
+    fn surface(&self, ray: &Ray, hit: &Hit) -> Surface {
+        let frame = Onb::from_w(&self.normal);
+        let p = frame.to_local(&(ray.at(hit.distance) - self.center));
+        Surface {
+            normal: frame.w,
+            uv: cap_uv(&p, self.radius),
+        }
+    }
+
+    fn sample(&self, _time: f64, seed: &mut u32) -> Option<ShapeSample> {
+        let frame = Onb::from_w(&self.normal);
+        let pdf = 1.0 / (PI * self.radius * self.radius);
+        Some(sample_cap(
+            seed,
+            &frame,
+            &self.center,
+            0.0,
+            self.radius,
+            frame.w,
+            pdf,
+        ))
+    }
+
+    fn params(&self) -> Vec<(&'static str, Param)> {
+        vec![
+            ("center", Param::Point(self.center)),
+            ("normal", Param::Direction(self.normal)),
+            ("radius", Param::Length(self.radius)),
+        ]
+    }
+
+    fn set_param(&mut self, name: &str, value: Param) -> bool {
+        match (name, value) {
+            ("center", Param::Point(center)) => self.center = center,
+            ("normal", Param::Direction(normal)) => self.normal = normal,
+            ("radius", Param::Length(radius)) => self.radius = radius,
+            _ => return false,
+        }
+        true
+    }
+}
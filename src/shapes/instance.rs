@@ -0,0 +1,121 @@
+use std::sync::Arc;
+
+use nalgebra::{Matrix4, Vector3, Vector4};
+
+use super::{Hit, Interval, Param, Shape, ShapeSample, Surface};
+use crate::rt::{
+    aabb::Aabb,
+    ray::Ray,
+    transform::{Transform, TransformMatrices},
+};
+
+/// Places a shape in the world with an affine transform. The shape is shared,
+/// so the same geometry can be instanced any number of times.
+pub struct Instance {
+    pub shape: Arc<dyn Shape>,
+    transform: Transform,
+    matrices: TransformMatrices,
+}
+
+impl Instance {
+    pub fn new(shape: Arc<dyn Shape>, transform: Transform) -> Instance {
+        Instance {
+            shape,
+            transform,
+            matrices: TransformMatrices::new(&transform),
+        }
+    }
+
+    /// Instance placed by an affine matrix, shear included. Its parameters
+    /// are the decomposition of the matrix, editing them drops the shear.
+    pub fn from_matrix(shape: Arc<dyn Shape>, matrix: Matrix4<f64>) -> Instance {
+        Instance {
+            shape,
+            transform: Transform::from_matrix(&matrix),
+            matrices: TransformMatrices::from_matrix(matrix),
+        }
+    }
+
+    pub fn transform(&self) -> &Transform {
+        &self.transform
+    }
+
+    pub fn set_transform(&mut self, transform: Transform) {
+        self.transform = transform;
+        self.matrices = TransformMatrices::new(&transform);
+    }
+}
+
+impl Shape for Instance {
+    fn name(&self) -> &'static str {
+        "instance"
+    }
+
+    fn intersect(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<Hit> {
+        self.shape
+            .intersect(&self.matrices.ray_to_object(ray), t_min, t_max)
+    }
+
+    fn intervals(&self, ray: &Ray) -> Option<Vec<Interval>> {
+        self.shape.intervals(&self.matrices.ray_to_object(ray))
+    }
+
+    fn bounds(&self) -> Aabb {
+        let bounds = self.shape.bounds();
+        if !bounds.is_finite() {
+            return Aabb::infinite();
+        }
+        let corners = bounds
+            .corners()
+            .map(|corner| self.matrices.point_to_world(&corner));
+        Aabb::from_points(&corners)
+    }
+
+    fn surface(&self, ray: &Ray, hit: &Hit) -> Surface {
+        let surface = self.shape.surface(&self.matrices.ray_to_object(ray), hit);
+        Surface {
+            normal: self.matrices.normal_to_world(&surface.normal),
+            ..surface
+        }
+    }
+
+    fn uv_derivatives(&self, ray: &Ray, hit: &Hit) -> Option<[Vector3<f64>; 2]> {
+        let derivatives = self
+            .shape
+            .uv_derivatives(&self.matrices.ray_to_object(ray), hit)?;
+        Some(derivatives.map(|derivative| self.matrices.vector_to_world(&derivative)))
+    }
+
+    fn color(&self, ray: &Ray, hit: &Hit) -> Option<Vector4<f64>> {
+        self.shape.color(&self.matrices.ray_to_object(ray), hit)
+    }
+
+    fn sample(&self, time: f64, seed: &mut u32) -> Option<ShapeSample> {
+        let sample = self.shape.sample(time, seed)?;
+        Some(ShapeSample {
+            position: self.matrices.point_to_world(&sample.position),
+            normal: self.matrices.normal_to_world(&sample.normal),
+            pdf: sample.pdf / self.matrices.area_scale(&sample.normal),
+        })
+    }
+
+    fn params(&self) -> Vec<(&'static str, Param)> {
+        vec![
+            ("translation", Param::Point(self.transform.translation)),
+            ("rotation", Param::Rotation(self.transform.euler_degrees())),
+            ("scale", Param::Scale(self.transform.scale)),
+        ]
+    }
+
+    fn set_param(&mut self, name: &str, value: Param) -> bool {
+        let mut transform = self.transform;
+        match (name, value) {
+            ("translation", Param::Point(translation)) => transform.translation = translation,
+            ("rotation", Param::Rotation(angles)) => transform.set_euler_degrees(&angles),
+            ("scale", Param::Scale(scale)) => transform.scale = scale,
+            _ => return false,
+        }
+        self.set_transform(transform);
+        true
+    }
+}
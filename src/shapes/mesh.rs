@@ -0,0 +1,211 @@
+use nalgebra::{Vector2, Vector3, Vector4};
+
+use super::{Hit, Shape, ShapeSample, Surface};
+
+mod ply;
+use crate::{
+    random::random_f64,
+    rt::{aabb::Aabb, bvh::Bvh, ray::Ray},
+};
+
+/// Indexed triangle mesh with its own BVH. Wrap it in an `Arc` and place it
+/// with `Instance`s to have it show up many times while sharing its memory.
+pub struct Mesh {
+    pub positions: Vec<Vector3<f64>>,
+    /// Per vertex normals, when empty the face normals are used
+    pub normals: Vec<Vector3<f64>>,
+    /// Per vertex texture coordinates, when empty the barycentric
+    /// coordinates are used
+    pub uvs: Vec<Vector2<f64>>,
+    /// Per vertex colors multiplying the material albedo, can be empty
+    pub colors: Vec<Vector4<f64>>,
+    pub triangles: Vec<[u32; 3]>,
+    bvh: Bvh,
+    /// Running sum of the triangle areas, for sampling
+    cumulative_areas: Vec<f64>,
+}
+
+impl Mesh {
+    pub fn new(positions: Vec<Vector3<f64>>, triangles: Vec<[u32; 3]>) -> Mesh {
+        Mesh::with_attributes(positions, Vec::new(), Vec::new(), triangles)
+    }
+
+    pub fn with_attributes(
+        positions: Vec<Vector3<f64>>,
+        normals: Vec<Vector3<f64>>,
+        uvs: Vec<Vector2<f64>>,
+        triangles: Vec<[u32; 3]>,
+    ) -> Mesh {
+        let mut mesh = Mesh {
+            positions,
+            normals,
+            uvs,
+            colors: Vec::new(),
+            triangles,
+            bvh: Bvh::default(),
+            cumulative_areas: Vec::new(),
+        };
+        mesh.update();
+        mesh
+    }
+
+    /// Rebuilds the BVH, needed after changing the vertices or the triangles
+    pub fn update(&mut self) {
+        let bounds: Vec<Aabb> = (0..self.triangles.len())
+            .map(|i| Aabb::from_points(&self.vertices(i)))
+            .collect();
+        self.bvh = Bvh::build(&bounds);
+
+        let mut total = 0.0;
+        self.cumulative_areas = (0..self.triangles.len())
+            .map(|i| {
+                let [a, b, c] = self.vertices(i);
+                total += (b - a).cross(&(c - a)).norm() * 0.5;
+                total
+            })
+            .collect();
+    }
+
+    pub fn area(&self) -> f64 {
+        self.cumulative_areas.last().copied().unwrap_or(0.0)
+    }
+
+    fn vertices(&self, triangle: usize) -> [Vector3<f64>; 3] {
+        self.triangles[triangle].map(|i| self.positions[i as usize])
+    }
+
+    /// Möller-Trumbore, returns the distance and the barycentric coordinates
+    /// of the second and third vertices
+    fn intersect_triangle(&self, ray: &Ray, triangle: usize) -> Option<(f64, f64, f64)> {
+        let [a, b, c] = self.vertices(triangle);
+        let edge1 = b - a;
+        let edge2 = c - a;
+
+        let p = ray.direction.cross(&edge2);
+        let determinant = edge1.dot(&p);
+        if determinant.abs() < 1e-14 {
+            return None;
+        }
+        let inverse_determinant = 1.0 / determinant;
+
+        let s = ray.origin - a;
+        let u = s.dot(&p) * inverse_determinant;
+        if !(0.0..=1.0).contains(&u) {
+            return None;
+        }
+
+        let q = s.cross(&edge1);
+        let v = ray.direction.dot(&q) * inverse_determinant;
+        if v < 0.0 || u + v > 1.0 {
+            return None;
+        }
+
+        Some((edge2.dot(&q) * inverse_determinant, u, v))
+    }
+
+    fn interpolate<T>(&self, values: &[T], triangle: usize, u: f64, v: f64) -> T
+    where
+        T: Copy + std::ops::Mul<f64, Output = T> + std::ops::Add<Output = T>,
+    {
+        let [a, b, c] = self.triangles[triangle].map(|i| values[i as usize]);
+        a * (1.0 - u - v) + b * u + c * v
+    }
+}
+
+impl Shape for Mesh {
+    fn name(&self) -> &'static str {
+        "mesh"
+    }
+
+    fn intersect(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<Hit> {
+        let (_, hit) = self
+            .bvh
+            .intersect(ray, t_min, t_max, |triangle, t_min, t_max| {
+                let (distance, _, _) = self.intersect_triangle(ray, triangle)?;
+                (distance > t_min && distance < t_max).then_some(Hit {
+                    distance,
+                    primitive: triangle,
+                })
+            })?;
+        Some(hit)
+    }
+
+    fn bounds(&self) -> Aabb {
+        self.bvh.bounds()
+    }
+
+    fn surface(&self, ray: &Ray, hit: &Hit) -> Surface {
+        let triangle = hit.primitive;
+        let (_, u, v) = self
+            .intersect_triangle(ray, triangle)
+            .unwrap_or((hit.distance, 0.0, 0.0));
+
+        let normal = if self.normals.is_empty() {
+            let [a, b, c] = self.vertices(triangle);
+            (b - a).cross(&(c - a)).normalize()
+        } else {
+            self.interpolate(&self.normals, triangle, u, v).normalize()
+        };
+        let uv = if self.uvs.is_empty() {
+            Vector2::new(u, v)
+        } else {
+            self.interpolate(&self.uvs, triangle, u, v)
+        };
+
+        Surface { normal, uv }
+    }
+
+    fn uv_derivatives(&self, _ray: &Ray, hit: &Hit) -> Option<[Vector3<f64>; 2]> {
+        let triangle = hit.primitive;
+        let [a, b, c] = self.vertices(triangle);
+        let (edge1, edge2) = (b - a, c - a);
+        if self.uvs.is_empty() {
+            return Some([edge1, edge2]);
+        }
+
+        let [uv_a, uv_b, uv_c] = self.triangles[triangle].map(|i| self.uvs[i as usize]);
+        let (duv1, duv2) = (uv_b - uv_a, uv_c - uv_a);
+        let determinant = duv1.x * duv2.y - duv1.y * duv2.x;
+        if determinant.abs() < 1e-12 {
+            return None;
+        }
+        Some([
+            (edge1 * duv2.y - edge2 * duv1.y) / determinant,
+            (edge2 * duv1.x - edge1 * duv2.x) / determinant,
+        ])
+    }
+
+    fn color(&self, ray: &Ray, hit: &Hit) -> Option<Vector4<f64>> {
+        if self.colors.is_empty() {
+            return None;
+        }
+        let (_, u, v) = self.intersect_triangle(ray, hit.primitive)?;
+        Some(self.interpolate(&self.colors, hit.primitive, u, v))
+    }
+
+    fn sample(&self, _time: f64, seed: &mut u32) -> Option<ShapeSample> {
+        let area = self.area();
+        if area <= 0.0 {
+            return None;
+        }
+
+        let target = random_f64(seed) * area;
+        let triangle = self
+            .cumulative_areas
+            .partition_point(|&sum| sum < target)
+            .min(self.triangles.len() - 1);
+
+        // uniform barycentric coordinates, folding the square onto the triangle
+        let (mut u, mut v) = (random_f64(seed), random_f64(seed));
+        if u + v > 1.0 {
+            u = 1.0 - u;
+            v = 1.0 - v;
+        }
+        let [a, b, c] = self.vertices(triangle);
+        Some(ShapeSample {
+            position: a * (1.0 - u - v) + b * u + c * v,
+            normal: (b - a).cross(&(c - a)).normalize(),
+            pdf: 1.0 / area,
+        })
+    }
+}
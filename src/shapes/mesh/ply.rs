@@ -0,0 +1,511 @@
+//! PLY reader, ASCII and binary, for triangle meshes with optional per vertex
+//! normals, texture coordinates and colors. Polygons are triangulated as fans
+//! and elements other than vertices and faces are skipped.
+
+use std::{error::Error, fs, path::Path, str::SplitAsciiWhitespace};
+
+use nalgebra::{Vector2, Vector3, Vector4};
+
+use super::Mesh;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Format {
+    Ascii,
+    LittleEndian,
+    BigEndian,
+}
+
+#[derive(Clone, Copy)]
+enum Scalar {
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    F32,
+    F64,
+}
+
+enum Kind {
+    Scalar(Scalar),
+    List { count: Scalar, item: Scalar },
+}
+
+struct Property {
+    name: String,
+    kind: Kind,
+}
+
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>,
+}
+
+struct Header<'a> {
+    format: Format,
+    elements: Vec<Element>,
+    body: &'a [u8],
+}
+
+/// Reads the values of the body one at a time, whatever the format
+struct Body<'a> {
+    format: Format,
+    data: &'a [u8],
+    position: usize,
+    tokens: SplitAsciiWhitespace<'a>,
+}
+
+/// Where each vertex attribute is in the vertex properties
+#[derive(Default)]
+struct VertexLayout {
+    position: [Option<usize>; 3],
+    normal: [Option<usize>; 3],
+    uv: [Option<usize>; 2],
+    color: [Option<usize>; 4],
+    /// Integer colors go from 0 to 255
+    color_scale: f64,
+}
+
+impl Mesh {
+    pub fn load_ply(path: impl AsRef<Path>) -> Result<Mesh, Box<dyn Error>> {
+        Mesh::from_ply(&fs::read(path)?)
+    }
+
+    pub fn from_ply(bytes: &[u8]) -> Result<Mesh, Box<dyn Error>> {
+        let Header {
+            format,
+            elements,
+            body,
+        } = parse_header(bytes)?;
+        let mut body = Body::new(format, body)?;
+
+        let mut positions = Vec::new();
+        let mut normals = Vec::new();
+        let mut uvs = Vec::new();
+        let mut colors = Vec::new();
+        let mut triangles = Vec::new();
+        let mut values = Vec::new();
+
+        for element in &elements {
+            match element.name.as_str() {
+                "vertex" => {
+                    let layout = VertexLayout::new(element)?;
+                    // the count comes from the header, it can't be trusted
+                    positions.reserve_exact(element.count.min(body.remaining()));
+                    for _ in 0..element.count {
+                        values.clear();
+                        for property in &element.properties {
+                            values.push(body.read_property(&property.kind)?);
+                        }
+                        let get = |index: Option<usize>| index.map_or(0.0, |i| values[i]);
+
+                        positions.push(Vector3::from(layout.position.map(get)));
+                        if layout.normal.iter().all(Option::is_some) {
+                            normals.push(Vector3::from(layout.normal.map(get)));
+                        }
+                        if layout.uv.iter().all(Option::is_some) {
+                            uvs.push(Vector2::from(layout.uv.map(get)));
+                        }
+                        if layout.color[..3].iter().all(Option::is_some) {
+                            let scale = layout.color_scale;
+                            let [r, g, b, _] = layout.color.map(get);
+                            let alpha = layout.color[3].map_or(1.0, |i| values[i] * scale);
+                            colors.push(Vector4::new(r * scale, g * scale, b * scale, alpha));
+                        }
+                    }
+                }
+                "face" => {
+                    let indices = element
+                        .properties
+                        .iter()
+                        .position(|property| {
+                            matches!(property.kind, Kind::List { .. })
+                                && matches!(
+                                    property.name.as_str(),
+                                    "vertex_indices" | "vertex_index"
+                                )
+                        })
+                        .ok_or("faces have no vertex_indices list")?;
+                    triangles.reserve(element.count.min(body.remaining()));
+                    let mut polygon = Vec::new();
+                    for _ in 0..element.count {
+                        for (i, property) in element.properties.iter().enumerate() {
+                            match (&property.kind, i == indices) {
+                                (Kind::List { count, item }, true) => {
+                                    let count = body.read(*count)? as usize;
+                                    polygon.clear();
+                                    for _ in 0..count {
+                                        let index = body.read(*item)?;
+                                        if !(0.0..=u32::MAX as f64).contains(&index) {
+                                            return Err(
+                                                format!("face refers to vertex {index}").into()
+                                            );
+                                        }
+                                        polygon.push(index as u32);
+                                    }
+                                    for k in 1..polygon.len().saturating_sub(1) {
+                                        triangles.push([polygon[0], polygon[k], polygon[k + 1]]);
+                                    }
+                                }
+                                (kind, _) => body.skip_property(kind)?,
+                            }
+                        }
+                    }
+                }
+                _ => {
+                    for _ in 0..element.count {
+                        for property in &element.properties {
+                            body.skip_property(&property.kind)?;
+                        }
+                    }
+                }
+            }
+        }
+
+        let vertex_count = positions.len();
+        if triangles
+            .iter()
+            .flatten()
+            .any(|&i| i as usize >= vertex_count)
+        {
+            return Err("face refers to a vertex that doesn't exist".into());
+        }
+
+        let mut mesh = Mesh::with_attributes(positions, normals, uvs, triangles);
+        mesh.colors = colors;
+        Ok(mesh)
+    }
+}
+
+fn parse_header(bytes: &[u8]) -> Result<Header<'_>, Box<dyn Error>> {
+    const END: &[u8] = b"end_header";
+    let end = bytes
+        .windows(END.len())
+        .position(|window| window == END)
+        .ok_or("missing end_header")?;
+    // the body starts after the line break following end_header
+    let body_start = bytes[end..]
+        .iter()
+        .position(|&byte| byte == b'\n')
+        .map_or(bytes.len(), |i| end + i + 1);
+
+    let header = std::str::from_utf8(&bytes[..end])?;
+    let mut lines = header.lines().map(str::trim);
+    if lines.next() != Some("ply") {
+        return Err("not a PLY file".into());
+    }
+
+    let mut format = None;
+    let mut elements: Vec<Element> = Vec::new();
+    for line in lines {
+        let words: Vec<&str> = line.split_ascii_whitespace().collect();
+        match words.as_slice() {
+            ["format", kind, _version] => {
+                format = Some(match *kind {
+                    "ascii" => Format::Ascii,
+                    "binary_little_endian" => Format::LittleEndian,
+                    "binary_big_endian" => Format::BigEndian,
+                    _ => return Err(format!("unknown PLY format '{}'", kind).into()),
+                })
+            }
+            ["element", name, count] => elements.push(Element {
+                name: name.to_string(),
+                count: count.parse()?,
+                properties: Vec::new(),
+            }),
+            ["property", "list", count, item, name] => {
+                let element = elements.last_mut().ok_or("property before any element")?;
+                element.properties.push(Property {
+                    name: name.to_string(),
+                    kind: Kind::List {
+                        count: Scalar::parse(count)?,
+                        item: Scalar::parse(item)?,
+                    },
+                });
+            }
+            ["property", scalar, name] => {
+                let element = elements.last_mut().ok_or("property before any element")?;
+                element.properties.push(Property {
+                    name: name.to_string(),
+                    kind: Kind::Scalar(Scalar::parse(scalar)?),
+                });
+            }
+            ["comment", ..] | ["obj_info", ..] | [] => {}
+            _ => return Err(format!("unexpected PLY header line '{}'", line).into()),
+        }
+    }
+
+    let format = format.ok_or("missing PLY format")?;
+    Ok(Header {
+        format,
+        elements,
+        body: &bytes[body_start..],
+    })
+}
+
+impl Scalar {
+    fn parse(name: &str) -> Result<Scalar, Box<dyn Error>> {
+        Ok(match name {
+            "char" | "int8" => Scalar::I8,
+            "uchar" | "uint8" => Scalar::U8,
+            "short" | "int16" => Scalar::I16,
+            "ushort" | "uint16" => Scalar::U16,
+            "int" | "int32" => Scalar::I32,
+            "uint" | "uint32" => Scalar::U32,
+            "float" | "float32" => Scalar::F32,
+            "double" | "float64" => Scalar::F64,
+            _ => return Err(format!("unknown PLY type '{}'", name).into()),
+        })
+    }
+
+    fn size(self) -> usize {
+        match self {
+            Scalar::I8 | Scalar::U8 => 1,
+            Scalar::I16 | Scalar::U16 => 2,
+            Scalar::I32 | Scalar::U32 | Scalar::F32 => 4,
+            Scalar::F64 => 8,
+        }
+    }
+
+    fn is_integer(self) -> bool {
+        !matches!(self, Scalar::F32 | Scalar::F64)
+    }
+}
+
+impl VertexLayout {
+    fn new(element: &Element) -> Result<VertexLayout, Box<dyn Error>> {
+        let mut layout = VertexLayout {
+            color_scale: 1.0,
+            ..Default::default()
+        };
+        for (i, property) in element.properties.iter().enumerate() {
+            let Kind::Scalar(scalar) = property.kind else {
+                continue;
+            };
+            let slot = match property.name.as_str() {
+                "x" => &mut layout.position[0],
+                "y" => &mut layout.position[1],
+                "z" => &mut layout.position[2],
+                "nx" => &mut layout.normal[0],
+                "ny" => &mut layout.normal[1],
+                "nz" => &mut layout.normal[2],
+                "u" | "s" | "texture_u" => &mut layout.uv[0],
+                "v" | "t" | "texture_v" => &mut layout.uv[1],
+                "red" | "r" => &mut layout.color[0],
+                "green" | "g" => &mut layout.color[1],
+                "blue" | "b" => &mut layout.color[2],
+                "alpha" | "a" => &mut layout.color[3],
+                _ => continue,
+            };
+            *slot = Some(i);
+            if matches!(property.name.as_str(), "red" | "r") && scalar.is_integer() {
+                layout.color_scale = 1.0 / 255.0;
+            }
+        }
+        if layout.position.iter().any(Option::is_none) {
+            return Err("vertices have no x, y and z properties".into());
+        }
+        Ok(layout)
+    }
+}
+
+impl<'a> Body<'a> {
+    fn new(format: Format, data: &'a [u8]) -> Result<Body<'a>, Box<dyn Error>> {
+        let text = match format {
+            Format::Ascii => std::str::from_utf8(data)?,
+            _ => "",
+        };
+        Ok(Body {
+            format,
+            data,
+            position: 0,
+            tokens: text.split_ascii_whitespace(),
+        })
+    }
+
+    /// Bytes left, more than the values left
+    fn remaining(&self) -> usize {
+        self.data.len() - self.position
+    }
+
+    fn read(&mut self, scalar: Scalar) -> Result<f64, Box<dyn Error>> {
+        if self.format == Format::Ascii {
+            let token = self.tokens.next().ok_or("PLY file ends too early")?;
+            return Ok(token.parse()?);
+        }
+
+        let size = scalar.size();
+        let bytes = self
+            .data
+            .get(self.position..self.position + size)
+            .ok_or("PLY file ends too early")?;
+        self.position += size;
+
+        let mut buffer = [0; 8];
+        buffer[..size].copy_from_slice(bytes);
+        if self.format == Format::BigEndian {
+            buffer[..size].reverse();
+        }
+        let [b0, b1, b2, b3, ..] = buffer;
+        Ok(match scalar {
+            Scalar::I8 => b0 as i8 as f64,
+            Scalar::U8 => b0 as f64,
+            Scalar::I16 => i16::from_le_bytes([b0, b1]) as f64,
+            Scalar::U16 => u16::from_le_bytes([b0, b1]) as f64,
+            Scalar::I32 => i32::from_le_bytes([b0, b1, b2, b3]) as f64,
+            Scalar::U32 => u32::from_le_bytes([b0, b1, b2, b3]) as f64,
+            Scalar::F32 => f32::from_le_bytes([b0, b1, b2, b3]) as f64,
+            Scalar::F64 => f64::from_le_bytes(buffer),
+        })
+    }
+
+    /// Value of a scalar property, lists are skipped and read as zero
+    fn read_property(&mut self, kind: &Kind) -> Result<f64, Box<dyn Error>> {
+        match kind {
+            Kind::Scalar(scalar) => self.read(*scalar),
+            list => self.skip_property(list).map(|_| 0.0),
+        }
+    }
+
+    fn skip_property(&mut self, kind: &Kind) -> Result<(), Box<dyn Error>> {
+        match kind {
+            Kind::Scalar(scalar) => {
+                self.read(*scalar)?;
+            }
+            Kind::List { count, item } => {
+                let count = self.read(*count)? as usize;
+                for _ in 0..count {
+                    self.read(*item)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const POSITIONS: [[f32; 3]; 4] = [
+        [0.0, 0.0, 0.0],
+        [1.0, 0.0, 0.0],
+        [1.0, 1.0, -0.5],
+        [0.0, 1.0, 2.0],
+    ];
+    const COLORS: [[u8; 3]; 4] = [[255, 0, 0], [0, 255, 0], [0, 0, 255], [51, 102, 0]];
+
+    fn header(format: &str, vertices: usize) -> String {
+        format!(
+            "ply\nformat {format} 1.0\ncomment written by the tests\n\
+             element vertex {vertices}\nproperty float x\nproperty float y\n\
+             property float z\nproperty uchar red\nproperty uchar green\n\
+             property uchar blue\nelement face 1\n\
+             property list uchar int vertex_indices\n\
+             element edge 1\nproperty int vertex1\nproperty int vertex2\n\
+             end_header\n"
+        )
+    }
+
+    /// The quad of `POSITIONS` with one face referring to `indices`, and an
+    /// edge to skip
+    fn binary(big_endian: bool, indices: [i32; 4]) -> Vec<u8> {
+        let format = if big_endian {
+            "binary_big_endian"
+        } else {
+            "binary_little_endian"
+        };
+        let mut bytes = header(format, 4).into_bytes();
+        let int = |value: i32| {
+            if big_endian {
+                value.to_be_bytes()
+            } else {
+                value.to_le_bytes()
+            }
+        };
+        for (position, color) in POSITIONS.iter().zip(COLORS) {
+            for value in position {
+                bytes.extend(if big_endian {
+                    value.to_be_bytes()
+                } else {
+                    value.to_le_bytes()
+                });
+            }
+            bytes.extend(color);
+        }
+        bytes.push(4);
+        indices
+            .into_iter()
+            .for_each(|index| bytes.extend(int(index)));
+        bytes.extend(int(0));
+        bytes.extend(int(2));
+        bytes
+    }
+
+    fn ascii(indices: [i32; 4]) -> Vec<u8> {
+        let mut text = header("ascii", 4);
+        for (position, color) in POSITIONS.iter().zip(COLORS) {
+            text += &format!(
+                "{} {} {} {} {} {}\n",
+                position[0], position[1], position[2], color[0], color[1], color[2]
+            );
+        }
+        text += &format!(
+            "4 {} {} {} {}\n0 2\n",
+            indices[0], indices[1], indices[2], indices[3]
+        );
+        text.into_bytes()
+    }
+
+    fn assert_quad(mesh: &Mesh) {
+        let positions: Vec<_> = POSITIONS
+            .iter()
+            .map(|p| Vector3::new(p[0] as f64, p[1] as f64, p[2] as f64))
+            .collect();
+        assert_eq!(mesh.positions, positions);
+        assert_eq!(mesh.triangles, vec![[0, 1, 2], [0, 2, 3]]);
+        assert!(mesh.normals.is_empty() && mesh.uvs.is_empty());
+        assert_eq!(mesh.colors.len(), 4);
+        for (color, expected) in mesh.colors.iter().zip(COLORS) {
+            let expected = Vector4::new(
+                expected[0] as f64 / 255.0,
+                expected[1] as f64 / 255.0,
+                expected[2] as f64 / 255.0,
+                1.0,
+            );
+            assert!((color - expected).abs().max() < 1e-12, "{color}");
+        }
+    }
+
+    #[test]
+    fn ascii_round_trip() {
+        assert_quad(&Mesh::from_ply(&ascii([0, 1, 2, 3])).unwrap());
+    }
+
+    #[test]
+    fn little_endian_round_trip() {
+        assert_quad(&Mesh::from_ply(&binary(false, [0, 1, 2, 3])).unwrap());
+    }
+
+    #[test]
+    fn big_endian_round_trip() {
+        assert_quad(&Mesh::from_ply(&binary(true, [0, 1, 2, 3])).unwrap());
+    }
+
+    #[test]
+    fn out_of_range_indices() {
+        for indices in [[0, 1, 2, 4], [0, -1, 2, 3]] {
+            assert!(Mesh::from_ply(&ascii(indices)).is_err());
+            assert!(Mesh::from_ply(&binary(false, indices)).is_err());
+            assert!(Mesh::from_ply(&binary(true, indices)).is_err());
+        }
+    }
+
+    #[test]
+    fn truncated_body() {
+        let bytes = binary(false, [0, 1, 2, 3]);
+        assert!(Mesh::from_ply(&bytes[..bytes.len() - 3]).is_err());
+    }
+}
@@ -0,0 +1,82 @@
+use nalgebra::Vector3;
+use serde::{Deserialize, Serialize};
+
+use super::{hit_plane_at, Hit, Interval, Param, Shape, Surface};
+use crate::rt::{aabb::Aabb, onb::Onb, ray::Ray};
+
+/// Infinite plane going through `point`, facing `normal`
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Plane {
+    pub point: Vector3<f64>,
+    pub normal: Vector3<f64>,
+}
+
+impl Plane {
+    pub fn new(point: Vector3<f64>, normal: Vector3<f64>) -> Plane {
+        Plane { point, normal }
+    }
+}
+
+impl Default for Plane {
+    fn default() -> Self {
+        Self::new(Vector3::new(0.0, -1.0, 0.0), Vector3::new(0.0, 1.0, 0.0))
+    }
+}
+
+impl Shape for Plane {
+    fn name(&self) -> &'static str {
+        "plane"
+    }
+
+    fn intersect(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<Hit> {
+        let distance = hit_plane_at(ray, &self.point, &self.normal)?;
+        (distance > t_min && distance < t_max).then_some(Hit {
+            distance,
+            primitive: 0,
+        })
+    }
+
+    /// The inside of a plane is the half-space behind it
+    fn intervals(&self, ray: &Ray) -> Option<Vec<Interval>> {
+        let interval = match hit_plane_at(ray, &self.point, &self.normal) {
+            Some(t) if self.normal.dot(&ray.direction) < 0.0 => Interval::new(t, f64::INFINITY),
+            Some(t) => Interval::new(f64::NEG_INFINITY, t),
+            None if (ray.origin - self.point).dot(&self.normal) < 0.0 => {
+                Interval::new(f64::NEG_INFINITY, f64::INFINITY)
+            }
+            None => return Some(Vec::new()),
+        };
+        Some(vec![interval])
+    }
+
+    fn bounds(&self) -> Aabb {
+        Aabb::infinite()
+    }
+
+    fn surface(&self, ray: &Ray, hit: &Hit) -> Surface {
+        // the plane is textured with world units along its tangents
+        let frame = Onb::from_w(&self.normal);
+        let p = frame.to_local(&(ray.at(hit.distance) - self.point));
+        Surface {
+            normal: frame.w,
+            uv: p.xy(),
+        }
+    }
+
+    fn params(&self) -> Vec<(&'static str, Param)> {
+        vec![
+            ("point", Param::Point(self.point)),
+            ("normal", Param::Direction(self.normal)),
+        ]
+    }
+
+    fn set_param(&mut self, name: &str, value: Param) -> bool {
+        match (name, value) {
+            ("point", Param::Point(point)) => self.point = point,
+            ("normal", Param::Direction(normal)) => self.normal = normal,
+            _ => return false,
+        }
+        true
+    }
+}
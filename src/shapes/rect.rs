@@ -0,0 +1,114 @@
+use nalgebra::{Vector2, Vector3};
+use serde::{Deserialize, Serialize};
+
+use super::{hit_plane_at, Hit, Param, Shape, ShapeSample, Surface};
+use crate::{
+    random::random_f64,
+    rt::{aabb::Aabb, ray::Ray},
+};
+
+/// Parallelogram centered at `center`, spanning `center ± u ± v`
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Rect {
+    pub center: Vector3<f64>,
+    pub u: Vector3<f64>,
+    pub v: Vector3<f64>,
+}
+
+impl Rect {
+    pub fn new(center: Vector3<f64>, u: Vector3<f64>, v: Vector3<f64>) -> Rect {
+        Rect { center, u, v }
+    }
+
+    pub fn normal(&self) -> Vector3<f64> {
+        self.u.cross(&self.v).normalize()
+    }
+
+    /// Coordinates of `point` in the (u, v) basis, both in [-1, 1] inside the rectangle
+    fn coordinates(&self, point: &Vector3<f64>) -> Vector2<f64> {
+        let n = self.u.cross(&self.v);
+        let w = n / n.norm_squared();
+        let p = point - self.center;
+        Vector2::new(w.dot(&p.cross(&self.v)), w.dot(&self.u.cross(&p)))
+    }
+}
+
+impl Default for Rect {
+    fn default() -> Self {
+        Self::new(
+            Default::default(),
+            Vector3::new(0.5, 0.0, 0.0),
+            Vector3::new(0.0, 0.5, 0.0),
+        )
+    }
+}
+
+impl Shape for Rect {
+    fn name(&self) -> &'static str {
+        "rectangle"
+    }
+
+    fn intersect(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<Hit> {
+        let distance = hit_plane_at(ray, &self.center, &self.u.cross(&self.v))?;
+        if distance <= t_min || distance >= t_max {
+            return None;
+        }
+        let coordinates = self.coordinates(&ray.at(distance));
+        (coordinates.x.abs() <= 1.0 && coordinates.y.abs() <= 1.0).then_some(Hit {
+            distance,
+            primitive: 0,
+        })
+    }
+
+    fn bounds(&self) -> Aabb {
+        let corners = [
+            self.center + self.u + self.v,
+            self.center + self.u - self.v,
+            self.center - self.u + self.v,
+            self.center - self.u - self.v,
+        ];
+        Aabb::from_points(&corners)
+    }
+
+    fn surface(&self, ray: &Ray, hit: &Hit) -> Surface {
+        let uv = self.coordinates(&ray.at(hit.distance)).add_scalar(1.0) * 0.5;
+        Surface {
+            normal: self.normal(),
+            uv,
+        }
+    }
+
+    fn uv_derivatives(&self, _ray: &Ray, _hit: &Hit) -> Option<[Vector3<f64>; 2]> {
+        Some([self.u * 2.0, self.v * 2.0])
+    }
+
+    fn sample(&self, _time: f64, seed: &mut u32) -> Option<ShapeSample> {
+        let a = random_f64(seed) * 2.0 - 1.0;
+        let b = random_f64(seed) * 2.0 - 1.0;
+        let area = 4.0 * self.u.cross(&self.v).norm();
+        Some(ShapeSample {
+            position: self.center + a * self.u + b * self.v,
+            normal: self.normal(),
+            pdf: 1.0 / area,
+        })
+    }
+
+    fn params(&self) -> Vec<(&'static str, Param)> {
+        vec![
+            ("center", Param::Point(self.center)),
+            ("u", Param::Point(self.u)),
+            ("v", Param::Point(self.v)),
+        ]
+    }
+
+    fn set_param(&mut self, name: &str, value: Param) -> bool {
+        match (name, value) {
+            ("center", Param::Point(center)) => self.center = center,
+            ("u", Param::Point(u)) => self.u = u,
+            ("v", Param::Point(v)) => self.v = v,
+            _ => return false,
+        }
+        true
+    }
+}
@@ -0,0 +1,399 @@
+use nalgebra::{UnitQuaternion, Vector2, Vector3};
+use serde::{Deserialize, Serialize};
+
+use super::{Hit, Shape, Surface};
+use crate::rt::{aabb::Aabb, ray::Ray};
+
+/// Signed distance field, built as a tree of primitives and operations
+///
+/// Primitives are centered on their own position and oriented along the y
+/// axis; use `Transform` to move them around.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "sdf", rename_all = "snake_case")]
+pub enum Sdf {
+    Sphere {
+        center: Vector3<f64>,
+        radius: f64,
+    },
+    Box {
+        center: Vector3<f64>,
+        half_size: Vector3<f64>,
+    },
+    Torus {
+        center: Vector3<f64>,
+        major_radius: f64,
+        minor_radius: f64,
+    },
+    Capsule {
+        start: Vector3<f64>,
+        end: Vector3<f64>,
+        radius: f64,
+    },
+    Cylinder {
+        center: Vector3<f64>,
+        radius: f64,
+        height: f64,
+    },
+    /// Both shapes, blended over `smoothness` units
+    Union {
+        left: Box<Sdf>,
+        right: Box<Sdf>,
+        #[serde(default)]
+        smoothness: f64,
+    },
+    /// `left` with `right` carved out of it
+    Subtraction {
+        left: Box<Sdf>,
+        right: Box<Sdf>,
+        #[serde(default)]
+        smoothness: f64,
+    },
+    Intersection {
+        left: Box<Sdf>,
+        right: Box<Sdf>,
+        #[serde(default)]
+        smoothness: f64,
+    },
+    /// Copies of the shape every `spacing` units, `limit` copies to each side
+    /// of the original on every axis
+    Repeat {
+        shape: Box<Sdf>,
+        spacing: Vector3<f64>,
+        limit: Vector3<f64>,
+    },
+    /// Rotates the shape around the y axis by `rate` radians per unit of height
+    Twist {
+        shape: Box<Sdf>,
+        rate: f64,
+    },
+    /// Inflates the shape by `radius`, rounding its edges
+    Round {
+        shape: Box<Sdf>,
+        radius: f64,
+    },
+    Transform {
+        shape: Box<Sdf>,
+        #[serde(default)]
+        translation: Vector3<f64>,
+        /// Euler angles in degrees
+        #[serde(default)]
+        rotation: Vector3<f64>,
+        #[serde(default = "one")]
+        scale: f64,
+    },
+}
+
+fn one() -> f64 {
+    1.0
+}
+
+/// Mix between `a` and `b`, with `t` going from 0 to 1
+fn mix(a: f64, b: f64, t: f64) -> f64 {
+    a * (1.0 - t) + b * t
+}
+
+fn rotation(degrees: &Vector3<f64>) -> UnitQuaternion<f64> {
+    let radians = degrees.map(f64::to_radians);
+    UnitQuaternion::from_euler_angles(radians.x, radians.y, radians.z)
+}
+
+impl Sdf {
+    /// Distance from `p` to the surface, negative inside. Operations like
+    /// twisting only give a bound of the actual distance.
+    pub fn distance(&self, p: &Vector3<f64>) -> f64 {
+        match self {
+            Sdf::Sphere { center, radius } => (p - center).norm() - radius,
+            Sdf::Box { center, half_size } => {
+                let q = (p - center).abs() - half_size;
+                q.sup(&Vector3::zeros()).norm() + q.max().min(0.0)
+            }
+            Sdf::Torus {
+                center,
+                major_radius,
+                minor_radius,
+            } => {
+                let p = p - center;
+                let q = Vector2::new(p.xz().norm() - major_radius, p.y);
+                q.norm() - minor_radius
+            }
+            Sdf::Capsule { start, end, radius } => {
+                let pa = p - start;
+                let ba = end - start;
+                let h = (pa.dot(&ba) / ba.norm_squared()).clamp(0.0, 1.0);
+                (pa - ba * h).norm() - radius
+            }
+            Sdf::Cylinder {
+                center,
+                radius,
+                height,
+            } => {
+                let p = p - center;
+                let d = Vector2::new(p.xz().norm() - radius, p.y.abs() - height * 0.5);
+                d.max().min(0.0) + d.sup(&Vector2::zeros()).norm()
+            }
+            // polynomial smooth minimum and its variations, by Inigo Quilez
+            Sdf::Union {
+                left,
+                right,
+                smoothness,
+            } => {
+                let (a, b) = (left.distance(p), right.distance(p));
+                if *smoothness <= 0.0 {
+                    return a.min(b);
+                }
+                let h = (0.5 + 0.5 * (b - a) / smoothness).clamp(0.0, 1.0);
+                mix(b, a, h) - smoothness * h * (1.0 - h)
+            }
+            Sdf::Subtraction {
+                left,
+                right,
+                smoothness,
+            } => {
+                let (a, b) = (left.distance(p), right.distance(p));
+                if *smoothness <= 0.0 {
+                    return a.max(-b);
+                }
+                let h = (0.5 - 0.5 * (a + b) / smoothness).clamp(0.0, 1.0);
+                mix(a, -b, h) + smoothness * h * (1.0 - h)
+            }
+            Sdf::Intersection {
+                left,
+                right,
+                smoothness,
+            } => {
+                let (a, b) = (left.distance(p), right.distance(p));
+                if *smoothness <= 0.0 {
+                    return a.max(b);
+                }
+                let h = (0.5 - 0.5 * (b - a) / smoothness).clamp(0.0, 1.0);
+                mix(b, a, h) + smoothness * h * (1.0 - h)
+            }
+            Sdf::Repeat {
+                shape,
+                spacing,
+                limit,
+            } => {
+                let cell = p
+                    .component_div(spacing)
+                    .map(f64::round)
+                    .zip_map(limit, |cell, limit| cell.clamp(-limit, limit));
+                shape.distance(&(p - spacing.component_mul(&cell)))
+            }
+            Sdf::Twist { shape, rate } => {
+                let (sin, cos) = (rate * p.y).sin_cos();
+                let q = Vector3::new(cos * p.x - sin * p.z, p.y, sin * p.x + cos * p.z);
+                shape.distance(&q)
+            }
+            Sdf::Round { shape, radius } => shape.distance(p) - radius,
+            Sdf::Transform {
+                shape,
+                translation,
+                rotation: angles,
+                scale,
+            } => {
+                let local = rotation(angles).inverse_transform_vector(&(p - translation)) / *scale;
+                shape.distance(&local) * scale
+            }
+        }
+    }
+
+    /// Conservative bounds of the surface
+    pub fn bounds(&self) -> Aabb {
+        match self {
+            Sdf::Sphere { center, radius } => Aabb::around(center, &Vector3::repeat(*radius)),
+            Sdf::Box { center, half_size } => Aabb::around(center, half_size),
+            Sdf::Torus {
+                center,
+                major_radius,
+                minor_radius,
+            } => {
+                let extent = major_radius + minor_radius;
+                Aabb::around(center, &Vector3::new(extent, *minor_radius, extent))
+            }
+            Sdf::Capsule { start, end, radius } => {
+                let half_size = Vector3::repeat(*radius);
+                Aabb::around(start, &half_size).union(&Aabb::around(end, &half_size))
+            }
+            Sdf::Cylinder {
+                center,
+                radius,
+                height,
+            } => Aabb::around(center, &Vector3::new(*radius, height * 0.5, *radius)),
+            // smooth blends can bulge out by a fraction of the smoothness
+            Sdf::Union {
+                left,
+                right,
+                smoothness,
+            } => {
+                let bounds = left.bounds().union(&right.bounds());
+                Aabb::new(
+                    bounds.min.add_scalar(-smoothness * 0.25),
+                    bounds.max.add_scalar(smoothness * 0.25),
+                )
+            }
+            Sdf::Subtraction { left, .. } => left.bounds(),
+            Sdf::Intersection { left, right, .. } => {
+                let (left, right) = (left.bounds(), right.bounds());
+                Aabb::new(left.min.sup(&right.min), left.max.inf(&right.max))
+            }
+            Sdf::Repeat {
+                shape,
+                spacing,
+                limit,
+            } => {
+                let bounds = shape.bounds();
+                let offset = spacing.component_mul(limit).abs();
+                Aabb::new(bounds.min - offset, bounds.max + offset)
+            }
+            Sdf::Twist { shape, .. } => {
+                // any rotation around y stays within the circle through the furthest corner
+                let bounds = shape.bounds();
+                let radius = bounds
+                    .corners()
+                    .iter()
+                    .map(|corner| corner.xz().norm())
+                    .fold(0.0, f64::max);
+                Aabb::new(
+                    Vector3::new(-radius, bounds.min.y, -radius),
+                    Vector3::new(radius, bounds.max.y, radius),
+                )
+            }
+            Sdf::Round { shape, radius } => {
+                let bounds = shape.bounds();
+                Aabb::new(
+                    bounds.min.add_scalar(-radius),
+                    bounds.max.add_scalar(*radius),
+                )
+            }
+            Sdf::Transform {
+                shape,
+                translation,
+                rotation: angles,
+                scale,
+            } => {
+                let rotation = rotation(angles);
+                let corners = shape
+                    .bounds()
+                    .corners()
+                    .map(|corner| translation + rotation * (corner * *scale));
+                Aabb::from_points(&corners)
+            }
+        }
+    }
+
+    /// How much a step along the ray has to be shortened so it can't
+    /// overshoot the surface, 1 for exact distance fields
+    pub fn step_scale(&self) -> f64 {
+        match self {
+            Sdf::Union { left, right, .. }
+            | Sdf::Subtraction { left, right, .. }
+            | Sdf::Intersection { left, right, .. } => left.step_scale().min(right.step_scale()),
+            Sdf::Repeat { shape, .. } | Sdf::Round { shape, .. } | Sdf::Transform { shape, .. } => {
+                shape.step_scale()
+            }
+            Sdf::Twist { shape, rate } => {
+                // the twist stretches space more the further it is from the axis
+                let bounds = shape.bounds();
+                let radius = bounds
+                    .corners()
+                    .iter()
+                    .map(|corner| corner.xz().norm())
+                    .fold(0.0, f64::max);
+                shape.step_scale() / (1.0 + (rate * radius).powi(2)).sqrt()
+            }
+            _ => 1.0,
+        }
+    }
+
+    /// Gradient of the field, with the tetrahedron technique
+    pub fn normal(&self, p: &Vector3<f64>, epsilon: f64) -> Vector3<f64> {
+        let k = [
+            Vector3::new(1.0, -1.0, -1.0),
+            Vector3::new(-1.0, -1.0, 1.0),
+            Vector3::new(-1.0, 1.0, -1.0),
+            Vector3::new(1.0, 1.0, 1.0),
+        ];
+        k.iter()
+            .map(|k| k * self.distance(&(p + k * epsilon)))
+            .sum::<Vector3<f64>>()
+            .normalize()
+    }
+}
+
+/// Renders a signed distance field by sphere tracing it
+pub struct SdfShape {
+    sdf: Sdf,
+    bounds: Aabb,
+    step_scale: f64,
+    pub max_steps: u32,
+    /// Distance to the surface below which the ray is considered to hit it
+    pub epsilon: f64,
+}
+
+impl SdfShape {
+    pub fn new(sdf: Sdf) -> SdfShape {
+        SdfShape {
+            bounds: sdf.bounds(),
+            step_scale: sdf.step_scale(),
+            sdf,
+            max_steps: 256,
+            epsilon: 1e-5,
+        }
+    }
+
+    pub fn sdf(&self) -> &Sdf {
+        &self.sdf
+    }
+
+    pub fn set_sdf(&mut self, sdf: Sdf) {
+        *self = SdfShape {
+            max_steps: self.max_steps,
+            epsilon: self.epsilon,
+            ..SdfShape::new(sdf)
+        };
+    }
+}
+
+impl Shape for SdfShape {
+    fn name(&self) -> &'static str {
+        "sdf"
+    }
+
+    fn intersect(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<Hit> {
+        let (t_enter, t_exit) = self.bounds.intersect(ray, t_min, t_max)?;
+        let inverse_length = 1.0 / ray.direction.norm();
+
+        let mut t = t_enter;
+        for _ in 0..self.max_steps {
+            let distance = self.sdf.distance(&ray.at(t));
+            if distance.abs() < self.epsilon && t > t_min {
+                return Some(Hit {
+                    distance: t,
+                    primitive: 0,
+                });
+            }
+            // rays starting inside the shape march towards its boundary too
+            t += distance.abs().max(self.epsilon) * self.step_scale * inverse_length;
+            if t > t_exit {
+                return None;
+            }
+        }
+        None
+    }
+
+    fn bounds(&self) -> Aabb {
+        self.bounds
+    }
+
+    fn surface(&self, ray: &Ray, hit: &Hit) -> Surface {
+        let position = ray.at(hit.distance);
+        let normal = self.sdf.normal(&position, self.epsilon * 10.0);
+
+        // there is no natural parameterization, project the bounds onto the
+        // two axes the normal is least aligned with
+        let local = (position - self.bounds.min).component_div(&self.bounds.size());
+        let axis = normal.iamax();
+        let uv = Vector2::new(local[(axis + 1) % 3], local[(axis + 2) % 3]);
+        Surface { normal, uv }
+    }
+}
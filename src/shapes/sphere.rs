@@ -0,0 +1,139 @@
+use std::f64::consts::PI;
+
+use nalgebra::{Vector2, Vector3};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    closest_quadratic_root, sample_unit_sphere, Hit, Interval, Param, Shape, ShapeSample, Surface,
+};
+use crate::rt::{aabb::Aabb, ray::Ray, roots::solve_quadratic};
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Sphere {
+    /// Center at time 0
+    pub position: Vector3<f64>,
+    pub radius: f64,
+    /// Center at time 1 of a moving sphere, which goes in a straight line
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub end_position: Option<Vector3<f64>>,
+}
+
+impl Sphere {
+    pub fn new(position: Vector3<f64>, radius: f64) -> Sphere {
+        Sphere {
+            position,
+            radius,
+            end_position: None,
+        }
+    }
+
+    pub fn center(&self, time: f64) -> Vector3<f64> {
+        match self.end_position {
+            Some(end) => self.position.lerp(&end, time),
+            None => self.position,
+        }
+    }
+}
+
+impl Default for Sphere {
+    fn default() -> Self {
+        Self {
+            position: Default::default(),
+            radius: 0.5,
+            end_position: None,
+        }
+    }
+}
+
+impl Shape for Sphere {
+    fn name(&self) -> &'static str {
+        "sphere"
+    }
+
+    fn intersect(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<Hit> {
+        let oc = ray.origin - self.center(ray.time);
+
+        let a = ray.direction.dot(&ray.direction);
+        let b = 2.0 * oc.dot(&ray.direction);
+        let c = oc.dot(&oc) - self.radius * self.radius;
+
+        let distance = closest_quadratic_root(a, b, c, t_min, t_max, |_| true)?;
+        Some(Hit {
+            distance,
+            primitive: 0,
+        })
+    }
+
+    fn intervals(&self, ray: &Ray) -> Option<Vec<Interval>> {
+        let oc = ray.origin - self.center(ray.time);
+        let roots = solve_quadratic(
+            ray.direction.dot(&ray.direction),
+            2.0 * oc.dot(&ray.direction),
+            oc.dot(&oc) - self.radius * self.radius,
+        );
+        Some(
+            roots
+                .map(|(t0, t1)| Interval::new(t0, t1))
+                .into_iter()
+                .collect(),
+        )
+    }
+
+    /// Covers the whole motion, times between 0 and 1
+    fn bounds(&self) -> Aabb {
+        let half_size = Vector3::repeat(self.radius);
+        let start = Aabb::around(&self.position, &half_size);
+        match self.end_position {
+            Some(end) => start.union(&Aabb::around(&end, &half_size)),
+            None => start,
+        }
+    }
+
+    fn surface(&self, ray: &Ray, hit: &Hit) -> Surface {
+        let normal = (ray.at(hit.distance) - self.center(ray.time)).normalize();
+        let uv = Vector2::new(
+            0.5 + normal.z.atan2(normal.x) / (2.0 * PI),
+            0.5 + normal.y.asin() / PI,
+        );
+        Surface { normal, uv }
+    }
+
+    fn uv_derivatives(&self, ray: &Ray, hit: &Hit) -> Option<[Vector3<f64>; 2]> {
+        let n = (ray.at(hit.distance) - self.center(ray.time)).normalize();
+        let ring = n.x.hypot(n.z);
+        let dpdu = Vector3::new(-n.z, 0.0, n.x) * (2.0 * PI * self.radius);
+        let dpdv = Vector3::new(-n.x * n.y / ring, ring, -n.z * n.y / ring) * (PI * self.radius);
+        (ring > 1e-9).then_some([dpdu, dpdv])
+    }
+
+    fn sample(&self, time: f64, seed: &mut u32) -> Option<ShapeSample> {
+        let normal = sample_unit_sphere(seed);
+        Some(ShapeSample {
+            position: self.center(time) + normal * self.radius,
+            normal,
+            pdf: 1.0 / (4.0 * PI * self.radius * self.radius),
+        })
+    }
+
+    fn params(&self) -> Vec<(&'static str, Param)> {
+        let mut params = vec![
+            ("position", Param::Point(self.position)),
+            ("radius", Param::Length(self.radius)),
+        ];
+        if let Some(end) = self.end_position {
+            params.push(("end position", Param::Point(end)));
+        }
+        params
+    }
+
+    fn set_param(&mut self, name: &str, value: Param) -> bool {
+        match (name, value) {
+            ("position", Param::Point(position)) => self.position = position,
+            ("radius", Param::Length(radius)) => self.radius = radius,
+            ("end position", Param::Point(end)) => self.end_position = Some(end),
+            _ => return false,
+        }
+        true
+    }
+}
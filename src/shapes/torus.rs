@@ -0,0 +1,170 @@
+use std::f64::consts::PI;
+
+use nalgebra::{Vector2, Vector3};
+use serde::{Deserialize, Serialize};
+
+use super::{azimuth, disk_extent, Hit, Interval, Param, Shape, ShapeSample, Surface};
+use crate::{
+    random::random_f64,
+    rt::{
+        aabb::Aabb,
+        onb::Onb,
+        ray::Ray,
+        roots::{solve_normalized_quartic, solve_quadratic},
+    },
+};
+
+/// Torus lying on the plane perpendicular to `axis`
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Torus {
+    pub center: Vector3<f64>,
+    pub axis: Vector3<f64>,
+    pub major_radius: f64,
+    pub minor_radius: f64,
+}
+
+impl Torus {
+    pub fn new(
+        center: Vector3<f64>,
+        axis: Vector3<f64>,
+        major_radius: f64,
+        minor_radius: f64,
+    ) -> Torus {
+        Torus {
+            center,
+            axis,
+            major_radius,
+            minor_radius,
+        }
+    }
+}
+
+impl Default for Torus {
+    fn default() -> Self {
+        Self::new(Default::default(), Vector3::new(0.0, 1.0, 0.0), 0.5, 0.2)
+    }
+}
+
+impl Torus {
+    /// All crossings of the ray with the surface, sorted, in ray parameter units
+    fn roots(&self, ray: &Ray) -> Vec<f64> {
+        let frame = Onb::from_w(&self.axis);
+        let length = ray.direction.norm();
+        let d = frame.to_local(&ray.direction) / length;
+        let o = frame.to_local(&(ray.origin - self.center));
+
+        // cheap rejection against the bounding sphere, which also gives a closer
+        // starting point for the quartic so its coefficients stay well conditioned
+        let bounding_radius = self.major_radius + self.minor_radius;
+        let Some((t_enter, _)) = solve_quadratic(
+            1.0,
+            2.0 * o.dot(&d),
+            o.dot(&o) - bounding_radius * bounding_radius,
+        ) else {
+            return Vec::new();
+        };
+        let o = o + d * t_enter;
+
+        let r2 = self.major_radius * self.major_radius;
+        let k = o.dot(&o) + r2 - self.minor_radius * self.minor_radius;
+        let od = o.dot(&d);
+        let roots = solve_normalized_quartic(
+            4.0 * od,
+            4.0 * od * od + 2.0 * k - 4.0 * r2 * (d.x * d.x + d.y * d.y),
+            4.0 * k * od - 8.0 * r2 * (o.x * d.x + o.y * d.y),
+            k * k - 4.0 * r2 * (o.x * o.x + o.y * o.y),
+        );
+
+        roots.into_iter().map(|t| (t + t_enter) / length).collect()
+    }
+}
+
+impl Shape for Torus {
+    fn name(&self) -> &'static str {
+        "torus"
+    }
+
+    fn intersect(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<Hit> {
+        let distance = self
+            .roots(ray)
+            .into_iter()
+            .find(|&t| t > t_min && t < t_max)?;
+        Some(Hit {
+            distance,
+            primitive: 0,
+        })
+    }
+
+    fn intervals(&self, ray: &Ray) -> Option<Vec<Interval>> {
+        let roots = self.roots(ray);
+        Some(
+            roots
+                .chunks_exact(2)
+                .map(|pair| Interval::new(pair[0], pair[1]))
+                .collect(),
+        )
+    }
+
+    fn bounds(&self) -> Aabb {
+        let axis = self.axis.normalize();
+        let half_size = disk_extent(&axis, self.major_radius).add_scalar(self.minor_radius);
+        Aabb::around(&self.center, &half_size)
+    }
+
+    fn surface(&self, ray: &Ray, hit: &Hit) -> Surface {
+        let frame = Onb::from_w(&self.axis);
+        let p = frame.to_local(&(ray.at(hit.distance) - self.center));
+        let ring = Vector3::new(p.x, p.y, 0.0).normalize() * self.major_radius;
+
+        let tube_angle = p.z.atan2(p.xy().norm() - self.major_radius);
+        Surface {
+            normal: frame.to_world(&(p - ring)).normalize(),
+            uv: Vector2::new(azimuth(&p), 0.5 + tube_angle / (2.0 * PI)),
+        }
+    }
+
+    fn sample(&self, _time: f64, seed: &mut u32) -> Option<ShapeSample> {
+        let (major, minor) = (self.major_radius, self.minor_radius);
+
+        // the outer side of the tube has more area, so reject proportionally to
+        // the distance from the axis
+        let (theta, phi) = loop {
+            let theta = 2.0 * PI * random_f64(seed);
+            let phi = 2.0 * PI * random_f64(seed);
+            if random_f64(seed) * (major + minor) <= major + minor * phi.cos() {
+                break (theta, phi);
+            }
+        };
+
+        let frame = Onb::from_w(&self.axis);
+        let radial = Vector3::new(theta.cos(), theta.sin(), 0.0);
+        let local_normal = radial * phi.cos() + Vector3::new(0.0, 0.0, phi.sin());
+        let p = radial * major + local_normal * minor;
+        Some(ShapeSample {
+            position: self.center + frame.to_world(&p),
+            normal: frame.to_world(&local_normal),
+            pdf: 1.0 / (4.0 * PI * PI * major * minor),
+        })
+    }
+
+    fn params(&self) -> Vec<(&'static str, Param)> {
+        vec![
+            ("center", Param::Point(self.center)),
+            ("axis", Param::Direction(self.axis)),
+            ("major radius", Param::Length(self.major_radius)),
+            ("minor radius", Param::Length(self.minor_radius)),
+        ]
+    }
+
+    fn set_param(&mut self, name: &str, value: Param) -> bool {
+        match (name, value) {
+            ("center", Param::Point(center)) => self.center = center,
+            ("axis", Param::Direction(axis)) => self.axis = axis,
+            ("major radius", Param::Length(radius)) => self.major_radius = radius,
+            ("minor radius", Param::Length(radius)) => self.minor_radius = radius,
+            _ => return false,
+        }
+        true
+    }
+}
@@ -0,0 +1,112 @@
+/*
+ * Smoke test of include/raytracing.h, built and run by tests/c_api.rs.
+ * Prints what failed and exits with 1.
+ */
+
+#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+
+#include "raytracing.h"
+
+#define WIDTH 40
+#define HEIGHT 30
+
+static int failures = 0;
+
+#define EXPECT(call, status)                                                   \
+    do {                                                                       \
+        RtStatus got = (call);                                                 \
+        if (got != (status)) {                                                 \
+            fprintf(stderr, "%s: %d instead of %d (%s)\n", #call, (int)got,    \
+                    (int)(status), rt_last_error());                           \
+            failures++;                                                        \
+        }                                                                      \
+    } while (0)
+
+#define CHECK(condition)                                                       \
+    do {                                                                       \
+        if (!(condition)) {                                                    \
+            fprintf(stderr, "%s:%d: %s\n", __FILE__, __LINE__, #condition);    \
+            failures++;                                                        \
+        }                                                                      \
+    } while (0)
+
+int main(int argc, char **argv)
+{
+    RtScene *scene = NULL, *loaded = NULL;
+    RtRenderer *renderer = NULL;
+    RtMaterial material;
+    size_t red = 99, white = 99, index = 99;
+    uint32_t samples = 0;
+    double center[3] = {0, 0, 0}, floor_point[3] = {0, -1, 0}, up[3] = {0, 1, 0};
+    double zero[3] = {0, 0, 0}, position[3] = {0, 0, 6}, forward[3] = {0, 0, -1};
+    double box_min[3] = {1.5, -1, -1}, box_max[3] = {2.5, 0, 0};
+    double light[3] = {2, 3, 4}, light_color[3] = {1, 1, 1};
+    double vertices[9] = {-2.5, -1, 0, -1.5, -1, 0, -2, 0.5, 0};
+    uint32_t triangle[3] = {0, 1, 2}, bad_triangle[3] = {0, 1, 5};
+    float *pixels = malloc(sizeof(float) * WIDTH * HEIGHT * 4);
+
+    EXPECT(rt_scene_create(&scene), RT_OK);
+    EXPECT(rt_material_default(&material), RT_OK);
+    CHECK(material.albedo[3] == 1.0 && material.transmission == 0.0);
+    material.albedo[1] = material.albedo[2] = 0;
+    EXPECT(rt_scene_add_material(scene, &material, &red), RT_OK);
+    material.albedo[1] = material.albedo[2] = 1;
+    EXPECT(rt_scene_add_material(scene, &material, &white), RT_OK);
+    CHECK(red == 0 && white == 1);
+    EXPECT(rt_scene_set_material(scene, white, &material), RT_OK);
+    EXPECT(rt_scene_set_material(scene, 7, &material), RT_OUT_OF_RANGE);
+
+    EXPECT(rt_scene_add_sphere(scene, center, 1.0, red, &index), RT_OK);
+    CHECK(index == 0);
+    EXPECT(rt_scene_add_sphere(scene, center, -1.0, red, NULL), RT_INVALID_ARGUMENT);
+    EXPECT(rt_scene_add_sphere(scene, center, 1.0, 7, NULL), RT_OUT_OF_RANGE);
+    EXPECT(rt_scene_add_sphere(NULL, center, 1.0, red, NULL), RT_NULL_POINTER);
+    EXPECT(rt_scene_add_plane(scene, floor_point, up, white, NULL), RT_OK);
+    EXPECT(rt_scene_add_plane(scene, floor_point, zero, white, NULL), RT_INVALID_ARGUMENT);
+    EXPECT(rt_scene_add_cuboid(scene, box_min, box_max, white, NULL), RT_OK);
+    EXPECT(rt_scene_add_mesh(scene, vertices, 3, triangle, 1, red, &index), RT_OK);
+    CHECK(index == 3);
+    EXPECT(rt_scene_add_mesh(scene, vertices, 3, bad_triangle, 1, red, NULL), RT_OUT_OF_RANGE);
+    EXPECT(rt_scene_add_mesh(scene, vertices, SIZE_MAX / 2, triangle, 1, red, NULL),
+           RT_OUT_OF_RANGE);
+    EXPECT(rt_scene_add_mesh(scene, vertices, 3, triangle, SIZE_MAX / 2, red, NULL),
+           RT_OUT_OF_RANGE);
+    EXPECT(rt_scene_add_point_light(scene, light, light_color, -1.0), RT_INVALID_ARGUMENT);
+    EXPECT(rt_scene_add_point_light(scene, light, light_color, 20.0), RT_OK);
+
+    EXPECT(rt_renderer_create(0, HEIGHT, &renderer), RT_INVALID_ARGUMENT);
+    EXPECT(rt_renderer_create(WIDTH, HEIGHT, &renderer), RT_OK);
+    EXPECT(rt_renderer_set_camera(renderer, position, forward, 45.0), RT_OK);
+    EXPECT(rt_renderer_set_camera(renderer, position, zero, 45.0), RT_INVALID_ARGUMENT);
+    EXPECT(rt_renderer_set_integrator(renderer, "magic"), RT_INVALID_ARGUMENT);
+    EXPECT(rt_renderer_set_integrator(renderer, "direct lighting"), RT_OK);
+    EXPECT(rt_renderer_render(renderer, scene, 4), RT_OK);
+    EXPECT(rt_renderer_samples(renderer, &samples), RT_OK);
+    CHECK(samples == 4);
+
+    EXPECT(rt_renderer_read_pixels(renderer, pixels, 10), RT_OUT_OF_RANGE);
+    EXPECT(rt_renderer_read_pixels(renderer, pixels, WIDTH * HEIGHT * 4), RT_OK);
+    {
+        /* the red sphere fills the middle of the picture */
+        float *middle = pixels + (HEIGHT / 2 * WIDTH + WIDTH / 2) * 4;
+        CHECK(middle[0] > 0.05f && middle[0] > 4 * middle[1] && middle[0] > 4 * middle[2]);
+        CHECK(middle[3] == 1.0f);
+    }
+
+    EXPECT(rt_scene_load("/this/scene/does/not/exist.yaml", &loaded), RT_LOAD_FAILED);
+    if (argc > 1) {
+        EXPECT(rt_scene_load(argv[1], &loaded), RT_OK);
+        EXPECT(rt_renderer_reset(renderer), RT_OK);
+        EXPECT(rt_renderer_render(renderer, loaded, 1), RT_OK);
+        rt_scene_destroy(loaded);
+    }
+
+    rt_renderer_destroy(renderer);
+    rt_scene_destroy(scene);
+    rt_scene_destroy(NULL);
+    rt_renderer_destroy(NULL);
+    free(pixels);
+    return failures == 0 ? 0 : 1;
+}
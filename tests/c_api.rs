@@ -0,0 +1,81 @@
+//! The C interface through include/raytracing.h, with tests/c/smoke.c
+//! compiled against the header and linked to the shared library. Skipped
+//! without a C compiler.
+
+#![cfg(unix)]
+
+use std::{env, fs, path::Path, process};
+
+const SCENE: &str = "
+lights:
+  - { type: point, position: [1, 2, 1], color: [1, 1, 1], intensity: 4 }
+materials:
+  - { albedo: [0.8, 0.8, 0.8, 1] }
+objects:
+  - shape: { type: sphere, position: [0, 0, -2], radius: 0.8 }
+";
+
+#[test]
+fn header_matches_the_library() {
+    let compiler = env::var("CC").unwrap_or_else(|_| "cc".to_string());
+    if process::Command::new(&compiler)
+        .arg("--version")
+        .output()
+        .is_err()
+    {
+        eprintln!("skipped, there is no C compiler '{compiler}'");
+        return;
+    }
+
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+    // tests link the rlib, so the shared library is built here, in a target
+    // directory of its own as cargo locks the one running the tests
+    let target = root.join("target").join("c_api");
+    let cargo = env::var("CARGO").unwrap_or_else(|_| "cargo".to_string());
+    let status = process::Command::new(cargo)
+        .args(["build", "--lib", "--manifest-path"])
+        .arg(root.join("Cargo.toml"))
+        .arg("--target-dir")
+        .arg(&target)
+        .status()
+        .unwrap();
+    assert!(status.success());
+    let library = target.join("debug");
+    let directory = env::temp_dir().join(format!("raytracing-c-api-{}", process::id()));
+    let _ = fs::remove_dir_all(&directory);
+    fs::create_dir_all(&directory).unwrap();
+    let program = directory.join("smoke");
+    let scene = directory.join("scene.yaml");
+    fs::write(&scene, SCENE).unwrap();
+
+    let output = process::Command::new(&compiler)
+        .args(["-std=c99", "-Wall", "-Wextra", "-Werror", "-o"])
+        .arg(&program)
+        .arg(root.join("tests/c/smoke.c"))
+        .arg("-I")
+        .arg(root.join("include"))
+        .arg("-L")
+        .arg(&library)
+        .arg("-lraytracing")
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    // cargo's library path leads to the libraries of its own target directory
+    let output = process::Command::new(&program)
+        .arg(&scene)
+        .env("LD_LIBRARY_PATH", &library)
+        .env("DYLD_LIBRARY_PATH", &library)
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    fs::remove_dir_all(&directory).unwrap();
+}
@@ -0,0 +1,121 @@
+//! Renders split between worker processes of the `render` binary
+
+#![cfg(unix)]
+
+use std::{fs, path::Path, process};
+
+use raytracing::{
+    camera::{Camera, CameraPose},
+    integrator::IntegratorKind,
+    renderer::{
+        distributed::{Coordinator, Job},
+        Canvas, RaytracingRenderer, RendererSettings,
+    },
+    scene::Scene,
+};
+
+const SCENE: &str = "
+lights:
+  - { type: point, position: [1, 2, 1], color: [1, 1, 1], intensity: 4 }
+materials:
+  - { albedo: [0.8, 0.8, 0.8, 1], roughness: 1.0 }
+  - { albedo: [0.9, 0.3, 0.2, 1], roughness: 0.3 }
+objects:
+  - shape: { type: plane, point: [0, -1, 0], normal: [0, 1, 0] }
+  - shape: { type: sphere, position: [0, 0, -1], radius: 0.8 }
+    material: 1
+";
+
+const WIDTH: u32 = 32;
+const HEIGHT: u32 = 24;
+const SAMPLES: usize = 200;
+
+fn renderer() -> RaytracingRenderer {
+    let settings = RendererSettings {
+        accumulate: true,
+        use_threads: true,
+        slow_random: false,
+    };
+    let mut renderer = RaytracingRenderer::new(Canvas::new(1, 1), settings);
+    renderer.on_resize(WIDTH, HEIGHT);
+    renderer
+}
+
+#[test]
+fn killed_worker_gives_its_batch_back() {
+    let directory = std::env::temp_dir().join(format!("raytracing-distributed-{}", process::id()));
+    let _ = fs::remove_dir_all(&directory);
+    fs::create_dir_all(&directory).unwrap();
+    let scene_path = directory.join("scene.yaml");
+    fs::write(&scene_path, SCENE).unwrap();
+
+    let scene = Scene::load(&scene_path).unwrap();
+    let mut camera = Camera::new(45.0, 0.1, 100.0);
+    camera.on_resize(WIDTH, HEIGHT);
+    camera.set_pose(&CameraPose::default());
+
+    let mut single = renderer();
+    while single.frame_index() <= SAMPLES {
+        single.render(&scene, &camera);
+    }
+
+    // the first worker to start is killed a moment later, in the middle of
+    // its batches. Workers keep the shell's process id, which the
+    // coordinator expects.
+    let script = r#"
+        if mkdir "$1" 2>/dev/null; then
+            (sleep 0.5; kill -9 $$) &
+        fi
+        exec "$0" --worker "$2"
+    "#;
+    let program = env!("CARGO_BIN_EXE_render");
+    let killed = directory.join("killed");
+    let args = ["-c", script, program, killed.to_str().unwrap()];
+    let mut coordinator = Coordinator::spawn(3, Path::new("sh"), &args).unwrap();
+    assert_eq!(coordinator.worker_count(), 3);
+
+    let job = Job {
+        scene: scene_path,
+        time: 0.0,
+        camera: camera.pose(),
+        width: WIDTH,
+        height: HEIGHT,
+        integrator: IntegratorKind::Path,
+    };
+    let mut distributed = renderer();
+    coordinator
+        .render(&job, SAMPLES, 4, &mut distributed, &scene, &camera)
+        .unwrap();
+    assert_eq!(coordinator.worker_count(), 2);
+    assert_eq!(distributed.frame_index(), single.frame_index());
+
+    for (a, b) in distributed
+        .accumulation_data
+        .iter()
+        .zip(&single.accumulation_data)
+    {
+        assert!(
+            (a - b).abs().max() <= 1e-9 * b.abs().max().max(1.0),
+            "{a} != {b}"
+        );
+    }
+    drop(coordinator);
+    fs::remove_dir_all(&directory).unwrap();
+}
+
+#[test]
+fn workers_have_no_checkpoints() {
+    let output = process::Command::new(env!("CARGO_BIN_EXE_render"))
+        .args([
+            "scene.yaml",
+            "--workers",
+            "2",
+            "--checkpoint",
+            "render.checkpoint",
+        ])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(2));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("--checkpoint can't be used with --workers"));
+}
@@ -0,0 +1,174 @@
+//! The HTTP render service, on a free local port
+
+use std::{
+    io::{Read, Write},
+    net::{SocketAddr, TcpStream},
+    thread,
+    time::{Duration, Instant},
+};
+
+use image::GenericImageView;
+use raytracing::{
+    integrator::IntegratorKind,
+    renderer::service::{self, JobSettings},
+};
+use serde_json::Value;
+use tiny_http::Server;
+
+const SCENE: &str = "
+lights:
+  - { type: point, position: [1, 2, 1], color: [1, 1, 1], intensity: 4 }
+materials:
+  - { albedo: [0.8, 0.8, 0.8, 1] }
+objects:
+  - shape: { type: plane, point: [0, -1, 0], normal: [0, 1, 0] }
+  - shape: { type: sphere, position: [0, 0, -2], radius: 0.8 }
+";
+
+struct Answer {
+    code: u16,
+    content_type: String,
+    body: Vec<u8>,
+}
+
+impl Answer {
+    fn json(&self) -> Value {
+        serde_json::from_slice(&self.body).expect("JSON answer")
+    }
+}
+
+fn start() -> SocketAddr {
+    let server = Server::http("127.0.0.1:0").unwrap();
+    let address = server.server_addr().to_ip().unwrap();
+    let defaults = JobSettings {
+        samples: 4,
+        width: 16,
+        height: 12,
+        integrator: IntegratorKind::Path,
+    };
+    thread::spawn(move || service::serve_on(server, defaults));
+    address
+}
+
+fn request(address: SocketAddr, method: &str, path: &str, body: &str) -> Answer {
+    let mut stream = TcpStream::connect(address).unwrap();
+    write!(
+        stream,
+        "{method} {path} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\
+         Content-Length: {}\r\n\r\n{body}",
+        body.len()
+    )
+    .unwrap();
+    let mut answer = Vec::new();
+    stream.read_to_end(&mut answer).unwrap();
+
+    let end = answer
+        .windows(4)
+        .position(|window| window == b"\r\n\r\n")
+        .expect("end of the headers");
+    let head = String::from_utf8_lossy(&answer[..end]).to_string();
+    let code = head.split(' ').nth(1).unwrap().parse().unwrap();
+    let content_type = head
+        .lines()
+        .find_map(|line| {
+            let (name, value) = line.split_once(':')?;
+            name.eq_ignore_ascii_case("content-type")
+                .then(|| value.trim().to_string())
+        })
+        .unwrap_or_default();
+    Answer {
+        code,
+        content_type,
+        body: answer[end + 4..].to_vec(),
+    }
+}
+
+/// Status of the job once it is in `state`
+fn wait_for(address: SocketAddr, id: u64, state: &str) -> Value {
+    let deadline = Instant::now() + Duration::from_secs(60);
+    loop {
+        let status = request(address, "GET", &format!("/jobs/{id}"), "").json();
+        if status["state"] == state {
+            return status;
+        }
+        assert!(Instant::now() < deadline, "job {id} is still {status}");
+        thread::sleep(Duration::from_millis(20));
+    }
+}
+
+#[test]
+fn renders_a_job() {
+    let address = start();
+    let submitted = request(address, "POST", "/jobs?samples=3&width=20", SCENE);
+    assert_eq!(submitted.code, 201);
+    let id = submitted.json()["id"].as_u64().unwrap();
+
+    let status = wait_for(address, id, "done");
+    assert_eq!(status["samples_done"], 3);
+    assert_eq!(status["width"], 20);
+    assert_eq!(status["height"], 12);
+
+    let png = request(address, "GET", &format!("/jobs/{id}/image.png"), "");
+    assert_eq!((png.code, png.content_type.as_str()), (200, "image/png"));
+    let image = image::load_from_memory(&png.body).unwrap();
+    assert_eq!((image.width(), image.height()), (20, 12));
+
+    let exr = request(address, "GET", &format!("/jobs/{id}/image.exr"), "");
+    assert_eq!((exr.code, exr.content_type.as_str()), (200, "image/x-exr"));
+    assert_eq!(&exr.body[..4], &[0x76, 0x2f, 0x31, 0x01]);
+
+    let jobs = request(address, "GET", "/jobs", "").json();
+    assert_eq!(jobs.as_array().unwrap().len(), 1);
+}
+
+#[test]
+fn cancels_a_job() {
+    let address = start();
+    let submitted = request(address, "POST", "/jobs?samples=60000", SCENE);
+    assert_eq!(submitted.code, 201);
+    let id = submitted.json()["id"].as_u64().unwrap();
+    wait_for(address, id, "running");
+
+    let cancelled = request(address, "POST", &format!("/jobs/{id}/cancel"), "");
+    assert_eq!(cancelled.code, 200);
+    let status = wait_for(address, id, "cancelled");
+    assert!(status["samples_done"].as_u64().unwrap() < 60000);
+
+    let exr = request(address, "GET", &format!("/jobs/{id}/image.exr"), "");
+    assert_eq!(exr.code, 409);
+}
+
+#[test]
+fn rejects_bad_jobs() {
+    let address = start();
+    for (path, body) in [
+        ("/jobs", "objects: [ { shape: "),
+        (
+            "/jobs",
+            "objects:\n  - shape: { type: sphere }\n    material: 3\n",
+        ),
+        ("/jobs?width=100000&height=100000", SCENE),
+        ("/jobs?samples=1000000", SCENE),
+        ("/jobs?samples=0", SCENE),
+        ("/jobs?integrator=magic", SCENE),
+        (
+            "/jobs",
+            "materials:\n  - albedo_texture: { type: image, path: /etc/hostname }\n",
+        ),
+        (
+            "/jobs",
+            "objects:\n  - shape: { type: mesh, path: ../mesh.ply }\n",
+        ),
+    ] {
+        let answer = request(address, "POST", path, body);
+        assert_eq!(answer.code, 400, "{path}");
+        assert!(answer.json()["error"].is_string(), "{path}");
+    }
+    let huge = format!("{SCENE}#{}", "-".repeat(16 << 20));
+    assert_eq!(request(address, "POST", "/jobs", &huge).code, 413);
+    assert_eq!(request(address, "GET", "/jobs/7", "").code, 404);
+    assert_eq!(
+        request(address, "GET", "/jobs", "").json(),
+        Value::Array(vec![])
+    );
+}